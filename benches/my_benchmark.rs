@@ -1,8 +1,8 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use die_e::{
     backgammon::backgammon_logic::Backgammon,
     constants::{DEVICE},
-    mcts::{node_store::NodeStore, node::Node, utils::{turn_policy_to_probs_tensor, turn_policy_to_probs}},
+    mcts::{node_store::NodeStore, node::Node, puct::PuctConfig, utils::{turn_policy_to_probs_tensor, turn_policy_to_probs}},
     base::LearnableGame
 };
 use itertools::Itertools;
@@ -72,7 +72,7 @@ fn bench(c: &mut Criterion) {
         b.iter(|| {
             let mut state = Backgammon::default();
             state.roll_die();
-            let node = Node::new(state, 0, None, None, 0.);
+            let node = Node::new(state, 0, None, None);
             let policy = Tensor::rand(1352, (Kind::Float, *DEVICE));
             turn_policy_to_probs_tensor(&policy, &node)
         })
@@ -82,7 +82,7 @@ fn bench(c: &mut Criterion) {
         b.iter(|| {
             let mut state = Backgammon::default();
             state.roll_die();
-            let node = Node::new(state, 0, None, None, 0.);
+            let node = Node::new(state, 0, None, None);
             let policy = Tensor::rand(1352, (Kind::Float, *DEVICE));
             turn_policy_to_probs(&policy, &node)
         })
@@ -94,9 +94,8 @@ fn bench(c: &mut Criterion) {
             state.roll_die();
             let mut store = NodeStore::new();
             store.add_node(state, None, None, 0.);
-            let mut node = store.get_node(0);
             let policy = Tensor::rand(1352, (Kind::Float, Device::Cpu));
-            node.alpha_expand_tensor(&mut store, &policy);
+            store.alpha_expand_tensor(0, &policy);
         })
     });
 
@@ -107,13 +106,136 @@ fn bench(c: &mut Criterion) {
             let mut store = NodeStore::new();
             store.add_node(state, None, None, 0.);
             let policy = (0..1352).map(|_| rng.gen_range(0..20) as f32).collect_vec();
-            let mut node = store.get_node(0);
-            node.alpha_expand(&mut store, policy);
+            store.alpha_expand(0, &policy);
         })
     });
 
+    // Search tree with a few thousand nodes to compare selection by reference against cloning the nodes
+    let store = build_search_tree(2_000);
+
+    group.bench_function("select leaf by reference", |b| {
+        b.iter(|| {
+            let mut idx = 0;
+            while !store.get_node_ref(idx).children.is_empty() {
+                idx = store.select_alpha(idx, &PuctConfig::constant(2.));
+            }
+            black_box(idx)
+        })
+    });
+
+    let old_store = OldNodeStore::from_store(&store);
+    group.bench_function("select leaf by clone", |b| {
+        b.iter(|| black_box(select_leaf_by_clone(&old_store, 0, 2.)))
+    });
+
+    group.bench_function("search loop uniform prior", |b| {
+        b.iter(|| black_box(build_search_tree(200).len()))
+    });
+
     group.finish()
 }
 
+/*
+    Runs a search with a uniform prior and random leaf values, no network involved
+*/
+fn build_search_tree(iterations: usize) -> NodeStore<Backgammon> {
+    let mut rng = thread_rng();
+    let mut state = Backgammon::default();
+    state.roll_die();
+    let policy = vec![1. / 1352.; 1352];
+    let mut store = NodeStore::with_capacity(iterations * 32);
+    store.add_node(state, None, None, 0.);
+    for _ in 0..iterations {
+        let mut idx = 0;
+        while !store.get_node_ref(idx).children.is_empty() {
+            idx = store.select_alpha(idx, &PuctConfig::constant(2.));
+        }
+        store.alpha_expand(idx, &policy);
+        store.backpropagate(idx, rng.gen_range(-1.0..1.0));
+    }
+    store
+}
+
+/*
+    Node as it was stored before the arena layout, the statistics of the edge are kept in the node itself
+    and the children are a list of indices. The state and moves are only read by cloning them, as selection used to
+*/
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+struct OldNode {
+    state: Backgammon,
+    idx: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    visits: f32,
+    value: f32,
+    policy: f32,
+    action_taken: Option<<Backgammon as LearnableGame>::Move>,
+    expandable_moves: Vec<<Backgammon as LearnableGame>::Move>,
+}
+
+struct OldNodeStore {
+    nodes: Vec<OldNode>,
+}
+
+impl OldNodeStore {
+    // Copies the tree and its statistics from the arena
+    fn from_store(store: &NodeStore<Backgammon>) -> Self {
+        let nodes = (0..store.len())
+            .map(|idx| {
+                let node = store.get_node_ref(idx);
+                OldNode {
+                    state: node.state,
+                    idx,
+                    parent: node.parent,
+                    children: node.children.clone().collect_vec(),
+                    visits: store.visits(idx),
+                    value: store.value(idx),
+                    policy: store.policy(idx),
+                    action_taken: node.action_taken.clone(),
+                    expandable_moves: node.expandable_moves.clone(),
+                }
+            })
+            .collect_vec();
+        OldNodeStore { nodes }
+    }
+
+    // Nodes were handed out as clones
+    fn get_node(&self, idx: usize) -> OldNode {
+        self.nodes[idx].clone()
+    }
+
+    fn alpha_ucb(&self, node: &OldNode, c: f32) -> f32 {
+        let q_value = if node.visits == 0.0 { 0.0 } else { node.value / node.visits };
+        match node.parent {
+            Some(parent_idx) => {
+                let parent = self.get_node(parent_idx);
+                q_value + (c * parent.visits.sqrt() / (node.visits + 1.0)) * node.policy
+            }
+            None => f32::INFINITY,
+        }
+    }
+}
+
+/*
+    Selection as it was done before the arena layout, every child and its parent are cloned to be scored
+*/
+fn select_leaf_by_clone(store: &OldNodeStore, idx: usize, c: f32) -> usize {
+    let node = store.get_node(idx);
+    if node.children.is_empty() {
+        return node.idx;
+    }
+    let best = node.children
+        .iter()
+        .map(|child_idx| store.get_node(*child_idx))
+        .max_by(|a, b| {
+            store.alpha_ucb(a, c)
+                .partial_cmp(&store.alpha_ucb(b, c))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .unwrap();
+    select_leaf_by_clone(store, best.idx, c)
+}
+
 criterion_group!(benches, bench);
 criterion_main!(benches);
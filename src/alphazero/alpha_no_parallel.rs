@@ -59,7 +59,7 @@ impl AlphaZero {
 
             // Save results to memory
             memory.push(MemoryFragment {
-                outcome: state.get_player(),
+                outcome: state.get_player() as f32,
                 ps: pi,
                 state: state.as_tensor(),
             });
@@ -73,7 +73,7 @@ impl AlphaZero {
                 return memory
                     .iter()
                     .map(|mem| MemoryFragment {
-                        outcome: if mem.outcome == winner { 1 } else { -1 },
+                        outcome: if mem.outcome == winner as f32 { 1. } else { -1. },
                         ps: mem.ps.shallow_clone(),
                         state: mem.state.shallow_clone(),
                     })
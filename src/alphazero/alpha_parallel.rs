@@ -5,20 +5,63 @@ use itertools::Itertools;
 
 use crate::{
     base::LearnableGame,
+    evaluator::cache::CachingEvaluator,
     mcts::{
-        alpha_mcts::alpha_mcts_parallel, node_store::NodeStore, utils::get_prob_tensor_parallel,
+        alpha_mcts::alpha_mcts_parallel, gumbel::improved_policy_tensor_parallel, node_store::NodeStore,
+        utils::get_prob_tensor_parallel,
     },
+    move_guard::{GuardedMove, MoveGuard},
+    settings::save_config,
+    Error,
 };
 
-use super::alphazero::{AlphaZero, MemoryFragment};
+use super::{
+    alphazero::{AlphaZero, MemoryFragment},
+    start_positions::{SourceKind, StartPositions},
+};
 use nanoid::nanoid;
+use rand::{seq::SliceRandom, thread_rng, Rng};
+
+/*
+    Plays up to n_moves uniformly random moves on the state, stops before a move that would end the game.
+    Returns the number of moves played, including skipped turns
+*/
+fn play_random_opening<T: LearnableGame>(state: &mut T, n_moves: usize, rng: &mut impl Rng) -> usize {
+    for played in 0..n_moves {
+        let mut next_state = *state;
+        match state.get_valid_moves().choose(rng) {
+            Some(random_move) => next_state.apply_move(random_move),
+            None => next_state.skip_turn(),
+        }
+        if next_state.check_winner().is_some() {
+            return played;
+        }
+        *state = next_state;
+    }
+    n_moves
+}
+
+/*
+    Sets the outcomes of the memory of a finished game, value is the result of the game for player.
+    While a game is played the outcome of each fragment holds the player to move in its state
+*/
+fn finish_memory(memory: &[MemoryFragment], player: i8, value: f32) -> impl Iterator<Item = MemoryFragment> + '_ {
+    memory.iter().map(move |mem| MemoryFragment {
+        outcome: if mem.outcome == player as f32 { value } else { -value },
+        ps: mem.ps.shallow_clone(),
+        state: mem.state.shallow_clone(),
+    })
+}
 
 impl AlphaZero {
-    pub fn learn_parallel<T: LearnableGame>(&mut self) {
+    pub fn learn_parallel<T: LearnableGame>(&mut self) -> Result<(), Error> {
         let run_id = nanoid!();
         let runpath_base = format!("./data/{}/run-{}", &T::name(), &run_id);
         println!("Staring up run with run_id: {}", &run_id);
-        let _ = fs::create_dir_all(&runpath_base);
+        fs::create_dir_all(&runpath_base)?;
+        if let Some(conf) = &self.effective_config {
+            save_config(conf, &Path::new(&runpath_base).join("config.json"))?;
+        }
         let sty = ProgressStyle::with_template(
             "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
         )
@@ -38,10 +81,13 @@ impl AlphaZero {
                 .with_style(sty.clone()),
         );
 
+        // Book and game positions are loaded once for all learn iterations
+        let start_positions = StartPositions::<T>::load(&self.config.start_positions)?;
+
         for l_i in 0..self.config.learn_iterations {
             // Create dir for current learn iteration
             let lrn_path = format!("{}/lrn-{}", &runpath_base, l_i);
-            let _ = fs::create_dir(&lrn_path);
+            fs::create_dir_all(&lrn_path)?;
 
             pb_self_play.reset();
             // Get samples for training
@@ -49,7 +95,7 @@ impl AlphaZero {
             for sp_i in 0..self.config.self_play_iterations {
                 pb_self_play.set_message(format!("Self-play iteration #{}", sp_i + 1));
 
-                let mut res = self.self_play_parallel::<T>();
+                let mut res = self.self_play_parallel::<T>(&start_positions, l_i, Path::new(&runpath_base));
                 memory.append(&mut res);
                 pb_self_play.set_message(format!(
                     "Saving training data... Self-play iteration #{}",
@@ -58,8 +104,8 @@ impl AlphaZero {
 
                 // Make self play dir
                 let sp_dir_path = format!("{}/sp-{}", &lrn_path, sp_i);
-                let _ = fs::create_dir(&sp_dir_path);
-                self.save_training_data(&memory, Path::new(&sp_dir_path));
+                fs::create_dir_all(&sp_dir_path)?;
+                self.save_training_data(&memory, Path::new(&sp_dir_path))?;
                 pb_self_play.set_message(format!(
                     "Self-play iteration #{} complete, saved training data",
                     sp_i + 1
@@ -93,18 +139,28 @@ impl AlphaZero {
                     &model_save_path, e
                 ),
             }
-            self.play_vs_best_model::<T>();
+            self.play_vs_best_model::<T>(Path::new(&runpath_base))?;
             pb_learn.inc(1);
         }
+        Ok(())
     }
 
-    pub fn self_play_parallel<T: LearnableGame>(&self) -> Vec<MemoryFragment> {
+    /*
+        Plays num_self_play_batches games in parallel, each game starts from a position of start_positions
+        for the learn iteration (see start_positions.rs), illegal moves are recorded in run_dir
+    */
+    pub fn self_play_parallel<T: LearnableGame>(&self, start_positions: &StartPositions<T>, learn_iteration: usize, run_dir: &Path) -> Vec<MemoryFragment> {
         let n_batches: usize = self.config.num_self_play_batches;
+        let mut rng = thread_rng();
+        // Moves of the random opening of each game are played before the search starts and are not saved to memory
+        let mut n_rounds = vec![0; n_batches];
         let mut states: HashMap<usize, (usize, T)> = (0..n_batches)
             .map(|idx| {
-                let mut state = T::new();
-                if !T::IS_DETERMINISTIC {
-                    state.roll_die();
+                let (mut state, source) = start_positions.sample(learn_iteration, &mut rng);
+                // Only games from the initial position play a random opening
+                if source == SourceKind::Initial {
+                    let n_opening_moves = rng.gen_range(0..=self.config.random_opening_moves);
+                    n_rounds[idx] = play_random_opening(&mut state, n_opening_moves, &mut rng);
                 }
                 (idx, (idx, state))
             })
@@ -113,7 +169,13 @@ impl AlphaZero {
         let mut memories = Vec::from_iter((0..n_batches).map(|_| Vec::<MemoryFragment>::new()));
         let mut all_memories = vec![];
 
-        let mut n_rounds = vec![0; n_batches];
+        // Evaluations are shared by all games and all moves of this self play
+        let evaluator = CachingEvaluator::with_capacity(&self.model, self.mcts_config.eval_cache_size);
+
+        // Store of the previous search and, for each game, the node of its current state in that store
+        let mut prev_store: NodeStore<T> = NodeStore::new();
+        let mut reuse_roots: HashMap<usize, usize> = HashMap::new();
+        let mut guard = MoveGuard::new(&self.mcts_config.move_guard.in_dir(run_dir), "self_play");
 
         let sty = ProgressStyle::with_template(
             "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
@@ -134,8 +196,14 @@ impl AlphaZero {
             ));
 
             // Mutates store, does not return anything
-            let mut store = NodeStore::new();
-            let state_to_process = states.iter().map(|tup| tup.1 .1).collect_vec();
+            let (init_idxs, state_to_process): (Vec<usize>, Vec<T>) = states.values().copied().unzip();
+            let mut store = if self.mcts_config.tree_reuse && !reuse_roots.is_empty() {
+                let roots = init_idxs.iter().map(|init_idx| reuse_roots.get(init_idx).copied()).collect_vec();
+                prev_store.reroot(&roots, &state_to_process)
+            } else {
+                NodeStore::new()
+            };
+            reuse_roots.clear();
             let mcts_pb = self.pb.add(
                 ProgressBar::new(self.mcts_config.iterations as u64)
                     .with_style(sty.clone())
@@ -143,10 +211,10 @@ impl AlphaZero {
                     .with_finish(indicatif::ProgressFinish::AndClear),
             );
             // Fill store with games
-            alpha_mcts_parallel(
+            let gumbel_choices = alpha_mcts_parallel(
                 &mut store,
                 &state_to_process,
-                &self.model,
+                &evaluator,
                 &self.mcts_config,
                 Some(mcts_pb),
             );
@@ -161,64 +229,71 @@ impl AlphaZero {
             let roots = (0..states.len())
                 .map(|i| store.get_node_ref(i))
                 .collect_vec();
-            let prob_tensor = get_prob_tensor_parallel(&roots, &store)
-                .pow_(1.0 / self.config.temperature) // Apply temperature
-                .to_device(tch::Device::Cpu); // Move to CPU for faster access
+            let prob_tensor = match &self.mcts_config.gumbel {
+                // Gumbel searches train on the improved policy rather than the visit counts
+                Some(gumbel_config) => improved_policy_tensor_parallel(&store, &(0..states.len()).collect_vec(), gumbel_config),
+                None => get_prob_tensor_parallel(&roots, &store).pow_(1.0 / self.config.temperature), // Apply temperature
+            }
+            .to_device(tch::Device::Cpu); // Move to CPU for faster access
 
             for (processed_idx, (init_idx, state)) in states.values_mut().enumerate() {
                 let curr_prob_tensor = prob_tensor.get(processed_idx as i64);
 
                 // Check round limit
                 if n_rounds[*init_idx] >= self.mcts_config.simulate_round_limit {
-                    let curr_memory = memories[*init_idx].iter().map(|mem| MemoryFragment {
-                        outcome: 0,
-                        ps: mem.ps.shallow_clone(),
-                        state: mem.state.shallow_clone(),
-                    });
-                    all_memories.extend(curr_memory);
+                    all_memories.extend(finish_memory(&memories[*init_idx], state.get_player(), 0.));
                     states_to_remove.push(*init_idx);
                 }
 
                 // If prob tensor of the current state is all zeros then skip turn, has_children check just in case
                 if !curr_prob_tensor.sum(None).is_nonzero()
-                    || store.get_node(processed_idx).children.is_empty()
+                    || store.children(processed_idx).is_empty()
                 {
                     n_rounds[*init_idx] += 1;
                     state.skip_turn();
                     continue;
                 }
 
-                // Select an action from probabilities
-                let selected_action = Self::weighted_select_tensor_idx(&curr_prob_tensor);
+                // Select an action from the search results, Gumbel searches play the move left by their sequential halving
+                let decoded_action = match gumbel_choices[processed_idx] {
+                    Some(child_idx) => store.get_node_ref(child_idx).action_taken.clone().unwrap(),
+                    None => self.mcts_config.self_play_move_selection.select_move(&store, processed_idx, n_rounds[*init_idx]),
+                };
+                let decoded_action = match guard.check(state, &decoded_action) {
+                    GuardedMove::Play(action) => action,
+                    // The search results of an illegal move are not trained on
+                    GuardedMove::Skip => {
+                        n_rounds[*init_idx] += 1;
+                        state.skip_turn();
+                        continue;
+                    }
+                };
 
                 // Save results to memory
                 memories[*init_idx].push(MemoryFragment {
-                    outcome: state.get_player(),
+                    outcome: state.get_player() as f32,
                     ps: curr_prob_tensor,
                     state: state.as_tensor(),
                 });
 
-                // Decode and play selected action
-                let decoded_action = state.decode(selected_action as u32);
-                let valid_moves = state.get_valid_moves();
-                assert!(
-                    valid_moves.contains(&decoded_action),
-                    "Valid moves: {:?} does not contain decoded move: {:?}",
-                    valid_moves,
-                    decoded_action
-                );
+                // Play selected action
                 state.apply_move(&decoded_action);
+                if self.mcts_config.tree_reuse {
+                    if let Some(node_idx) = store.find_descendant(processed_idx, state, 1) {
+                        reuse_roots.insert(*init_idx, node_idx);
+                    }
+                }
 
                 // Increment round
                 n_rounds[*init_idx] += 1;
 
                 if let Some(winner) = state.check_winner() {
-                    let curr_memory = memories[*init_idx].iter().map(|mem| MemoryFragment {
-                        outcome: if winner == mem.outcome {1} else if winner == -mem.outcome {-1} else {0},
-                        ps: mem.ps.shallow_clone(),
-                        state: mem.state.shallow_clone(),
-                    });
-                    all_memories.extend(curr_memory);
+                    // A draw (winner 0) is 0 for both players
+                    all_memories.extend(finish_memory(&memories[*init_idx], winner, winner.abs() as f32));
+                    states_to_remove.push(*init_idx);
+                } else if let Some(value) = self.mcts_config.exact_value(state) {
+                    // The result is already known (e.g. a bear-off race), the game ends with its expected outcome
+                    all_memories.extend(finish_memory(&memories[*init_idx], state.get_player(), value));
                     states_to_remove.push(*init_idx);
                 }
             }
@@ -226,6 +301,11 @@ impl AlphaZero {
             for state_idx in states_to_remove {
                 states.remove(&state_idx);
             }
+            prev_store = store;
+        }
+        let _ = self.pb.println(format!("Evaluation cache - {}", evaluator.stats()));
+        if guard.n_illegal() > 0 {
+            let _ = self.pb.println(format!("Illegal moves - {}", guard.n_illegal()));
         }
         all_memories
     }
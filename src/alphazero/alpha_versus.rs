@@ -1,19 +1,20 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use tch::nn::VarStore;
 
 use crate::{
     base::LearnableGame,
-    constants::DEVICE, versus::{play, Player, Agent},
+    constants::DEVICE, versus::{play, Player, Agent}, Error,
 };
 
 use super::{alphazero::AlphaZero, nnet::ResNet};
 
 impl AlphaZero {
     /**
-     * Plays against the current best model, saves if 55% better
+     * Plays against the current best model, saves if 55% better.
+     * Illegal moves of the match are recorded in run_dir
      */
-    pub fn play_vs_best_model<T: LearnableGame>(&self) {
+    pub fn play_vs_best_model<T: LearnableGame>(&self, run_dir: &Path) -> Result<(), Error> {
         let best_model_path_str = format!("./models/{}/best_model.ot", T::name());
         let best_model_path = PathBuf::from(best_model_path_str);
         if !best_model_path.exists() {
@@ -22,11 +23,11 @@ impl AlphaZero {
                 Ok(_) => println!("model saved!"),
                 Err(e) => println!("unable to save model, caught error: {}", e),
             }
-            return;
+            return Ok(());
         }
-        let nnet_best = ResNet::from_path::<T>(&best_model_path);
+        let nnet_best = ResNet::from_path::<T>(&best_model_path)?;
         // Create vs copy because we move the vs into the ResNet
-        let is_model_better = match self.play_vs_model::<T>(nnet_best) {
+        let is_model_better = match self.play_vs_model::<T>(nnet_best, run_dir) {
             Some(1) => {
                 self.pb.println("new model was better!").unwrap();
                 true
@@ -54,9 +55,10 @@ impl AlphaZero {
                     .unwrap(),
             }
         }
+        Ok(())
     }
 
-    pub fn play_vs_model<T: LearnableGame>(&self, other_model: ResNet) -> Option<usize> {
+    pub fn play_vs_model<T: LearnableGame>(&self, other_model: ResNet, run_dir: &Path) -> Option<usize> {
         let vs_self = VarStore::new(*DEVICE);
         let mut nnet_self = ResNet::new::<T>(vs_self);
         nnet_self.vs.copy(&self.model.vs)
@@ -69,7 +71,7 @@ impl AlphaZero {
             player_type: Agent::Model,
             model: Some(other_model)
         };
-        let match_res = play::<T>(self_model_p, other_model_p, &self.mcts_config, self.config.temperature);
+        let match_res = play::<T>(self_model_p, other_model_p, &self.mcts_config, self.config.temperature, run_dir);
         println!("Match result: {}", match_res);
         if match_res.winrate >= 0.55 {
             Some(1)
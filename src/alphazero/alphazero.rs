@@ -14,11 +14,11 @@ use tch::{
 };
 
 
-use super::nnet::ResNet;
+use super::{nnet::ResNet, start_positions::StartConfig};
 
 use crate::{
     constants::{DEFAULT_TYPE, DEVICE},
-    mcts::alpha_mcts::alpha_mcts, MctsConfig, base::LearnableGame,
+    mcts::alpha_mcts::alpha_mcts_search, MctsConfig, base::LearnableGame, settings::Settings, Error,
 };
 
 #[derive(Debug)]
@@ -29,6 +29,8 @@ pub struct AlphaZeroConfig {
     pub num_epochs: usize,
     pub training_batch_size: usize,
     pub num_self_play_batches: usize,
+    pub random_opening_moves: usize,
+    pub start_positions: StartConfig,
 }
 
 impl AlphaZeroConfig {
@@ -40,10 +42,13 @@ impl AlphaZeroConfig {
             num_epochs: conf.get_int("num_epochs")? as usize,
             training_batch_size: conf.get_int("training_batch_size")? as usize,
             num_self_play_batches: conf.get_int("num_self_play_batches")? as usize,
+            random_opening_moves: conf.get_int("random_opening_moves")? as usize,
+            start_positions: StartConfig::from_config(conf)?,
         })
     }
 }
 
+#[derive(Debug)]
 pub struct OptimizerParams {
     wd: f64, 
     lr: f64
@@ -64,10 +69,12 @@ pub struct AlphaZero {
     pub config: AlphaZeroConfig,
     pub mcts_config: MctsConfig,
     pub(crate) pb: MultiProgress,
+    // The configuration the instance was created from, saved in the directory of each run
+    pub(crate) effective_config: Option<Config>,
 }
 #[derive(Debug)]
 pub struct MemoryFragment {
-    pub outcome: i8,   // Outcome of game
+    pub outcome: f32,  // Outcome of game, or the exact value of the state if it is known
     pub ps: Tensor,    // Probabilities
     pub state: Tensor, // Encoded game state
 }
@@ -78,52 +85,41 @@ torch.optim.Adam(params, lr=0.001, betas=(0.9, 0.999), eps=1e-08, weight_decay=0
 
 
 impl AlphaZero {
-    pub fn new<T: LearnableGame>(model_path: Option<PathBuf>, config: AlphaZeroConfig, mcts_config: MctsConfig, op: OptimizerParams) -> Self {
+    pub fn new<T: LearnableGame>(model_path: Option<PathBuf>, config: AlphaZeroConfig, mcts_config: MctsConfig, op: OptimizerParams) -> Result<Self, Error> {
         let mut vs = nn::VarStore::new(*DEVICE);
 
         println!("Initializing AlphaZero...\nDevice: {:?}\n{:?}\n{:?}", *DEVICE, &config, mcts_config);
 
+        let best_model_path = PathBuf::from(format!("./models/{}/best_model.ot", T::name()));
         match model_path {
-            Some(m_path) => match vs.load(&m_path) {
-                Ok(_) => println!("Successfully loaded model on path: {}", m_path.to_str().unwrap()),
-                Err(e) => panic!("failed to load model: (might be a large error log) \n{}", e),
+            Some(m_path) => {
+                vs.load(&m_path).map_err(|source| Error::Model { path: m_path.clone(), source })?;
+                println!("Successfully loaded model on path: {}", m_path.display());
             },
-            None if Path::new(&format!("./models/{}/best_model.ot", T::name())).exists() => {
-                let m_path_str = format!("./models/{}/best_model.ot", T::name());
-                let bm_path = Path::new(&m_path_str);
-                match vs.load(bm_path) {
-                    Ok(_) => println!("Successfully loaded best model"),
-                    Err(e) => panic!("failed to load best model: {}", e),
-                }
+            None if best_model_path.exists() => {
+                vs.load(&best_model_path).map_err(|source| Error::Model { path: best_model_path.clone(), source })?;
+                println!("Successfully loaded best model");
             },
             None => println!("No best model found, initialized from scratch")
         }
 
-        let opt = Adam::default().wd(op.wd).build(&vs, op.lr).unwrap();
+        let opt = Adam::default().wd(op.wd).build(&vs, op.lr)?;
 
-        AlphaZero {
+        Ok(AlphaZero {
             model: ResNet::new::<T>(vs),
             optimizer: opt,
             config,
             mcts_config,
             pb: MultiProgress::new(),
-        }
+            effective_config: None,
+        })
     }
 
-    pub fn from_config<T: LearnableGame>(model_path: Option<PathBuf>, config: &Config) -> Self {
-        let az_config = match AlphaZeroConfig::from_config(config){
-            Ok(config) => config,
-            Err(e) => panic!("Unable to load AlphaZero config, {}", e),
-        };
-        let mcts_config = match MctsConfig::from_config(config) {
-            Ok(config) => config,
-            Err(e) => panic!("Unable to load MCTS config, {}", e),
-        };
-        let op = match OptimizerParams::from_config(config) {
-            Ok(op) => op,
-            Err(e) => panic!("Unable to load optimizer params, {}", e),
-        };
-        AlphaZero::new::<T>(model_path, az_config, mcts_config, op)
+    pub fn from_config<T: LearnableGame>(model_path: Option<PathBuf>, config: &Config) -> Result<Self, Error> {
+        let settings = Settings::from_config(config)?;
+        let mut az = AlphaZero::new::<T>(model_path, settings.alphazero, settings.mcts, settings.optimizer)?;
+        az.effective_config = Some(config.clone());
+        Ok(az)
     }
 
     pub fn weighted_select_tensor_idx(pi: &Tensor) -> usize {
@@ -136,22 +132,21 @@ impl AlphaZero {
         dist.sample(&mut rng)
     }
 
-    pub fn get_next_move_for_state<T: LearnableGame>(&self, current_state: &T) -> T::Move {
-        let mut pi = match alpha_mcts(current_state, &self.model, &self.mcts_config) {
-            Some(pi) => pi,
-            None => return T::EMPTY_MOVE
-        };
-        let temperatured_pi = pi.pow_(1.0 / self.config.temperature);
-        let selected_action = AlphaZero::weighted_select_tensor_idx(&temperatured_pi);
-        current_state.decode(selected_action as u32)
+    /**
+     * Searches the state and picks a move with mcts_config.move_selection,
+     * move_number is the number of moves played in the game so far
+     */
+    pub fn get_next_move_for_state<T: LearnableGame>(&self, current_state: &T, move_number: usize) -> T::Move {
+        let store = alpha_mcts_search(current_state, &self.model, &self.mcts_config);
+        self.mcts_config.move_selection.select_move(&store, 0, move_number)
     }
 
-    pub fn save_training_data(&self, data: &[MemoryFragment], path: &Path) {
+    pub fn save_training_data(&self, data: &[MemoryFragment], path: &Path) -> Result<(), Error> {
         if !path.exists() {
-            panic!("path: {} does not exist!", path.to_str().unwrap())
+            return Err(Error::NotFound(path.to_path_buf()));
         }
 
-        let (outcomes, ps_values, states): (Vec<i8>, Vec<Tensor>, Vec<Tensor>) =
+        let (outcomes, ps_values, states): (Vec<f32>, Vec<Tensor>, Vec<Tensor>) =
             multiunzip(data.iter().map(|fragment| {
                 (
                     fragment.outcome,
@@ -165,14 +160,10 @@ impl AlphaZero {
         let states = Tensor::concat(&states, 0).to_device(tch::Device::Cpu);
         let outcomes = Tensor::from_slice(&outcomes);
 
-        match (
-            ps.save(path.join("ps.ot")),
-            states.save(path.join("states.ot")),
-            outcomes.save(path.join("outcomes.ot")),
-        ) {
-            (Ok(_), Ok(_), Ok(_)) => (),
-            _ => panic!("unable to save training data!"),
-        }
+        ps.save(path.join("ps.ot"))?;
+        states.save(path.join("states.ot"))?;
+        outcomes.save(path.join("outcomes.ot"))?;
+        Ok(())
     }
 
     /**
@@ -181,22 +172,22 @@ impl AlphaZero {
      path: the Path to load the data from can end with:
      * lrn-x for all training data under the learn 
      */
-    pub fn load_training_data(path: &Path) -> Vec<MemoryFragment> {
+    pub fn load_training_data(path: &Path) -> Result<Vec<MemoryFragment>, Error> {
         if !path.exists() {
-            panic!("path: {} does not exist!", path.to_str().unwrap())
+            return Err(Error::NotFound(path.to_path_buf()));
         }
-        let ps = Tensor::load(path.join("ps.ot")).unwrap().squeeze();
-        let states = Tensor::load(path.join("states.ot")).unwrap().squeeze();
-        let outcomes = Tensor::load(path.join("outcomes.ot")).unwrap().squeeze();
+        let ps = Tensor::load(path.join("ps.ot"))?.squeeze();
+        let states = Tensor::load(path.join("states.ot"))?.squeeze();
+        let outcomes = Tensor::load(path.join("outcomes.ot"))?.squeeze();
 
         let data_size = ps.size()[0];
-        (0..data_size).map(|data_idx| {
+        Ok((0..data_size).map(|data_idx| {
             MemoryFragment {
-                outcome: outcomes.get(data_idx).int64_value(&[]) as i8,
+                outcome: outcomes.get(data_idx).double_value(&[]) as f32,
                 ps: ps.get(data_idx).squeeze().shallow_clone(),
                 state: states.get(data_idx).shallow_clone(),
             }
-        }).collect_vec()
+        }).collect_vec())
     }
 
     pub fn train(&mut self, memory: &mut Vec<MemoryFragment>) {
@@ -204,7 +195,7 @@ impl AlphaZero {
         memory.shuffle(&mut rng);
         for batch_idx in (0..memory.len()).step_by(self.config.training_batch_size) {
             let sample = &memory[batch_idx..min(batch_idx + self.config.training_batch_size, memory.len())];
-            let (outcomes, ps_values, states): (Vec<i8>, Vec<Tensor>, Vec<Tensor>) =
+            let (outcomes, ps_values, states): (Vec<f32>, Vec<Tensor>, Vec<Tensor>) =
                 multiunzip(sample.iter().map(|fragment| {
                     (
                         fragment.outcome,
@@ -3,3 +3,5 @@ mod alpha_parallel;
 mod alpha_no_parallel;
 pub mod alphazero;
 pub mod nnet;
+pub mod net_agents;
+pub mod start_positions;
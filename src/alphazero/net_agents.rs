@@ -0,0 +1,136 @@
+use std::cmp::Ordering;
+
+use itertools::Itertools;
+use rand::{distributions::WeightedIndex, prelude::Distribution, seq::SliceRandom, thread_rng};
+
+use crate::{base::LearnableGame, evaluator::{tensor_to_vec, Evaluator}};
+
+/*
+Cheap agents that use the network (or any evaluator) directly without any search
+    - Policy: picks a move from the policy head masked to the valid moves
+    - Value: evaluates every valid move with the value head and picks the best one (1-ply)
+*/
+
+// Max number of states fed to the evaluator at once
+const EVAL_BATCH_SIZE: usize = 2048;
+
+/**
+ * Evaluates the states with the value head of the evaluator.
+ * Values are from the perspective of the player to move in each state.
+ */
+pub fn evaluate_states<T: LearnableGame>(evaluator: &impl Evaluator<T>, states: &[T]) -> Vec<f32> {
+    let mut values = Vec::with_capacity(states.len());
+    for chunk in states.chunks(EVAL_BATCH_SIZE) {
+        values.extend(tensor_to_vec(&evaluator.value(chunk)));
+    }
+    values
+}
+
+/**
+ * Returns every valid move of each state with its expected value from the perspective of the player to move.
+ * If a move passes the turn, the value is averaged over all rolls of the next player.
+ * All positions of all states are evaluated in batches.
+ */
+pub fn batch_move_values<T: LearnableGame>(evaluator: &impl Evaluator<T>, states: &[T]) -> Vec<Vec<(T::Move, f32)>> {
+    let mut results: Vec<Vec<(T::Move, f32)>> = vec![];
+    // (state idx, move idx, weight of the leaf) for every position to evaluate
+    let mut leaf_refs: Vec<(usize, usize, f32)> = vec![];
+    let mut leaves: Vec<T> = vec![];
+
+    for (state_idx, state) in states.iter().enumerate() {
+        let player = state.get_player();
+        let mut move_values = vec![];
+        for (move_idx, valid_move) in state.get_valid_moves().into_iter().enumerate() {
+            let mut next_state = *state;
+            next_state.apply_move(&valid_move);
+
+            let value = match next_state.check_winner() {
+                Some(winner) if winner == player => 1.,
+                Some(winner) if winner == -player => -1.,
+                Some(_) => 0.,
+                None if next_state.get_player() == player => {
+                    leaf_refs.push((state_idx, move_idx, 1.));
+                    leaves.push(next_state);
+                    0.
+                }
+                None => {
+                    // Value of the next state is from the opponent's perspective
+                    for (outcome, prob) in next_state.chance_outcomes() {
+                        leaf_refs.push((state_idx, move_idx, -prob));
+                        leaves.push(outcome);
+                    }
+                    0.
+                }
+            };
+            move_values.push((valid_move, value));
+        }
+        results.push(move_values);
+    }
+
+    let values = evaluate_states(evaluator, &leaves);
+    for (&(state_idx, move_idx, weight), value) in leaf_refs.iter().zip(values) {
+        results[state_idx][move_idx].1 += weight * value;
+    }
+    results
+}
+
+pub fn move_values<T: LearnableGame>(evaluator: &impl Evaluator<T>, state: &T) -> Vec<(T::Move, f32)> {
+    batch_move_values(evaluator, &[*state]).pop().unwrap()
+}
+
+/**
+ * Picks the move with the highest expected value for each state
+ */
+pub fn value_actions<T: LearnableGame>(evaluator: &impl Evaluator<T>, states: &[T]) -> Vec<T::Move> {
+    batch_move_values(evaluator, states)
+        .into_iter()
+        .map(|move_values| {
+            move_values
+                .into_iter()
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+                .map(|(best_move, _)| best_move)
+                .unwrap_or(T::EMPTY_MOVE)
+        })
+        .collect_vec()
+}
+
+/**
+ * Picks a move for each state from the policy head masked to the valid moves.
+ * Probabilities are raised to 1/temp, a temp of 0 picks the move with the highest probability.
+ */
+pub fn policy_actions<T: LearnableGame>(evaluator: &impl Evaluator<T>, states: &[T], temp: f64) -> Vec<T::Move> {
+    if states.is_empty() {
+        return vec![];
+    }
+    let mut rng = thread_rng();
+    let policy = evaluator.policy(states).to_device(tch::Device::Cpu);
+
+    states
+        .iter()
+        .enumerate()
+        .map(|(processed_idx, state)| {
+            let valid_moves = state.get_valid_moves();
+            if valid_moves.is_empty() {
+                return T::EMPTY_MOVE;
+            }
+            let probs = valid_moves
+                .iter()
+                .map(|valid_move| policy.double_value(&[processed_idx as i64, state.encode(valid_move) as i64]))
+                .collect_vec();
+
+            if temp == 0. {
+                let best_idx = probs
+                    .iter()
+                    .position_max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                    .unwrap();
+                return valid_moves[best_idx].clone();
+            }
+            let weights = probs.iter().map(|p| p.powf(1.0 / temp)).collect_vec();
+            match WeightedIndex::new(&weights) {
+                Ok(dist) => valid_moves[dist.sample(&mut rng)].clone(),
+                // All weights are zero, fall back to a random valid move
+                Err(_) => valid_moves.choose(&mut rng).unwrap().clone(),
+            }
+        })
+        .collect_vec()
+}
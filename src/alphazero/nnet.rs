@@ -5,7 +5,7 @@ use tch::{
     Tensor,
 };
 
-use crate::{constants::DEVICE, base::LearnableGame};
+use crate::{constants::DEVICE, base::LearnableGame, Error};
 
 /*
 Constants
@@ -106,15 +106,14 @@ impl ResNet {
         }
     }
     
-    pub fn from_path<T: LearnableGame>(model_path: &PathBuf) -> Self {
+    pub fn from_path<T: LearnableGame>(model_path: &PathBuf) -> Result<Self, Error> {
         let vs = VarStore::new(*DEVICE);
         let mut nnet = Self::new::<T>(vs);
-        let model_path_as_str = model_path.to_str().unwrap();
-        match nnet.vs.load(model_path) {
-            Ok(_) => println!("Successfully loaded model on path {}", model_path_as_str),
-            Err(e) => panic!("unable to load model on path {}, error: {}", &model_path_as_str, e),
-        }
-        nnet
+        nnet.vs
+            .load(model_path)
+            .map_err(|source| Error::Model { path: model_path.clone(), source })?;
+        println!("Successfully loaded model on path {}", model_path.display());
+        Ok(nnet)
     }
 
     pub fn forward_t(&self, xs: &Tensor, train: bool) -> (Tensor, Tensor) {
@@ -153,4 +152,10 @@ impl ResNet {
             .apply_t(&self.policy_head, train)
             .softmax(1, None)
     }
-}
+
+    pub fn forward_value(&self, xs: &Tensor, train: bool) -> Tensor {
+        xs.apply_t(&self.init_block, train)
+            .apply_t(&self.res_layer, train)
+            .apply_t(&self.value_head, train)
+    }
+}
\ No newline at end of file
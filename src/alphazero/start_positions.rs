@@ -0,0 +1,256 @@
+use std::{fs, path::{Path, PathBuf}, str::FromStr};
+
+use config::{Config, ConfigError};
+use itertools::Itertools;
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{analysis::parse_position, base::LearnableGame, rollout::needs_roll, versus::load_all_games, Error};
+
+/*
+Where the games of self play start, set per learn iteration by start_schedule:
+    - initial: the initial position, followed by up to random_opening_moves random moves
+    - random: a position of LearnableGame::random_position, of the given phase if set (e.g. "bearoff" for Backgammon)
+    - book: a position of the opening book in opening_book_path, picked by the weights of the book
+    - games: a position of the saved games in start_games_path, picked uniformly
+Each game picks a source by the weights of the sources of the step of the learn iteration.
+Without a start_schedule the games start from the initial position, random_start_fraction of them from a random position.
+Positions without a roll (e.g. book positions given as position ids) are rolled before the game starts.
+The phases are read as names from the config and parsed to the LearnableGame::Phase of the game when the positions are loaded.
+
+The opening book is a JSON list of positions with their weights, each position is a position id or the JSON of a state:
+    [{ "position": "-1:2,0,0,0,0,-5,0,-3,0,0,0,5,-5,0,0,0,3,0,5,0,0,0,0,-2:0,0:0,0", "weight": 2.0 }]
+
+Ex. a curriculum that starts with bear-offs and moves back to full games:
+    [{ from_iteration = 0, sources = [{ source = "random", phase = "bearoff", weight = 1.0 }] },
+     { from_iteration = 10, sources = [{ source = "random", phase = "race", weight = 1.0 }, { source = "initial", weight = 1.0 }] },
+     { from_iteration = 20, sources = [{ source = "initial", weight = 1.0 }] }]
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    Initial,
+    Random,
+    Book,
+    Games,
+}
+
+// P is the phase, its name in the config or the LearnableGame::Phase of a game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StartSource<P = String> {
+    pub source: SourceKind,
+    // Phase of the random positions, any phase if not given
+    pub phase: Option<P>,
+    pub weight: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StartStep<P = String> {
+    pub from_iteration: usize,
+    pub sources: Vec<StartSource<P>>,
+}
+
+// Start sources by learn iteration, each step applies from its from_iteration until the from_iteration of the next step
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartSchedule<P = String> {
+    steps: Vec<StartStep<P>>,
+}
+
+impl <P> StartSchedule<P> {
+    pub fn new(mut steps: Vec<StartStep<P>>) -> Self {
+        steps.sort_by_key(|step| step.from_iteration);
+        StartSchedule { steps }
+    }
+
+    // Every game starts from the initial position
+    pub fn initial() -> Self {
+        StartSchedule::with_random_fraction(0.)
+    }
+
+    // Games start from the initial position, random_start_fraction of them from a random position of any phase
+    pub fn with_random_fraction(random_start_fraction: f64) -> Self {
+        let sources = [(SourceKind::Initial, 1. - random_start_fraction), (SourceKind::Random, random_start_fraction)]
+            .into_iter()
+            .filter(|(_, weight)| *weight > 0.)
+            .map(|(source, weight)| StartSource { source, phase: None, weight })
+            .collect();
+        StartSchedule::new(vec![StartStep { from_iteration: 0, sources }])
+    }
+
+    // Iterations before the first step use the sources of the first step
+    pub fn sources_at(&self, learn_iteration: usize) -> &[StartSource<P>] {
+        self.steps
+            .iter()
+            .take_while(|step| step.from_iteration <= learn_iteration)
+            .last()
+            .or(self.steps.first())
+            .map_or(&[], |step| &step.sources)
+    }
+
+    fn sources(&self) -> impl Iterator<Item = &StartSource<P>> {
+        self.steps.iter().flat_map(|step| step.sources.iter())
+    }
+}
+
+impl StartSchedule {
+    /**
+     * The schedule with the phase names parsed to the phases of a game, fails on the first name that is not a phase
+     */
+    pub fn parse_phases<P: FromStr<Err = String>>(&self) -> Result<StartSchedule<P>, String> {
+        let steps = self
+            .steps
+            .iter()
+            .map(|step| {
+                let sources = step
+                    .sources
+                    .iter()
+                    .map(|source| {
+                        let phase = source.phase.as_deref().map(str::parse).transpose()?;
+                        Ok(StartSource { source: source.source, phase, weight: source.weight })
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok(StartStep { from_iteration: step.from_iteration, sources })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(StartSchedule { steps })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartConfig {
+    pub schedule: StartSchedule,
+    pub book_path: Option<PathBuf>,
+    pub games_path: Option<PathBuf>,
+}
+
+// An empty path disables the key
+fn optional_path(conf: &Config, key: &str) -> Result<Option<PathBuf>, ConfigError> {
+    let path = conf.get_string(key)?;
+    Ok(if path.is_empty() { None } else { Some(PathBuf::from(path)) })
+}
+
+impl StartConfig {
+    pub fn from_config(conf: &Config) -> Result<Self, ConfigError> {
+        let steps = conf.get::<Vec<StartStep>>("start_schedule")?;
+        let random_start_fraction = conf.get_float("random_start_fraction")?;
+        let schedule = match (steps.is_empty(), random_start_fraction > 0.) {
+            (true, _) => StartSchedule::with_random_fraction(random_start_fraction),
+            (false, false) => StartSchedule::new(steps),
+            (false, true) => return Err(ConfigError::Message(String::from(
+                "start_schedule: add the random positions to the schedule instead of setting random_start_fraction"
+            ))),
+        };
+        for step in schedule.steps.iter() {
+            if step.sources.is_empty() || step.sources.iter().any(|source| source.weight < 0.) || step.sources.iter().all(|source| source.weight == 0.) {
+                return Err(ConfigError::Message(format!(
+                    "start_schedule: the sources of iteration {} need a weight above 0 and no negative weights",
+                    step.from_iteration
+                )));
+            }
+        }
+        Ok(StartConfig {
+            schedule,
+            book_path: optional_path(conf, "opening_book_path")?,
+            games_path: optional_path(conf, "start_games_path")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookEntry {
+    // A position id or the JSON of a state
+    pub position: String,
+    pub weight: f64,
+}
+
+/**
+ * Reads the opening book at path, the weights must not be negative
+ */
+pub fn load_opening_book<T: LearnableGame>(path: &Path) -> Result<Vec<(T, f64)>, Error> {
+    if !path.is_file() {
+        return Err(Error::NotFound(path.to_path_buf()));
+    }
+    let entries: Vec<BookEntry> = serde_json::from_str(&fs::read_to_string(path)?)?;
+    entries
+        .into_iter()
+        .map(|entry| {
+            if entry.weight < 0. {
+                return Err(Error::InvalidInput(format!("Negative weight {} of {} in the opening book", entry.weight, entry.position)));
+            }
+            Ok((parse_position::<T>(&entry.position)?, entry.weight))
+        })
+        .collect()
+}
+
+// The schedule with the phases of the game and the positions of its book and games
+pub struct StartPositions<T: LearnableGame> {
+    schedule: StartSchedule<T::Phase>,
+    book: Vec<(T, f64)>,
+    game_positions: Vec<T>,
+}
+
+impl <T: LearnableGame> StartPositions<T> {
+    /**
+     * Loads the book and the games used by the schedule, see new
+     */
+    pub fn load(config: &StartConfig) -> Result<Self, Error> {
+        let uses = |kind: SourceKind| config.schedule.sources().any(|source| source.source == kind && source.weight > 0.);
+        let book = match &config.book_path {
+            Some(path) if uses(SourceKind::Book) => load_opening_book(path)?,
+            _ => vec![],
+        };
+        let game_positions = match &config.games_path {
+            Some(path) if uses(SourceKind::Games) => {
+                if !path.is_dir() {
+                    return Err(Error::NotFound(path.clone()));
+                }
+                let games = load_all_games::<T>(path.clone())?;
+                games.iter().map(|game| game.states()).flatten_ok().collect::<Result<Vec<_>, Error>>()?
+            },
+            _ => vec![],
+        };
+        StartPositions::new(config.schedule.clone(), book, game_positions)
+    }
+
+    /**
+     * Parses the phases of the schedule and checks that every source with a weight can be sampled
+     */
+    pub fn new(schedule: StartSchedule, book: Vec<(T, f64)>, game_positions: Vec<T>) -> Result<Self, Error> {
+        let invalid = |message: String| Error::InvalidConfig { key: String::from("start_schedule"), message };
+        let schedule = schedule.parse_phases::<T::Phase>().map_err(invalid)?;
+        for source in schedule.sources().filter(|source| source.weight > 0.) {
+            match source.source {
+                SourceKind::Book if book.iter().all(|(_, weight)| *weight == 0.) => {
+                    return Err(invalid(String::from("a book source needs an opening book with positions, set opening_book_path")))
+                },
+                SourceKind::Games if game_positions.is_empty() => {
+                    return Err(invalid(String::from("a games source needs saved games, set start_games_path")))
+                },
+                SourceKind::Random if !T::HAS_RANDOM_POSITIONS => {
+                    return Err(invalid(format!("{} can not generate random positions", T::name())))
+                },
+                _ => (),
+            }
+        }
+        Ok(StartPositions { schedule, book, game_positions })
+    }
+
+    /**
+     * The position a self play game of the learn iteration starts from, rolled if the game has dice.
+     * The source is returned as well, only games from the initial position play a random opening
+     */
+    pub fn sample(&self, learn_iteration: usize, rng: &mut impl Rng) -> (T, SourceKind) {
+        let source = self.schedule.sources_at(learn_iteration).choose_weighted(rng, |source| source.weight).unwrap();
+        let mut state = match source.source {
+            SourceKind::Initial => T::new(),
+            SourceKind::Random => T::random_position(source.phase, rng).unwrap(),
+            SourceKind::Book => self.book.choose_weighted(rng, |(_, weight)| *weight).unwrap().0,
+            SourceKind::Games => *self.game_positions.choose(rng).unwrap(),
+        };
+        if needs_roll(&state) {
+            state.roll_die();
+        }
+        (state, source.source)
+    }
+}
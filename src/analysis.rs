@@ -0,0 +1,208 @@
+use std::{cmp::Ordering, fmt, fs, path::Path, str::FromStr};
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{
+    alphazero::net_agents::{evaluate_states, move_values},
+    base::LearnableGame,
+    evaluator::{tensor_to_vec, Evaluator},
+    expectimax::expectimax_search,
+    mcts::alpha_mcts::alpha_mcts_search,
+    rollout::needs_roll,
+    Error, MctsConfig,
+};
+
+/*
+Analysis of a single position, every valid move of the position is ranked by the chosen search:
+    - Mcts: AlphaMCTS from the position without root noise, moves are ranked by their visits
+    - Expectimax: the expectimax search of the config, moves pruned by the move filter are ranked last
+    - Value: the value head equity of each move (same as Agent::Value)
+Each move also gets its prior from the policy head (masked to the valid moves) and its equity from the value head,
+the equity is averaged over the rolls of the opponent.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AnalysisSearch {
+    Mcts, Expectimax, Value
+}
+
+impl FromStr for AnalysisSearch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mcts" => Ok(AnalysisSearch::Mcts),
+            "expectimax" => Ok(AnalysisSearch::Expectimax),
+            "value" => Ok(AnalysisSearch::Value),
+            other => Err(format!("unknown search {}, expected one of mcts, expectimax, value", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MoveAnalysis<M> {
+    pub action: M,
+    // Visits of the move in the search tree, only set by Mcts
+    pub visits: Option<f32>,
+    // Mean value of the move in the search tree (Mcts) or its searched value (Expectimax), None if not searched
+    // Both are from the view of the player to move in the analyzed position
+    pub q: Option<f32>,
+    pub prior: f32,
+    pub equity: f32,
+    // Difference of the value the moves are compared with (q, or the equity for Value) from the best move
+    pub diff: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionAnalysis<T: LearnableGame> {
+    pub search: AnalysisSearch,
+    pub position_id: String,
+    pub state: T,
+    // Value head evaluation of the position for the player to move
+    pub value: f32,
+    // See LearnableGame::features
+    pub features: Vec<(&'static str, f32)>,
+    // Every valid move, best move first
+    pub moves: Vec<MoveAnalysis<T::Move>>,
+}
+
+impl <T: LearnableGame> fmt::Display for PositionAnalysis<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fmt_opt = |value: Option<f32>, precision: usize| match value {
+            Some(value) => format!("{:.*}", precision, value),
+            None => String::from("-"),
+        };
+        writeln!(f, "{}", self.state.to_pretty_str())?;
+        writeln!(f, "Position: {}", self.position_id)?;
+        writeln!(f, "Player to move: {}\tValue: {:.4}\tSearch: {:?}", self.state.get_player(), self.value, self.search)?;
+        if !self.features.is_empty() {
+            let features = self.features.iter().map(|(name, value)| format!("{}: {}", name, value)).join("  ");
+            writeln!(f, "{}", features)?;
+        }
+        let actions = self.moves.iter().map(|analysis| format!("{:?}", analysis.action)).collect_vec();
+        let width = actions.iter().map(|action| action.len()).max().unwrap_or(0).max(4);
+        writeln!(
+            f, "{:>4}  {:<width$}  {:>8}  {:>8}  {:>8}  {:>8}  {:>8}",
+            "#", "Move", "Visits", "Q", "Prior", "Equity", "Diff", width = width
+        )?;
+        for (rank, (analysis, action)) in self.moves.iter().zip(actions).enumerate() {
+            writeln!(
+                f, "{:>4}  {:<width$}  {:>8}  {:>8}  {:>8.4}  {:>8.4}  {:>8}",
+                rank + 1, action, fmt_opt(analysis.visits, 0), fmt_opt(analysis.q, 4),
+                analysis.prior, analysis.equity, fmt_opt(analysis.diff, 4), width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Policy head probabilities of the moves, renormalized over the moves
+fn move_priors<T: LearnableGame>(evaluator: &impl Evaluator<T>, state: &T, moves: &[T::Move]) -> Vec<f32> {
+    let policy = tensor_to_vec(&evaluator.policy(&[*state]));
+    let priors = moves.iter().map(|action| policy[state.encode(action) as usize]).collect_vec();
+    let total: f32 = priors.iter().sum();
+    if total > 0. {
+        priors.iter().map(|prior| prior / total).collect_vec()
+    } else {
+        priors
+    }
+}
+
+// Fails with Error::InvalidInput if the game has dice and the position has not been rolled, the moves depend on the roll
+pub fn analyze<T: LearnableGame>(state: &T, evaluator: &impl Evaluator<T>, mcts_config: &MctsConfig, search: AnalysisSearch) -> Result<PositionAnalysis<T>, Error> {
+    if needs_roll(state) {
+        return Err(Error::InvalidInput(format!("{} positions need a roll to be analyzed", T::name())));
+    }
+    let _guard = tch::no_grad_guard();
+    let (valid_moves, equities): (Vec<T::Move>, Vec<f32>) = move_values(evaluator, state).into_iter().unzip();
+    let priors = move_priors(evaluator, state, &valid_moves);
+
+    // (visits, q) of each valid move
+    let searched: Vec<(Option<f32>, Option<f32>)> = match search {
+        AnalysisSearch::Mcts => {
+            let config = MctsConfig { dirichlet_epsilon: 0., ..mcts_config.clone() };
+            let store = alpha_mcts_search(state, evaluator, &config);
+            valid_moves
+                .iter()
+                .map(|action| {
+                    match store.children(0).find(|&idx| store.get_node_ref(idx).action_taken.as_ref() == Some(action)) {
+                        Some(idx) => {
+                            let visits = store.visits(idx);
+                            (Some(visits), (visits > 0.).then(|| store.win_pct(idx)))
+                        }
+                        None => (None, None),
+                    }
+                })
+                .collect_vec()
+        }
+        AnalysisSearch::Expectimax => {
+            let move_values = expectimax_search(evaluator, &[*state], &mcts_config.expectimax).pop().unwrap();
+            valid_moves
+                .iter()
+                .map(|action| (None, move_values.iter().find(|(m, _)| m == action).map(|(_, value)| *value)))
+                .collect_vec()
+        }
+        AnalysisSearch::Value => valid_moves.iter().map(|_| (None, None)).collect_vec(),
+    };
+
+    let mut moves = valid_moves
+        .into_iter()
+        .zip(searched)
+        .zip(priors.into_iter().zip(equities))
+        .map(|((action, (visits, q)), (prior, equity))| MoveAnalysis { action, visits, q, prior, equity, diff: None })
+        .collect_vec();
+
+    let score = |analysis: &MoveAnalysis<T::Move>| match search {
+        AnalysisSearch::Value => Some(analysis.equity),
+        _ => analysis.q,
+    };
+    let cmp_opt = |a: Option<f32>, b: Option<f32>| match (a, b) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    };
+    moves.sort_by(|a, b| {
+        let by_visits = match search {
+            AnalysisSearch::Mcts => cmp_opt(b.visits, a.visits),
+            _ => Ordering::Equal,
+        };
+        by_visits.then_with(|| cmp_opt(score(b), score(a)))
+    });
+    let best_score = moves.first().and_then(score);
+    for analysis in moves.iter_mut() {
+        analysis.diff = score(analysis).zip(best_score).map(|(score, best)| score - best);
+    }
+
+    Ok(PositionAnalysis {
+        search,
+        position_id: state.position_id(),
+        state: *state,
+        value: evaluate_states(evaluator, &[*state])[0],
+        features: state.features(),
+        moves,
+    })
+}
+
+/**
+ * Reads a position given as the path of a JSON file, as inline JSON or as a position id (see LearnableGame::position_id)
+ */
+pub fn parse_position<T: LearnableGame>(position: &str) -> Result<T, Error> {
+    let path = Path::new(position);
+    if path.is_file() {
+        return Ok(serde_json::from_str(&fs::read_to_string(path)?)?);
+    }
+    if position.trim_start().starts_with('{') {
+        return Ok(serde_json::from_str(position)?);
+    }
+    T::from_position_id(position).ok_or_else(|| Error::InvalidInput(format!("{} is not a position file, JSON or position id", position)))
+}
+
+// Reads a roll written as 6-4, 6,4, 6 4 or 64
+pub fn parse_roll(roll: &str) -> Option<(u8, u8)> {
+    let dice = roll.chars().filter(|c| !matches!(c, '-' | ',' | ' ')).collect_vec();
+    if dice.len() != 2 {
+        return None;
+    }
+    let die = |c: char| c.to_digit(10).filter(|d| (1..=6).contains(d)).map(|d| d as u8);
+    Some((die(dice[0])?, die(dice[1])?))
+}
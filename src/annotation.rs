@@ -0,0 +1,236 @@
+use std::{fmt, fs::File, io::Write, path::Path};
+
+use config::{Config, ConfigError};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{analyze, AnalysisSearch},
+    base::LearnableGame,
+    evaluator::Evaluator,
+    versus::{Agent, Game},
+    Error, MctsConfig,
+};
+
+/*
+Annotation of recorded games, every turn of a game is analyzed (see analysis::analyze) and the played move
+is compared with the best move of the engine. Moves are classified by their equity loss:
+    - Doubtful: loss of at least thresholds.doubtful
+    - Error: loss of at least thresholds.error
+    - Blunder: loss of at least thresholds.blunder
+Turns with a single valid move are not analyzed and do not count as decisions.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorClass {
+    Doubtful, Error, Blunder
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorThresholds {
+    pub doubtful: f32,
+    pub error: f32,
+    pub blunder: f32,
+}
+
+impl Default for ErrorThresholds {
+    fn default() -> Self {
+        ErrorThresholds { doubtful: 0.04, error: 0.08, blunder: 0.16 }
+    }
+}
+
+impl ErrorThresholds {
+    pub fn from_config(conf: &Config) -> Result<Self, ConfigError> {
+        Ok(ErrorThresholds {
+            doubtful: conf.get_float("annotate_doubtful")? as f32,
+            error: conf.get_float("annotate_error")? as f32,
+            blunder: conf.get_float("annotate_blunder")? as f32,
+        })
+    }
+
+    pub fn classify(&self, equity_loss: f32) -> Option<ErrorClass> {
+        if equity_loss >= self.blunder {
+            Some(ErrorClass::Blunder)
+        } else if equity_loss >= self.error {
+            Some(ErrorClass::Error)
+        } else if equity_loss >= self.doubtful {
+            Some(ErrorClass::Doubtful)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(bound = "")]
+pub struct AnnotatedTurn<T: LearnableGame> {
+    // Index of the turn in the game
+    pub turn: usize,
+    pub player: Agent,
+    pub position_id: String,
+    pub roll: Option<(u8, u8)>,
+    pub played: T::Move,
+    pub best: T::Move,
+    // 1 for the best move
+    pub played_rank: usize,
+    pub n_moves: usize,
+    pub equity_loss: f32,
+    pub class: Option<ErrorClass>,
+    // See LearnableGame::features, e.g. to tell errors in races from errors in contact positions
+    pub features: Vec<(&'static str, f32)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PlayerStats {
+    // Turns with more than one valid move
+    pub decisions: usize,
+    pub doubtful: usize,
+    pub errors: usize,
+    pub blunders: usize,
+    pub total_equity_loss: f32,
+}
+
+impl PlayerStats {
+    pub fn add(&mut self, equity_loss: f32, class: Option<ErrorClass>) {
+        self.decisions += 1;
+        self.total_equity_loss += equity_loss;
+        match class {
+            Some(ErrorClass::Doubtful) => self.doubtful += 1,
+            Some(ErrorClass::Error) => self.errors += 1,
+            Some(ErrorClass::Blunder) => self.blunders += 1,
+            None => (),
+        }
+    }
+
+    pub fn merge(&mut self, other: &PlayerStats) {
+        self.decisions += other.decisions;
+        self.doubtful += other.doubtful;
+        self.errors += other.errors;
+        self.blunders += other.blunders;
+        self.total_equity_loss += other.total_equity_loss;
+    }
+
+    // Share of the decisions that were errors or blunders
+    pub fn error_rate(&self) -> f32 {
+        if self.decisions == 0 {
+            return 0.;
+        }
+        (self.errors + self.blunders) as f32 / self.decisions as f32
+    }
+
+    pub fn mean_equity_loss(&self) -> f32 {
+        if self.decisions == 0 {
+            return 0.;
+        }
+        self.total_equity_loss / self.decisions as f32
+    }
+}
+
+impl fmt::Display for PlayerStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Decisions: {}\tDoubtful: {}\tErrors: {}\tBlunders: {}\tError rate: {:.2}%\tMean equity loss: {:.4}",
+            self.decisions, self.doubtful, self.errors, self.blunders, self.error_rate() * 100., self.mean_equity_loss()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(bound = "")]
+pub struct AnnotatedGame<T: LearnableGame> {
+    pub game_id: String,
+    pub player1: Agent,
+    pub player2: Agent,
+    pub winner: Agent,
+    pub search: AnalysisSearch,
+    pub turns: Vec<AnnotatedTurn<T>>,
+    pub stats_p1: PlayerStats,
+    pub stats_p2: PlayerStats,
+}
+
+/**
+ * Analyzes every turn of the game and compares the played move with the best move.
+ * The equity loss of a move is its difference from the best move (see MoveAnalysis::diff),
+ * moves the search did not value are compared by their value head equity instead.
+ */
+pub fn annotate_game<T: LearnableGame>(
+    game: &Game<T>,
+    evaluator: &impl Evaluator<T>,
+    mcts_config: &MctsConfig,
+    search: AnalysisSearch,
+    thresholds: &ErrorThresholds,
+) -> Result<AnnotatedGame<T>, Error> {
+    let mut stats_p1 = PlayerStats::default();
+    let mut stats_p2 = PlayerStats::default();
+    let mut turns = vec![];
+
+    let states = game.states()?;
+    let pb = ProgressBar::new(game.turns.len() as u64).with_message(format!("Annotating {}", game.id));
+    for (turn_idx, (turn, state)) in game.turns.iter().zip(states).enumerate() {
+        pb.inc(1);
+        // Turns skipped by the move guard did not play their illegal action
+        if turn.skipped || state.get_valid_moves().len() < 2 {
+            continue;
+        }
+        let analysis = analyze(&state, evaluator, mcts_config, search)?;
+        let best = &analysis.moves[0];
+        // Game::states already checked that every action of a turn that was not skipped is valid
+        let (played_rank, played) = analysis
+            .moves
+            .iter()
+            .enumerate()
+            .find(|(_, m)| m.action == turn.action)
+            .map(|(rank, played)| (rank + 1, played))
+            .unwrap();
+        let equity_loss = match played.diff {
+            Some(diff) => -diff,
+            None => best.equity - played.equity,
+        }
+        .max(0.);
+        let class = thresholds.classify(equity_loss);
+
+        // Player 1 of versus::play moves as -1
+        if state.get_player() == -1 {
+            stats_p1.add(equity_loss, class);
+        } else {
+            stats_p2.add(equity_loss, class);
+        }
+        turns.push(AnnotatedTurn {
+            turn: turn_idx,
+            player: turn.player.clone(),
+            position_id: analysis.position_id.clone(),
+            roll: turn.roll,
+            played: turn.action.clone(),
+            best: best.action.clone(),
+            played_rank,
+            n_moves: analysis.moves.len(),
+            equity_loss,
+            class,
+            features: analysis.features.clone(),
+        });
+    }
+    pb.finish_and_clear();
+
+    Ok(AnnotatedGame {
+        game_id: game.id.clone(),
+        player1: game.player1.clone(),
+        player2: game.player2.clone(),
+        winner: game.winner.clone(),
+        search,
+        turns,
+        stats_p1,
+        stats_p2,
+    })
+}
+
+pub fn save_annotated_game<T: LearnableGame>(annotated: &AnnotatedGame<T>, dir_path: &Path) -> Result<(), Error> {
+    let path = dir_path.join(format!("{}-annotated.json", &annotated.game_id));
+    let file = File::create(path)?;
+
+    let serialized = serde_json::to_string_pretty(annotated)?;
+    let mut writer = std::io::BufWriter::new(file);
+    writer.write_all(serialized.as_bytes())?;
+
+    Ok(())
+}
@@ -1,11 +1,13 @@
 use itertools::Itertools;
-use rand::Rng;
+use rand::{seq::SliceRandom, Rng};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, fmt, vec};
+use std::{collections::{hash_map::DefaultHasher, HashSet}, fmt, hash::{Hash, Hasher}, vec};
 use tch::Tensor;
 
 use crate::{constants::DEFAULT_TYPE, base::LearnableGame};
 
+use super::{bearoff::BearoffDatabase, dice::{DiceSource, ThreadRngDice}, generator};
+
 // (the board itself, pieces_hit, pieces_collected)
 pub type Board = ([i8; 24], (u8, u8), (u8, u8));
 // (from, to) if to == -1 then it is collection, if from == -1 then it is putting a hit piece back
@@ -71,8 +73,11 @@ impl LearnableGame for Backgammon {
 
     const EMPTY_MOVE: Self::Move = vec![];
     const IS_DETERMINISTIC: bool = false;
+    type Phase = generator::Phase;
+    const HAS_RANDOM_POSITIONS: bool = true;
     const ACTION_SPACE_SIZE: i64 = 1352;
-    const N_INPUT_CHANNELS: i64 = 6;
+    // The positional-features feature adds a channel for each of pips, blots, points, prime, checkers back and race
+    const N_INPUT_CHANNELS: i64 = if cfg!(feature = "positional-features") { 12 } else { 6 };
     const CONV_OUTPUT_SIZE: i64 = 24;
     const N_FILTERS: i64 = 256;
     const N_RES_BLOCKS: i64 = 19;
@@ -98,9 +103,35 @@ impl LearnableGame for Backgammon {
     }
 
     fn roll_die(&mut self) -> (u8, u8) {
-        let mut rng = rand::thread_rng();
-        self.roll = (rng.gen_range(1..=6), rng.gen_range(1..=6));
-        self.roll
+        self.roll_die_with(&mut ThreadRngDice)
+    }
+
+    fn get_roll(&self) -> Option<(u8, u8)> {
+        Some(self.roll)
+    }
+
+    // A position of the phase, a uniformly random phase if none is given, see backgammon/generator.rs
+    fn random_position(phase: Option<generator::Phase>, rng: &mut impl Rng) -> Option<Self> {
+        let phase = phase.unwrap_or_else(|| *generator::PHASES.choose(rng).unwrap());
+        Some(generator::random_position(phase, rng))
+    }
+
+    fn set_roll(&mut self, roll: (u8, u8)) {
+        assert!((1..=6).contains(&roll.0) && (1..=6).contains(&roll.1), "invalid roll: {:?}", roll);
+        self.roll = roll;
+    }
+
+    fn chance_outcomes(&self) -> Vec<(Self, f32)> {
+        let mut outcomes = Vec::with_capacity(21);
+        for high in 1..=6 {
+            for low in 1..=high {
+                let mut state = *self;
+                state.roll = (high, low);
+                let prob = if high == low { 1. / 36. } else { 2. / 36. };
+                outcomes.push((state, prob));
+            }
+        }
+        outcomes
     }
 
     fn check_winner(&self) -> Option<i8> {
@@ -163,10 +194,16 @@ impl LearnableGame for Backgammon {
         }).join("\n");
 
         let player_as_string = if self.player == -1 {String::from("Player 1")} else {String::from("Player 2")};
-        let player1_info = format!("Player 1:\n\tBroken Pieces: {}\n\tPieces Collected:{}", &self.board.1.0, &self.board.2.0);
-        let player2_info = format!("Player 2:\n\tBroken Pieces: {}\n\tPieces Collected:{}", &self.board.1.1, &self.board.2.1);
+        let player_info = |player: i8| format!(
+            "\tBroken Pieces: {}\n\tPieces Collected:{}\n\tPips: {}\tBlots: {}\tPoints: {}\tPrime: {}\tBack: {}",
+            self.checkers_on_bar(player), if player == -1 { self.board.2.0 } else { self.board.2.1 },
+            self.pip_count(player), self.blots(player), self.made_points(player), self.longest_prime(player), self.checkers_back(player),
+        );
+        let player1_info = format!("Player 1:\n{}", player_info(-1));
+        let player2_info = format!("Player 2:\n{}", player_info(1));
+        let stage = if self.is_race() { "Race" } else { "Contact" };
         
-        let info_string = format!("Current turn: {}\tRoll: {:?}\n{}\n{}", player_as_string, &self.roll, player1_info, player2_info);
+        let info_string = format!("Current turn: {}\tRoll: {:?}\t{}\n{}\n{}", player_as_string, &self.roll, stage, player1_info, player2_info);
 
         let line_break = "=".repeat(110);
 
@@ -174,6 +211,18 @@ impl LearnableGame for Backgammon {
     }
 
     fn apply_move(&mut self, actions: &Actions) {
+        self.apply_move_with(actions, &mut ThreadRngDice)
+    }
+
+    fn get_player(&self) -> i8 {
+        self.player
+    }
+    
+    fn skip_turn(&mut self) {
+        self.skip_turn_with(&mut ThreadRngDice)
+    }
+
+    fn apply_move_with(&mut self, actions: &Actions, dice: &mut impl DiceSource) {
         let next_state = Self::get_next_state(self.board, actions, self.player);
         self.board = next_state;
         if self.roll.0 == self.roll.1 && !self.is_second_play {
@@ -181,18 +230,14 @@ impl LearnableGame for Backgammon {
         } else {
             self.is_second_play = false;
             self.player *= -1;
-            self.roll_die();
+            self.roll_die_with(dice);
         }
     }
 
-    fn get_player(&self) -> i8 {
-        self.player
-    }
-    
-    fn skip_turn(&mut self) {
+    fn skip_turn_with(&mut self, dice: &mut impl DiceSource) {
         self.is_second_play = false;
         self.player *= -1;
-        self.roll_die();
+        self.roll_die_with(dice);
     }
     
     fn as_tensor(&self) -> Tensor {
@@ -237,20 +282,101 @@ impl LearnableGame for Backgammon {
             Tensor::full(24, 0, full_options).view([4, 6, 1])
         };
     
-        Tensor::stack(
-            &[
-                board_tensor,
-                player_tensor,
-                hit_tensor,
-                collect_tensor,
-                roll_tensor,
-                second_play_tensor,
-            ],
-            2,
-        )
+        #[allow(unused_mut)]
+        let mut channels = vec![
+            board_tensor,
+            player_tensor,
+            hit_tensor,
+            collect_tensor,
+            roll_tensor,
+            second_play_tensor,
+        ];
+        #[cfg(feature = "positional-features")]
+        {
+            // Same layout as the hit and collect channels, 12 cells of player -1 followed by 12 cells of player 1
+            let per_player = |feature: &dyn Fn(i8) -> f64| {
+                Tensor::cat(
+                    &[
+                        Tensor::full(12, feature(-1), full_options),
+                        Tensor::full(12, feature(1), full_options),
+                    ],
+                    0,
+                )
+                .view([4, 6, 1])
+            };
+            channels.push(per_player(&|player| self.pip_count(player) as f64 / 100.));
+            channels.push(per_player(&|player| self.blots(player) as f64));
+            channels.push(per_player(&|player| self.made_points(player) as f64));
+            channels.push(per_player(&|player| self.longest_prime(player) as f64));
+            channels.push(per_player(&|player| self.checkers_back(player) as f64));
+            channels.push(Tensor::full(24, if self.is_race() { 1 } else { 0 }, full_options).view([4, 6, 1]));
+        }
+
+        Tensor::stack(&channels, 2)
         .permute([3, 2, 0, 1])
     }
 
+    fn features(&self) -> Vec<(&'static str, f32)> {
+        self.position_features()
+    }
+
+    // Pure bear-off races are looked up in the bear-off database if one is given
+    fn exact_value(&self, bearoff: Option<&BearoffDatabase>) -> Option<f32> {
+        bearoff.and_then(|database| database.evaluate(self))
+    }
+
+    fn position_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.board, self.roll, self.player, self.is_second_play).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /**
+     * player:points:bar:collected, points are the 24 signed counts of the board
+     * e.g. the initial state is -1:2,0,0,0,0,-5,0,-3,0,0,0,5,-5,0,0,0,3,0,5,0,0,0,0,-2:0,0:0,0
+     * followed by :second when the player plays the second half of a double
+     */
+    fn position_id(&self) -> String {
+        let (points, bar, collected) = self.board;
+        format!(
+            "{}:{}:{},{}:{},{}{}",
+            self.player, points.iter().join(","), bar.0, bar.1, collected.0, collected.1,
+            if self.is_second_play { ":second" } else { "" }
+        )
+    }
+
+    fn from_position_id(id: &str) -> Option<Self> {
+        let parts = id.trim().split(':').collect_vec();
+        let is_second_play = match parts.get(4) {
+            None => false,
+            Some(&"second") if parts.len() == 5 => true,
+            _ => return None,
+        };
+        if parts.len() < 4 {
+            return None;
+        }
+        let player: i8 = parts[0].parse().ok()?;
+        if player != -1 && player != 1 {
+            return None;
+        }
+        let points: Vec<i8> = parts[1].split(',').map(|p| p.trim().parse().ok()).collect::<Option<_>>()?;
+        if points.iter().any(|n_checkers| n_checkers.unsigned_abs() > 15) {
+            return None;
+        }
+        let pair = |part: &str| -> Option<(u8, u8)> {
+            let (first, second) = part.split_once(',')?;
+            Some((first.trim().parse().ok()?, second.trim().parse().ok()?))
+        };
+        let mut state = Backgammon::new();
+        state.board = (points.try_into().ok()?, pair(parts[2])?, pair(parts[3])?);
+        state.player = player;
+        state.is_second_play = is_second_play;
+        if state.total_checkers(-1) != 15 || state.total_checkers(1) != 15 {
+            return None;
+        }
+        Some(state)
+    }
+
     fn get_id(&self) -> usize {
         self.id
     }
@@ -259,145 +385,13 @@ impl LearnableGame for Backgammon {
         self.id = new_id
     }
 
+    // The encoding is implemented in backgammon/encoding.rs
     fn encode(&self, actions: &Actions) -> u32 {
-        assert!(actions.len() <= 2, "encoding for actions > 2 is not implemented!");
-    
-        // return special case for when there are no actions
-        if actions.is_empty() {
-            return 1351;
-        }
-        
-        // get the roll and the high and low roll values
-        let roll = self.roll;
-        let (_high_roll, low_roll) = if roll.0 > roll.1 { (roll.0, roll.1) } else { (roll.1, roll.0) };
-        let mut low_roll_first_flag = false;
-        let mut low_roll_second_flag = false;
-    
-        // get the minimum roll values required to be able to play the actions provided
-        let mut minimum_rolls = actions.iter().map(|&(from, to)| {
-            match (from, to) {
-                (-1, t) if t < 6 => (t + 1) as u8,
-                (-1, t) if t > 17 => (24 - t) as u8,
-                (f, -1) if f < 6 => (f - (-1)) as u8,
-                (f, -1) if f > 17 => (24 - f) as u8,
-                (f, t) => (f - t).unsigned_abs(),
-            }
-        }).collect::<Vec<u8>>();
-    
-        // if only a single move is played, set the second minimum roll to be 0
-        if minimum_rolls.len() == 1 {minimum_rolls.push(0);}
-    
-        /*
-         * use base 26 encoding to encode the actions
-         * the values 0-23 is reserved for 'normal' moves (i.e. moves that are not from the bar or are not collection moves)
-         * and corresponds to the 'from' value of the action
-         * 24 is reserved for moves from the bar
-         * 25 is reserved for single moves (i.e. the value of the non-existent 'second move' of a single-move action)
-         * add the corresponding value for the first move and the corresponding value for the second move times 26
-         */ 
-        let mut encode_sum = 0;
-        for (i, &(from, to)) in actions.iter().enumerate() {
-            match i {
-                0 => {match (from, to) {
-                    (-1, t) if t < 6 => {
-                        encode_sum += 24;
-
-                        // raise the low_roll_first_flag if the first move is certainly the low roll
-                        let distance = (t - (-1)) as u8;
-                        low_roll_first_flag = distance == low_roll;
-                    },
-                    (-1, t) if t > 17 => {
-                        encode_sum += 24;
-
-                        // raise the low_roll_first_flag if the first move is certainly the low roll
-                        let distance = (24 - t) as u8;
-                        low_roll_first_flag = distance == low_roll;
-                    },
-                    (f, -1) if f < 6 => {encode_sum += f as u32;},
-                    (f, -1) if f > 17 => {encode_sum += f as u32;},
-                    (f, _) => {
-                        encode_sum += f as u32; 
-                        // raise the low_roll_first_flag if the first move is certainly the low roll
-                        low_roll_first_flag = minimum_rolls.first().unwrap() == &low_roll;
-                    },
-                }},
-                1 => {match (from, to) {
-                    (-1, t) if t < 6 => {
-                        encode_sum += 26 * 24;
-
-                        // raise the low_roll_second_flag if the first move is certainly the low roll
-                        let distance = (t - (-1)) as u8;
-                        low_roll_second_flag = distance == low_roll;
-                    },
-                    (-1, t) if t > 17 => {
-                        encode_sum += 26 * 24;
-
-                        // raise the low_roll_first_flag if the first move is certainly the low roll
-                        let distance = (24 - t) as u8;
-                        low_roll_second_flag = distance == low_roll;
-                    },
-                    (f, -1) if f < 6 => {encode_sum += 26 * (f as u32);},
-                    (f, -1) if f > 17 => {encode_sum += 26 * (f as u32);},
-                    (f, _) => {
-                        encode_sum += 26 * (f as u32);
-                        // raise the low_roll_second flag if the first move is certainly the low roll
-                        low_roll_second_flag = minimum_rolls.get(1).unwrap() == &low_roll;
-                    },
-                }},
-                _ => unreachable!(),
-            }
-        }
-    
-        // add 26 * 25 to encode_sum if the action has a single move and reset low_roll_first_flag
-        if actions.get(1).is_none() {low_roll_first_flag = false; encode_sum += 26 * 25}
-    
-        // compute whether the high roll was played first
-        let high_roll_first = if low_roll_first_flag {false} else if low_roll_second_flag {true} else if minimum_rolls[1] != 0 {minimum_rolls[0] >= minimum_rolls[1]} else {minimum_rolls[0] > low_roll};
-    
-        // add 676 to the final value if the high roll was played first
-        if high_roll_first { encode_sum } else { encode_sum + 676 }
+        Backgammon::encode(self, actions)
     }
-    
+
     fn decode(&self, action: u32) -> Actions {
-        // decoding for the special value (1351) for empty actions
-        if action == 1351 {
-            return vec![];
-        }
-    
-        let roll = self.roll;
-        let player = self.player;
-        let high_roll_first = action < 676;
-
-        /*
-         * extract the from values of the first and second action
-         * the from value '24' suggests a move from the bar
-         * note that the from2 value will be 25 if the action has a single move
-         */
-        let (from1, from2) = (if high_roll_first { action } else { action - 676 } % 26, 
-                                        if high_roll_first { action } else { action - 676 } / 26);
-        let single_action = from2 == 25;
-        let (high_roll, low_roll) = if roll.0 > roll.1 { (roll.0, roll.1) } else { (roll.1, roll.0) };
-        let (mut from1_i8, mut from2_i8) = (from1 as i8, from2 as i8);
-        let (low_roll_i8, high_roll_i8) = (low_roll as i8, high_roll as i8);
-    
-        // convert from values from 24 to -1 only if the player is the second player (helps with computation)
-        if from1_i8 == 24 && player == 1 { from1_i8 = -1; }
-        if from2_i8 == 24 && player == 1 { from2_i8 = -1; }
-    
-        // extract 'to' values
-        let (mut to1, mut to2) = if high_roll_first {
-            (from1_i8 + high_roll_i8 * player, from2_i8 + low_roll_i8 * player)
-        } else {
-            (from1_i8 + low_roll_i8 * player, from2_i8 + high_roll_i8 * player)
-        };
-    
-        // convert the 'to' and 'from' values from -1
-        if to1 >= 24 || to1 <= -1 { to1 = -1; }
-        if to2 >= 24 || to2 <= -1 { to2 = -1; }
-        if from1_i8 == 24 { from1_i8 = -1; }
-        if from2_i8 == 24 { from2_i8 = -1; }
-    
-        if single_action { vec![(from1_i8, to1)] } else { vec![(from1_i8, to1), (from2_i8, to2)] }
+        Backgammon::decode(self, action)
     }
 
     fn get_valid_moves(&self) -> Vec<Actions> {
@@ -426,6 +420,11 @@ impl Backgammon {
         }
     }
 
+    pub fn roll_die_with(&mut self, dice: &mut impl DiceSource) -> (u8, u8) {
+        self.roll = dice.roll();
+        self.roll
+    }
+
     /**
      * Checks if current board is valid by asserting that 
      * both players have 15 pieces on the board including collected and barred ones.
@@ -0,0 +1,321 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use itertools::Itertools;
+
+use super::backgammon_logic::{Backgammon, Board};
+
+/*
+One-sided bear-off database, for every position of up to max_checkers checkers on the six home points of a player
+it holds the distribution of the number of turns the player needs to bear off all of its checkers.
+
+    - Positions are the checker counts of the home points, counts[0] is the point closest to being borne off
+    - Each roll is played to minimize the expected number of turns, doubles play four dice
+    - Once both players only have checkers in their home boards there is no contact left,
+      the chance of the player to move winning follows from the two distributions (see BearoffDatabase::evaluate)
+
+On disk, a header (MAGIC, max_checkers, max_turns) is followed by the distribution of each position in index order,
+stored as the first turn with a nonzero probability, the number of stored turns and the probabilities as u16.
+*/
+
+const MAGIC: &[u8; 8] = b"DIEEBEAR";
+pub const N_POINTS: usize = 6;
+pub const MAX_CHECKERS: u8 = 15;
+// Turns beyond MAX_TURNS - 1 are counted as MAX_TURNS - 1, only a long run of small rolls needs that many
+pub const MAX_TURNS: usize = 32;
+
+pub type HomeCounts = [u8; N_POINTS];
+
+// Number of ways to put exactly n_checkers checkers on n_slots slots
+fn n_ways(n_slots: usize, n_checkers: usize) -> usize {
+    // binomial(n_checkers + n_slots - 1, n_slots - 1)
+    let k = n_slots - 1;
+    (1..=k).fold(1, |acc, i| acc * (n_checkers + i) / i)
+}
+
+/**
+ * Checker counts of the home points of the player, None if the player can not bear off yet
+ */
+pub fn home_counts(board: Board, player: i8) -> Option<HomeCounts> {
+    if !Backgammon::is_collectible(board, player) {
+        return None;
+    }
+    let mut counts = [0; N_POINTS];
+    for (distance, count) in counts.iter_mut().enumerate() {
+        // Player -1 bears off from point 0, player 1 from point 23
+        let point = if player == -1 { distance } else { 23 - distance };
+        *count = (board.0[point] * player).max(0) as u8;
+    }
+    Some(counts)
+}
+
+// All positions after playing the dice in any order, every die can always be played while checkers are left
+fn play_dice(counts: HomeCounts, dice: &[u8]) -> Vec<HomeCounts> {
+    let mut positions = vec![counts];
+    for &die in dice {
+        positions = positions
+            .into_iter()
+            .flat_map(|position| play_die(position, die))
+            .sorted()
+            .dedup()
+            .collect_vec();
+    }
+    positions
+}
+
+fn play_die(counts: HomeCounts, die: u8) -> Vec<HomeCounts> {
+    let die = die as usize;
+    let highest = match counts.iter().rposition(|&count| count > 0) {
+        Some(highest) => highest,
+        None => return vec![counts],
+    };
+    (0..N_POINTS)
+        .filter(|&point| counts[point] > 0)
+        .filter_map(|point| {
+            let mut next = counts;
+            next[point] -= 1;
+            if point + 1 > die {
+                next[point - die] += 1;
+            } else if point + 1 < die && point != highest {
+                // A higher die can only bear off from the highest point
+                return None;
+            }
+            Some(next)
+        })
+        .collect_vec()
+}
+
+fn successors(counts: HomeCounts, roll: (u8, u8), is_second_play: bool) -> Vec<HomeCounts> {
+    if roll.0 == roll.1 {
+        let n_dice = if is_second_play { 2 } else { 4 };
+        play_dice(counts, &vec![roll.0; n_dice])
+    } else {
+        let mut positions = play_dice(counts, &[roll.0, roll.1]);
+        positions.extend(play_dice(counts, &[roll.1, roll.0]));
+        positions.into_iter().sorted().dedup().collect_vec()
+    }
+}
+
+// Every position with up to max_checkers checkers
+fn all_positions(max_checkers: u8) -> Vec<HomeCounts> {
+    let mut positions = vec![[0; N_POINTS]];
+    for point in 0..N_POINTS {
+        positions = positions
+            .into_iter()
+            .flat_map(|counts| {
+                let n_placed: u8 = counts.iter().sum();
+                (0..=max_checkers - n_placed).map(move |count| {
+                    let mut next = counts;
+                    next[point] = count;
+                    next
+                })
+            })
+            .collect_vec();
+    }
+    positions
+}
+
+fn rolls() -> impl Iterator<Item = ((u8, u8), f32)> {
+    (1..=6).flat_map(|high| (1..=high).map(move |low| ((high, low), if high == low { 1. / 36. } else { 2. / 36. })))
+}
+
+#[derive(Clone, PartialEq)]
+pub struct BearoffDatabase {
+    max_checkers: u8,
+    // MAX_TURNS probabilities for each position, in index order
+    distributions: Vec<f32>,
+}
+
+// The configs holding the database are printed, the distributions are left out
+impl fmt::Debug for BearoffDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BearoffDatabase").field("max_checkers", &self.max_checkers).field("positions", &self.len()).finish()
+    }
+}
+
+impl BearoffDatabase {
+    pub fn max_checkers(&self) -> u8 {
+        self.max_checkers
+    }
+
+    pub fn len(&self) -> usize {
+        self.distributions.len() / MAX_TURNS
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.distributions.is_empty()
+    }
+
+    /**
+     * Index of the position, positions with up to max_checkers checkers are numbered from 0 without gaps
+     */
+    pub fn index(&self, counts: &HomeCounts) -> Option<usize> {
+        let n_checkers = counts.iter().map(|&count| count as usize).sum::<usize>();
+        if n_checkers > self.max_checkers as usize {
+            return None;
+        }
+        // The checkers that are not on the board are put on an extra slot, so every position has max_checkers checkers
+        let mut remaining = self.max_checkers as usize;
+        let mut index = 0;
+        for (point, &count) in counts.iter().enumerate() {
+            let n_slots_after = N_POINTS - point;
+            for placed in 0..count as usize {
+                index += n_ways(n_slots_after, remaining - placed);
+            }
+            remaining -= count as usize;
+        }
+        Some(index)
+    }
+
+    /**
+     * Solves every position with up to max_checkers checkers, positions are solved in increasing order of pips
+     * so the positions after a roll are always solved before
+     */
+    pub fn generate(max_checkers: u8) -> Self {
+        let n_positions = n_ways(N_POINTS + 1, max_checkers as usize);
+        let mut database = BearoffDatabase { max_checkers, distributions: vec![0.; n_positions * MAX_TURNS] };
+        let mut expected_turns = vec![0.; n_positions];
+
+        let positions = all_positions(max_checkers)
+            .into_iter()
+            .sorted_by_key(|counts| counts.iter().enumerate().map(|(point, &count)| (point + 1) * count as usize).sum::<usize>())
+            .collect_vec();
+
+        for counts in positions {
+            let index = database.index(&counts).unwrap();
+            let mut distribution = [0.; MAX_TURNS];
+            if counts.iter().all(|&count| count == 0) {
+                distribution[0] = 1.;
+            } else {
+                let mut expected = 1.;
+                for (roll, prob) in rolls() {
+                    let best = successors(counts, roll, false)
+                        .into_iter()
+                        .map(|next| database.index(&next).unwrap())
+                        .min_by(|&a, &b| expected_turns[a].partial_cmp(&expected_turns[b]).unwrap())
+                        .unwrap();
+                    expected += prob * expected_turns[best];
+                    for (turns, p) in database.distribution_at(best).iter().enumerate() {
+                        distribution[(turns + 1).min(MAX_TURNS - 1)] += prob * p;
+                    }
+                }
+                expected_turns[index] = expected;
+            }
+            database.distributions[index * MAX_TURNS..(index + 1) * MAX_TURNS].copy_from_slice(&distribution);
+        }
+        database
+    }
+
+    fn distribution_at(&self, index: usize) -> &[f32] {
+        &self.distributions[index * MAX_TURNS..(index + 1) * MAX_TURNS]
+    }
+
+    /**
+     * Probabilities of needing 0, 1, .. MAX_TURNS - 1 turns to bear off all checkers,
+     * None if the position has more checkers than the database
+     */
+    pub fn distribution(&self, counts: &HomeCounts) -> Option<&[f32]> {
+        self.index(counts).map(|index| self.distribution_at(index))
+    }
+
+    pub fn expected_turns(&self, counts: &HomeCounts) -> Option<f32> {
+        self.distribution(counts)
+            .map(|distribution| distribution.iter().enumerate().map(|(turns, p)| turns as f32 * p).sum())
+    }
+
+    /**
+     * Chance of the player to move winning the race before rolling, the player to move wins
+     * if it needs at most as many turns as the other player
+     */
+    pub fn win_probability(&self, to_move: &HomeCounts, other: &HomeCounts) -> Option<f32> {
+        let to_move = self.distribution(to_move)?;
+        let other = self.distribution(other)?;
+        let mut other_at_least = 1.;
+        let mut win = 0.;
+        for (p_to_move, p_other) in to_move.iter().zip(other.iter()) {
+            win += p_to_move * other_at_least;
+            other_at_least -= p_other;
+        }
+        Some(win.clamp(0., 1.))
+    }
+
+    /**
+     * Value of the state in [-1, 1] for the player to move, if both players are bearing off and the database holds
+     * their positions. A state with a roll plays it to maximize the chance of winning.
+     */
+    pub fn evaluate(&self, state: &Backgammon) -> Option<f32> {
+        if Backgammon::check_win_without_player(state.board).is_some() {
+            return None;
+        }
+        let to_move = home_counts(state.board, state.player)?;
+        let other = home_counts(state.board, -state.player)?;
+        let win = if state.roll == (0, 0) {
+            self.win_probability(&to_move, &other)?
+        } else {
+            successors(to_move, state.roll, state.is_second_play)
+                .iter()
+                .map(|next| match next.iter().all(|&count| count == 0) {
+                    true => Some(1.),
+                    false => self.win_probability(&other, next).map(|other_wins| 1. - other_wins),
+                })
+                .collect::<Option<Vec<f32>>>()?
+                .into_iter()
+                .fold(0., f32::max)
+        };
+        Some(2. * win - 1.)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[self.max_checkers, MAX_TURNS as u8])?;
+        for index in 0..self.len() {
+            let quantized = self.distribution_at(index)
+                .iter()
+                .map(|p| (p * u16::MAX as f32).round() as u16)
+                .collect_vec();
+            let first = quantized.iter().position(|&p| p > 0).unwrap_or(0);
+            let last = quantized.iter().rposition(|&p| p > 0).unwrap_or(0);
+            writer.write_all(&[first as u8, (last + 1 - first) as u8])?;
+            for p in &quantized[first..=last] {
+                writer.write_all(&p.to_le_bytes())?;
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0; 10];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid("not a bear-off database"));
+        }
+        let (max_checkers, max_turns) = (header[8], header[9] as usize);
+        if max_checkers > MAX_CHECKERS || max_turns != MAX_TURNS {
+            return Err(invalid("unsupported bear-off database"));
+        }
+
+        let n_positions = n_ways(N_POINTS + 1, max_checkers as usize);
+        let mut distributions = vec![0.; n_positions * MAX_TURNS];
+        for distribution in distributions.chunks_mut(MAX_TURNS) {
+            let mut range = [0; 2];
+            reader.read_exact(&mut range)?;
+            let (first, len) = (range[0] as usize, range[1] as usize);
+            if first + len > MAX_TURNS {
+                return Err(invalid("corrupt distribution"));
+            }
+            let mut bytes = vec![0; len * 2];
+            reader.read_exact(&mut bytes)?;
+            for (p, chunk) in distribution[first..first + len].iter_mut().zip(bytes.chunks(2)) {
+                *p = u16::from_le_bytes([chunk[0], chunk[1]]) as f32 / u16::MAX as f32;
+            }
+        }
+        Ok(BearoffDatabase { max_checkers, distributions })
+    }
+}
@@ -0,0 +1,106 @@
+use std::{fs, io, path::Path};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/**
+ * A source of dice rolls for Backgammon.
+ *
+ * Backgammon::apply_move and Backgammon::skip_turn roll the dice for the next player internally,
+ * LearnableGame::apply_move_with and LearnableGame::skip_turn_with take a DiceSource so tests and replays can control the next roll.
+ */
+pub trait DiceSource {
+    fn roll(&mut self) -> (u8, u8);
+}
+
+// Rolls using rand::thread_rng, this is what Backgammon::roll_die uses
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadRngDice;
+
+impl DiceSource for ThreadRngDice {
+    fn roll(&mut self) -> (u8, u8) {
+        let mut rng = rand::thread_rng();
+        (rng.gen_range(1..=6), rng.gen_range(1..=6))
+    }
+}
+
+// Rolls using a seeded rng, two sources created with the same seed produce the same rolls
+#[derive(Debug, Clone)]
+pub struct SeededDice {
+    rng: StdRng,
+}
+
+impl SeededDice {
+    pub fn new(seed: u64) -> Self {
+        SeededDice { rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl DiceSource for SeededDice {
+    fn roll(&mut self) -> (u8, u8) {
+        (self.rng.gen_range(1..=6), self.rng.gen_range(1..=6))
+    }
+}
+
+// Returns the given rolls in order, panics when the rolls are exhausted
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptedDice {
+    rolls: Vec<(u8, u8)>,
+    next: usize,
+}
+
+impl ScriptedDice {
+    pub fn new(rolls: Vec<(u8, u8)>) -> Self {
+        for roll in rolls.iter() {
+            assert!(
+                (1..=6).contains(&roll.0) && (1..=6).contains(&roll.1),
+                "invalid roll in scripted dice: {:?}", roll
+            );
+        }
+        ScriptedDice { rolls, next: 0 }
+    }
+
+    /**
+     * Reads the rolls from a file, one roll per line, e.g. "6 5" or "6,5".
+     * Empty lines and lines starting with '#' are skipped.
+     */
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut rolls = vec![];
+        for (line_idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let dice = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse::<u8>())
+                .collect::<Result<Vec<u8>, _>>();
+            match dice.as_deref() {
+                Ok(&[d1, d2]) if (1..=6).contains(&d1) && (1..=6).contains(&d2) => rolls.push((d1, d2)),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid roll on line {}: '{}'", line_idx + 1, line),
+                    ))
+                }
+            }
+        }
+        Ok(ScriptedDice::new(rolls))
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.rolls.len() - self.next
+    }
+}
+
+impl DiceSource for ScriptedDice {
+    fn roll(&mut self) -> (u8, u8) {
+        let roll = *self
+            .rolls
+            .get(self.next)
+            .unwrap_or_else(|| panic!("scripted dice exhausted after {} rolls!", self.rolls.len()));
+        self.next += 1;
+        roll
+    }
+}
@@ -0,0 +1,100 @@
+use std::str::FromStr;
+
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use super::backgammon_logic::{Backgammon, Board};
+use crate::base::LearnableGame;
+
+/*
+Random valid Backgammon positions, used by tests (the proptest strategies of tests/common) and as start positions of self play games.
+    - Contact: checkers anywhere on the board and sometimes on the bar, the players can still hit each other
+    - Race: the checkers of the players have passed each other, nothing is borne off yet
+    - Bearoff: every checker of both players is in its home board, some may be borne off already
+Every position has 15 checkers of each player on the board, the bar or borne off, no point holds checkers of both players,
+neither player has borne off every checker and the player to move has rolled.
+A seeded rng always gives the same positions.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Contact,
+    Race,
+    Bearoff,
+}
+
+impl FromStr for Phase {
+    type Err = String;
+
+    fn from_str(phase: &str) -> Result<Self, Self::Err> {
+        match phase.to_ascii_lowercase().as_str() {
+            "contact" => Ok(Phase::Contact),
+            "race" => Ok(Phase::Race),
+            "bearoff" => Ok(Phase::Bearoff),
+            other => Err(format!("Incorrect phase {}, can be 'contact', 'race' or 'bearoff'", other)),
+        }
+    }
+}
+
+pub const PHASES: [Phase; 3] = [Phase::Contact, Phase::Race, Phase::Bearoff];
+
+const N_CHECKERS: u8 = 15;
+
+// Puts n_checkers checkers of the player on random points of the range that the other player does not hold
+fn place(points: &mut [i8; 24], player: i8, n_checkers: u8, range: std::ops::Range<usize>, rng: &mut impl Rng) {
+    let free = range.filter(|&point| points[point] * player >= 0).collect::<Vec<_>>();
+    for _ in 0..n_checkers {
+        points[*free.choose(rng).unwrap()] += player;
+    }
+}
+
+// Home board of the player, player -1 bears off from point 0 and player 1 from point 23
+fn home(player: i8) -> std::ops::Range<usize> {
+    if player == -1 { 0..6 } else { 18..24 }
+}
+
+fn random_board(phase: Phase, rng: &mut impl Rng) -> Board {
+    let mut points = [0; 24];
+    match phase {
+        Phase::Contact => {
+            // Usually no checkers on the bar, up to two otherwise
+            let mut on_bar = || if rng.gen_bool(0.75) { 0 } else { rng.gen_range(1..=2) };
+            let bar = (on_bar(), on_bar());
+            place(&mut points, -1, N_CHECKERS - bar.0, 0..24, rng);
+            place(&mut points, 1, N_CHECKERS - bar.1, 0..24, rng);
+            (points, bar, (0, 0))
+        },
+        Phase::Race => {
+            // Player -1 is below the split and player 1 above it, each has at least 6 points to spread over
+            let split = rng.gen_range(6..=18);
+            place(&mut points, -1, N_CHECKERS, 0..split, rng);
+            place(&mut points, 1, N_CHECKERS, split..24, rng);
+            (points, (0, 0), (0, 0))
+        },
+        Phase::Bearoff => {
+            let borne_off = (rng.gen_range(0..N_CHECKERS), rng.gen_range(0..N_CHECKERS));
+            place(&mut points, -1, N_CHECKERS - borne_off.0, home(-1), rng);
+            place(&mut points, 1, N_CHECKERS - borne_off.1, home(1), rng);
+            (points, (0, 0), borne_off)
+        },
+    }
+}
+
+/**
+ * A random valid position of the phase with a random player to move and roll
+ */
+pub fn random_position(phase: Phase, rng: &mut impl Rng) -> Backgammon {
+    let board = loop {
+        let board = random_board(phase, rng);
+        // Checkers placed at random almost always meet, but not always
+        let contact = Backgammon::init_with_fields(board, -1, false).has_contact();
+        if contact == (phase == Phase::Contact) {
+            break board;
+        }
+    };
+    let player = *[-1, 1].choose(rng).unwrap();
+    let mut state = Backgammon::init_with_fields(board, player, false);
+    state.set_roll((rng.gen_range(1..=6), rng.gen_range(1..=6)));
+    state
+}
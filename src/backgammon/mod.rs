@@ -1,2 +1,6 @@
 pub mod encoding;
-pub mod backgammon_logic;
\ No newline at end of file
+pub mod backgammon_logic;
+pub mod dice;
+pub mod bearoff;
+pub mod position;
+pub mod generator;
\ No newline at end of file
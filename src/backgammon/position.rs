@@ -0,0 +1,103 @@
+use super::backgammon_logic::Backgammon;
+
+/*
+Positional facts of a Backgammon state, for either player:
+    - pip count: the pips the player has to move to bear off every checker, a checker on the bar needs 25
+    - race: no checker of a player can meet a checker of the other player anymore, contact otherwise
+    - blots and points: the points the player holds with a single checker and with two or more checkers
+    - prime: the longest run of consecutive points the player holds
+    - checkers back: the checkers of the player on the bar or in the home board of the other player
+
+Player -1 moves towards point 0 and player 1 towards point 23.
+*/
+
+impl Backgammon {
+    // Number of checkers of the player on each point, index 0 is the point the player bears off from
+    fn checkers_by_distance(&self, player: i8) -> [u8; 24] {
+        let mut checkers = [0; 24];
+        for (distance, n_checkers) in checkers.iter_mut().enumerate() {
+            let point = if player == -1 { distance } else { 23 - distance };
+            *n_checkers = (self.board.0[point] * player).max(0) as u8;
+        }
+        checkers
+    }
+
+    pub fn checkers_on_bar(&self, player: i8) -> u8 {
+        if player == -1 { self.board.1.0 } else { self.board.1.1 }
+    }
+
+    // Checkers of the player on the board, on the bar and borne off, 15 in every valid position
+    pub fn total_checkers(&self, player: i8) -> u32 {
+        let on_board: u32 = self.checkers_by_distance(player).iter().map(|&n_checkers| n_checkers as u32).sum();
+        let borne_off = if player == -1 { self.board.2.0 } else { self.board.2.1 };
+        on_board + self.checkers_on_bar(player) as u32 + borne_off as u32
+    }
+
+    pub fn pip_count(&self, player: i8) -> u32 {
+        let on_board: u32 = self
+            .checkers_by_distance(player)
+            .iter()
+            .enumerate()
+            .map(|(distance, &n_checkers)| (distance as u32 + 1) * n_checkers as u32)
+            .sum();
+        on_board + 25 * self.checkers_on_bar(player) as u32
+    }
+
+    pub fn is_race(&self) -> bool {
+        if self.checkers_on_bar(-1) > 0 || self.checkers_on_bar(1) > 0 {
+            return false;
+        }
+        let points = self.board.0;
+        // The furthest checker of player -1 has to be past the furthest checker of player 1
+        let furthest_p1 = points.iter().rposition(|&n| n < 0);
+        let furthest_p2 = points.iter().position(|&n| n > 0);
+        match (furthest_p1, furthest_p2) {
+            (Some(p1), Some(p2)) => p1 < p2,
+            _ => true,
+        }
+    }
+
+    pub fn has_contact(&self) -> bool {
+        !self.is_race()
+    }
+
+    pub fn blots(&self, player: i8) -> u8 {
+        self.checkers_by_distance(player).iter().filter(|&&n| n == 1).count() as u8
+    }
+
+    pub fn made_points(&self, player: i8) -> u8 {
+        self.checkers_by_distance(player).iter().filter(|&&n| n >= 2).count() as u8
+    }
+
+    pub fn longest_prime(&self, player: i8) -> u8 {
+        let mut longest = 0;
+        let mut current = 0;
+        for &n_checkers in self.checkers_by_distance(player).iter() {
+            current = if n_checkers >= 2 { current + 1 } else { 0 };
+            longest = longest.max(current);
+        }
+        longest
+    }
+
+    pub fn checkers_back(&self, player: i8) -> u8 {
+        let in_home_of_other: u8 = self.checkers_by_distance(player)[18..].iter().sum();
+        in_home_of_other + self.checkers_on_bar(player)
+    }
+
+    // The facts of both players by name, player -1 first
+    pub fn position_features(&self) -> Vec<(&'static str, f32)> {
+        vec![
+            ("pips_p1", self.pip_count(-1) as f32),
+            ("pips_p2", self.pip_count(1) as f32),
+            ("race", if self.is_race() { 1. } else { 0. }),
+            ("blots_p1", self.blots(-1) as f32),
+            ("blots_p2", self.blots(1) as f32),
+            ("points_p1", self.made_points(-1) as f32),
+            ("points_p2", self.made_points(1) as f32),
+            ("prime_p1", self.longest_prime(-1) as f32),
+            ("prime_p2", self.longest_prime(1) as f32),
+            ("back_p1", self.checkers_back(-1) as f32),
+            ("back_p2", self.checkers_back(1) as f32),
+        ]
+    }
+}
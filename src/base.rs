@@ -1,15 +1,22 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, str::FromStr};
 
 use tch::Tensor;
 
 
+use rand::Rng;
 use serde::{Serialize, de::DeserializeOwned};
 
+use crate::backgammon::{bearoff::BearoffDatabase, dice::DiceSource};
+
 pub trait LearnableGame: Clone + Debug + DeserializeOwned + Serialize + Send + Sync + Copy {
 
     type Move: Clone + Debug + DeserializeOwned + Serialize + Send + Sync + PartialEq;
     const EMPTY_MOVE: Self::Move;
     const IS_DETERMINISTIC: bool;
+    // Part of the game random positions are generated from, e.g. a bear-off in Backgammon, read from the config by name
+    type Phase: FromStr<Err = String> + Copy + Debug + PartialEq + Send + Sync;
+    // The game can generate random positions, see random_position
+    const HAS_RANDOM_POSITIONS: bool = false;
 
     // Parameters used when creating and running models
     // Number of unique encodings in a game
@@ -27,6 +34,12 @@ pub trait LearnableGame: Clone + Debug + DeserializeOwned + Serialize + Send + S
 
     fn name() -> String;
 
+    // A random valid position of the phase to start a self play game from, of any phase if None, rolled if the game has dice.
+    // Always Some if HAS_RANDOM_POSITIONS is set, None for games that can not generate positions
+    fn random_position(_phase: Option<Self::Phase>, _rng: &mut impl Rng) -> Option<Self> {
+        None
+    }
+
     fn get_valid_moves(&self) -> Vec<Self::Move>;
     fn apply_move(&mut self, action: &Self::Move);
     fn roll_die(&mut self) -> (u8, u8) {
@@ -35,7 +48,26 @@ pub trait LearnableGame: Clone + Debug + DeserializeOwned + Serialize + Send + S
         }
         unimplemented!("You should implement roll_die for non-deterministic games!")
     }
+    // Replaces the roll the player to move plays with, deterministic games have no roll to set
+    fn set_roll(&mut self, roll: (u8, u8));
+    // The roll the player to move plays with, None for deterministic games
+    fn get_roll(&self) -> Option<(u8, u8)> {
+        None
+    }
+    // All states that can follow a roll of the dice from the current state, with their probabilities
+    // Deterministic games have a single outcome, the state itself
+    fn chance_outcomes(&self) -> Vec<(Self, f32)> {
+        vec![(*self, 1.0)]
+    }
     fn skip_turn(&mut self);
+    // Same as apply_move and skip_turn, but the roll of the next player is taken from the given dice source.
+    // Deterministic games have no roll and ignore the dice
+    fn apply_move_with(&mut self, action: &Self::Move, _dice: &mut impl DiceSource) {
+        self.apply_move(action)
+    }
+    fn skip_turn_with(&mut self, _dice: &mut impl DiceSource) {
+        self.skip_turn()
+    }
     fn get_player(&self) -> i8;
 
     fn check_winner(&self) -> Option<i8>;
@@ -44,8 +76,38 @@ pub trait LearnableGame: Clone + Debug + DeserializeOwned + Serialize + Send + S
     fn decode(&self, action: u32) -> Self::Move;
     fn encode(&self, action: &Self::Move) -> u32;
 
+    // Named facts about the position shown by the analysis tools, e.g. pip counts
+    fn features(&self) -> Vec<(&'static str, f32)> {
+        vec![]
+    }
+
+    // Value of the position for the player to move if it is known without searching,
+    // e.g. from the bear-off database if one is given (see MctsConfig::bearoff and RolloutConfig::bearoff)
+    fn exact_value(&self, _bearoff: Option<&BearoffDatabase>) -> Option<f32> {
+        None
+    }
+
+    // Identifies the position for caching evaluations, states with the same key must evaluate the same
+    fn position_key(&self) -> u64;
+
+    // Short text form of the position without the roll, used to pass positions on the command line
+    fn position_id(&self) -> String;
+    fn from_position_id(id: &str) -> Option<Self>;
+
     fn get_id(&self) -> usize;
     fn set_id(&mut self, new_id: usize);
 
     fn to_pretty_str(&self) -> String;
 }
+
+// Phase of the games without phases, no name parses to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoPhase {}
+
+impl FromStr for NoPhase {
+    type Err = String;
+
+    fn from_str(phase: &str) -> Result<Self, Self::Err> {
+        Err(format!("Incorrect phase {}, the game has no phases", phase))
+    }
+}
@@ -0,0 +1,82 @@
+use std::{fmt, io, path::PathBuf};
+
+use config::ConfigError;
+use tch::TchError;
+
+/*
+Errors of the library APIs that read or write files, load configs and models or replay recorded games.
+Bugs in the engine itself (e.g. a corrupt search tree) still panic.
+*/
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // A file or directory that has to exist does not
+    NotFound(PathBuf),
+    Config(ConfigError),
+    // A config key that is unknown or out of its range
+    InvalidConfig { key: String, message: String },
+    // A model that could not be loaded, e.g. a missing file or a model of another game
+    Model { path: PathBuf, source: TchError },
+    // Any other tensor failure, e.g. reading or writing training data
+    Tensor(TchError),
+    // A game record or position that is not valid JSON for the game
+    Json(serde_json::Error),
+    // A move that is not valid in the position it was played in
+    IllegalMove { position_id: String, action: String },
+    // A malformed position id, roll, agent or other argument
+    InvalidInput(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::NotFound(path) => write!(f, "path {} does not exist", path.display()),
+            Error::Config(e) => write!(f, "invalid config: {}", e),
+            Error::InvalidConfig { key, message } => write!(f, "invalid config key {}: {}", key, message),
+            Error::Model { path, source } => write!(f, "unable to load model {}: {}", path.display(), source),
+            Error::Tensor(e) => write!(f, "tensor error: {}", e),
+            Error::Json(e) => write!(f, "invalid JSON: {}", e),
+            Error::IllegalMove { position_id, action } => write!(f, "illegal move {} in position {}", action, position_id),
+            Error::InvalidInput(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Config(e) => Some(e),
+            Error::Model { source, .. } => Some(source),
+            Error::Tensor(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::NotFound(_) | Error::InvalidConfig { .. } | Error::IllegalMove { .. } | Error::InvalidInput(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<TchError> for Error {
+    fn from(e: TchError) -> Self {
+        Error::Tensor(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
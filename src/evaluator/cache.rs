@@ -0,0 +1,208 @@
+use std::{collections::{BTreeMap, HashMap}, fmt, sync::Mutex};
+
+use itertools::Itertools;
+use tch::Tensor;
+
+use crate::base::LearnableGame;
+
+use super::{tensor_to_vec, Evaluator};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub entries: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "hits: {}, misses: {}, hit rate: {:.2}%, entries: {}",
+            self.hits, self.misses, self.hit_rate() * 100., self.entries
+        )
+    }
+}
+
+// Policy of the valid moves of a position as (encoded move, probability) and its value
+type Evaluation = (Vec<(u32, f32)>, f32);
+
+/*
+    Least recently used cache of evaluations
+        - entries: position key -> (evaluation, tick of its last use)
+        - recency: tick of the last use -> position key, ordered from the least to the most recently used
+    A full cache evicts the position that was not used the longest, so positions the search keeps coming back to stay
+*/
+struct Cache {
+    entries: HashMap<u64, (Evaluation, u64)>,
+    recency: BTreeMap<u64, u64>,
+    tick: u64,
+    hits: usize,
+    misses: usize,
+}
+
+impl Cache {
+    fn new() -> Self {
+        Cache { entries: HashMap::new(), recency: BTreeMap::new(), tick: 0, hits: 0, misses: 0 }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    // The evaluation of the position, marked as the most recently used
+    fn get(&mut self, key: u64) -> Option<Evaluation> {
+        let tick = self.next_tick();
+        let (evaluation, last_used) = self.entries.get_mut(&key)?;
+        self.recency.remove(last_used);
+        self.recency.insert(tick, key);
+        *last_used = tick;
+        Some(evaluation.clone())
+    }
+
+    // Adds the evaluation of the position, evicting the least recently used positions to stay within capacity
+    fn insert(&mut self, key: u64, evaluation: Evaluation, capacity: usize) {
+        let tick = self.next_tick();
+        if let Some((_, last_used)) = self.entries.insert(key, (evaluation, tick)) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(tick, key);
+        while self.entries.len() > capacity {
+            match self.recency.pop_first() {
+                Some((_, evicted)) => self.entries.remove(&evicted),
+                None => break,
+            };
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+/**
+ * Wraps an evaluator and caches its results by LearnableGame::position_key,
+ * only the positions that are not in the cache are passed to the inner evaluator.
+ *
+ * The policy is only kept for the valid moves of a position, every other action is returned as 0.
+ * When the cache is full the least recently used positions are evicted. Results are returned on the CPU.
+ * The cache is not locked while the inner evaluator runs, so other threads can use it in the meantime.
+ */
+pub struct CachingEvaluator<E> {
+    inner: E,
+    capacity: usize,
+    cache: Mutex<Cache>,
+}
+
+impl<E> CachingEvaluator<E> {
+    pub fn new(inner: E) -> Self {
+        Self::with_capacity(inner, usize::MAX)
+    }
+
+    pub fn with_capacity(inner: E, capacity: usize) -> Self {
+        CachingEvaluator {
+            inner,
+            capacity,
+            cache: Mutex::new(Cache::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.cache.lock().unwrap();
+        CacheStats { hits: cache.hits, misses: cache.misses, entries: cache.entries.len() }
+    }
+
+    pub fn clear(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.clear();
+        cache.hits = 0;
+        cache.misses = 0;
+    }
+}
+
+impl<T: LearnableGame, E: Evaluator<T>> Evaluator<T> for CachingEvaluator<E> {
+    fn evaluate(&self, states: &[T]) -> (Tensor, Tensor) {
+        let keys = states.iter().map(|state| state.position_key()).collect_vec();
+
+        // Hits are copied out of the cache, so an eviction by another thread cannot drop them.
+        // Each missing position is evaluated once, even if it is repeated in the batch
+        let mut results: HashMap<u64, Evaluation> = HashMap::with_capacity(states.len());
+        let miss_idxs = {
+            let mut cache = self.cache.lock().unwrap();
+            let mut miss_idxs = vec![];
+            for (idx, &key) in keys.iter().enumerate().unique_by(|(_, key)| **key) {
+                match cache.get(key) {
+                    Some(evaluation) => {
+                        results.insert(key, evaluation);
+                    }
+                    None => miss_idxs.push(idx),
+                }
+            }
+            cache.misses += miss_idxs.len();
+            cache.hits += states.len() - miss_idxs.len();
+            miss_idxs
+        };
+
+        if !miss_idxs.is_empty() {
+            let miss_states = miss_idxs.iter().map(|&idx| states[idx]).collect_vec();
+            let (policy, value) = self.inner.evaluate(&miss_states);
+            let policy_vec = tensor_to_vec(&policy);
+            let value_vec = tensor_to_vec(&value);
+            let policies = policy_vec.chunks(T::ACTION_SPACE_SIZE as usize);
+            let mut cache = self.cache.lock().unwrap();
+            for ((state, policy), value) in miss_states.iter().zip(policies).zip(value_vec) {
+                let move_probs = state
+                    .get_valid_moves()
+                    .iter()
+                    .map(|valid_move| {
+                        let encoded = state.encode(valid_move);
+                        (encoded, policy[encoded as usize])
+                    })
+                    .collect_vec();
+                cache.insert(state.position_key(), (move_probs.clone(), value), self.capacity);
+                results.insert(state.position_key(), (move_probs, value));
+            }
+        }
+
+        let mut policies: Vec<f32> = vec![0.; states.len() * T::ACTION_SPACE_SIZE as usize];
+        let mut values: Vec<f32> = Vec::with_capacity(states.len());
+        for (processed_idx, key) in keys.iter().enumerate() {
+            let (move_probs, value) = &results[key];
+            let offset = processed_idx * T::ACTION_SPACE_SIZE as usize;
+            for &(encoded, prob) in move_probs.iter() {
+                policies[offset + encoded as usize] = prob;
+            }
+            values.push(*value);
+        }
+
+        let n_states = states.len() as i64;
+        (
+            Tensor::from_slice(&policies).view([n_states, T::ACTION_SPACE_SIZE]),
+            Tensor::from_slice(&values).view([n_states, 1]),
+        )
+    }
+}
@@ -0,0 +1,55 @@
+use tch::Tensor;
+
+use crate::{backgammon::backgammon_logic::Backgammon, base::LearnableGame, constants::DEFAULT_TYPE};
+
+use super::{uniform::UniformEvaluator, Evaluator};
+
+// Expected pips of a single roll, doubles count twice
+const AVERAGE_ROLL_PIPS: f32 = 8.17;
+
+/**
+ * Handcrafted Backgammon evaluator, values a state by the race between the players.
+ * The player to move gets the pips of its roll as a bonus, or of an average roll if it has not rolled yet,
+ * and the pip difference is squashed by tanh. Policy is uniform.
+ */
+#[derive(Debug, Clone)]
+pub struct BackgammonHeuristic {
+    // Pip difference that maps to a value of tanh(1) ~ 0.76
+    pub pip_scale: f32,
+}
+
+impl Default for BackgammonHeuristic {
+    fn default() -> Self {
+        BackgammonHeuristic { pip_scale: 30. }
+    }
+}
+
+impl BackgammonHeuristic {
+    pub fn evaluate_state(&self, state: &Backgammon) -> f32 {
+        let player = state.get_player();
+        if let Some(winner) = state.check_winner() {
+            return if winner == player { 1. } else { -1. };
+        }
+        let roll_pips = match state.roll {
+            (0, 0) => AVERAGE_ROLL_PIPS,
+            (die, other_die) if die == other_die && !state.is_second_play => 4. * die as f32,
+            (die, other_die) => (die + other_die) as f32,
+        };
+        let lead = state.pip_count(-player) as f32 - state.pip_count(player) as f32 + roll_pips;
+        (lead / self.pip_scale).tanh()
+    }
+}
+
+impl Evaluator<Backgammon> for BackgammonHeuristic {
+    fn evaluate(&self, states: &[Backgammon]) -> (Tensor, Tensor) {
+        let (policy, _) = UniformEvaluator.evaluate(states);
+        (policy, self.value(states))
+    }
+
+    fn value(&self, states: &[Backgammon]) -> Tensor {
+        let values: Vec<f32> = states.iter().map(|state| self.evaluate_state(state)).collect();
+        Tensor::from_slice(&values)
+            .to_kind(DEFAULT_TYPE)
+            .view([states.len() as i64, 1])
+    }
+}
@@ -0,0 +1,78 @@
+use itertools::Itertools;
+use tch::Tensor;
+
+use crate::{alphazero::nnet::ResNet, base::LearnableGame, constants::{DEFAULT_TYPE, DEVICE}};
+
+pub mod cache;
+pub mod heuristic;
+pub mod rollout;
+pub mod uniform;
+
+/**
+ * Anything that can give a policy and a value for a batch of game states, used by the searches.
+ *
+ * evaluate returns (policy, value):
+ * the policy has the size [N, ACTION_SPACE_SIZE] and sums to 1 for each state, it does not have to be masked to valid moves.
+ * The value has the size [N, 1], in range [-1, 1] from the perspective of the player to move in each state.
+ * The tensors can be on any device.
+ */
+pub trait Evaluator<T: LearnableGame> {
+    fn evaluate(&self, states: &[T]) -> (Tensor, Tensor);
+
+    fn policy(&self, states: &[T]) -> Tensor {
+        self.evaluate(states).0
+    }
+
+    fn value(&self, states: &[T]) -> Tensor {
+        self.evaluate(states).1
+    }
+}
+
+impl<T: LearnableGame, E: Evaluator<T>> Evaluator<T> for &E {
+    fn evaluate(&self, states: &[T]) -> (Tensor, Tensor) {
+        (**self).evaluate(states)
+    }
+
+    fn policy(&self, states: &[T]) -> Tensor {
+        (**self).policy(states)
+    }
+
+    fn value(&self, states: &[T]) -> Tensor {
+        (**self).value(states)
+    }
+}
+
+// Stacks the states into a single tensor of size [N, N_INPUT_CHANNELS, ...] on DEVICE
+pub fn states_to_tensor<T: LearnableGame>(states: &[T]) -> Tensor {
+    let states_vec = states.iter().map(|state| state.as_tensor()).collect_vec();
+    Tensor::stack(&states_vec, 0).squeeze_dim(1).to_device(*DEVICE)
+}
+
+impl<T: LearnableGame> Evaluator<T> for ResNet {
+    fn evaluate(&self, states: &[T]) -> (Tensor, Tensor) {
+        let _guard = tch::no_grad_guard();
+        self.forward_t(&states_to_tensor(states), false)
+    }
+
+    fn policy(&self, states: &[T]) -> Tensor {
+        let _guard = tch::no_grad_guard();
+        self.forward_policy(&states_to_tensor(states), false)
+    }
+
+    fn value(&self, states: &[T]) -> Tensor {
+        let _guard = tch::no_grad_guard();
+        self.forward_value(&states_to_tensor(states), false)
+    }
+}
+
+// Copies the tensor into a flat Vec<f32> on the CPU
+pub fn tensor_to_vec(tensor: &Tensor) -> Vec<f32> {
+    let tensor = tensor
+        .to_device(tch::Device::Cpu)
+        .to_kind(DEFAULT_TYPE)
+        .contiguous();
+    let numel = tensor.numel();
+    let mut values = vec![0.; numel];
+    tensor.copy_data(&mut values, numel);
+    values
+}
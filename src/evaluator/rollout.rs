@@ -0,0 +1,62 @@
+use rand::seq::SliceRandom;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use tch::Tensor;
+
+use crate::{base::LearnableGame, constants::DEFAULT_TYPE};
+
+use super::{uniform::UniformEvaluator, Evaluator};
+
+/**
+ * Values states by the average outcome of random playouts, policy is uniform.
+ * Playouts that hit round_limit count as draws.
+ */
+#[derive(Debug, Clone)]
+pub struct RolloutEvaluator {
+    pub n_rollouts: usize,
+    pub round_limit: usize,
+}
+
+impl RolloutEvaluator {
+    pub fn new(n_rollouts: usize, round_limit: usize) -> Self {
+        RolloutEvaluator { n_rollouts, round_limit }
+    }
+
+    fn rollout<T: LearnableGame>(&self, state: &T) -> f32 {
+        let mut rng = rand::thread_rng();
+        let player = state.get_player();
+        let mut curr_state = *state;
+
+        for _ in 0..self.round_limit {
+            if let Some(winner) = curr_state.check_winner() {
+                return if winner == player {1.}
+                else if winner == -player {-1.}
+                else {0.};
+            }
+            match curr_state.get_valid_moves().choose(&mut rng) {
+                Some(move_to_play) => curr_state.apply_move(move_to_play),
+                None => curr_state.skip_turn(),
+            }
+        }
+        0.
+    }
+}
+
+impl<T: LearnableGame> Evaluator<T> for RolloutEvaluator {
+    fn evaluate(&self, states: &[T]) -> (Tensor, Tensor) {
+        let (policy, _) = UniformEvaluator.evaluate(states);
+        (policy, self.value(states))
+    }
+
+    fn value(&self, states: &[T]) -> Tensor {
+        let values: Vec<f32> = states
+            .par_iter()
+            .map(|state| {
+                let total: f32 = (0..self.n_rollouts).map(|_| self.rollout(state)).sum();
+                total / self.n_rollouts as f32
+            })
+            .collect();
+        Tensor::from_slice(&values)
+            .to_kind(DEFAULT_TYPE)
+            .view([states.len() as i64, 1])
+    }
+}
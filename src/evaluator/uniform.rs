@@ -0,0 +1,22 @@
+use tch::Tensor;
+
+use crate::{base::LearnableGame, constants::DEFAULT_TYPE};
+
+use super::Evaluator;
+
+// Uniform prior over all actions and a value of 0 for every state, useful as a test double
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UniformEvaluator;
+
+impl<T: LearnableGame> Evaluator<T> for UniformEvaluator {
+    fn evaluate(&self, states: &[T]) -> (Tensor, Tensor) {
+        let n_states = states.len() as i64;
+        let policy = Tensor::full(
+            [n_states, T::ACTION_SPACE_SIZE],
+            1.0 / T::ACTION_SPACE_SIZE as f64,
+            (DEFAULT_TYPE, tch::Device::Cpu),
+        );
+        let value = Tensor::zeros([n_states, 1], (DEFAULT_TYPE, tch::Device::Cpu));
+        (policy, value)
+    }
+}
@@ -0,0 +1,174 @@
+use std::cmp::Ordering;
+
+use config::{Config, ConfigError};
+use itertools::Itertools;
+
+use crate::{
+    alphazero::net_agents::{batch_move_values, evaluate_states},
+    base::LearnableGame,
+    evaluator::Evaluator,
+};
+
+/*
+Depth limited expectiminimax search with an evaluator (e.g. ResNet) over the valid moves and the dice outcomes.
+
+    - depth 1 evaluates every move with the value head, averaged over the rolls of the opponent (same as Agent::Value)
+    - depth n averages over every roll of the opponent, the opponent picks its best reply searched to depth n - 1
+    - the second play of a double is a continuation of the same turn and does not use up depth
+
+Before searching deeper, the moves of a position are ranked by their depth 1 value and pruned with a MoveFilter.
+All positions of a level are fed into the network in batches.
+*/
+
+#[derive(Debug, Clone)]
+pub struct MoveFilter {
+    // Number of best moves that are always kept
+    pub accept: usize,
+    // Max number of extra moves kept if their value is within threshold of the best move
+    pub extra: usize,
+    pub threshold: f32,
+}
+
+impl MoveFilter {
+    pub fn apply<M>(&self, mut move_values: Vec<(M, f32)>) -> Vec<(M, f32)> {
+        move_values.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        let best_value = match move_values.first() {
+            Some((_, value)) => *value,
+            None => return move_values,
+        };
+        let n_extra = move_values
+            .iter()
+            .skip(self.accept)
+            .take(self.extra)
+            .take_while(|(_, value)| best_value - value <= self.threshold)
+            .count();
+        move_values.truncate(self.accept + n_extra);
+        move_values
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpectimaxConfig {
+    pub depth: usize,
+    pub move_filter: MoveFilter,
+}
+
+impl Default for ExpectimaxConfig {
+    fn default() -> Self {
+        ExpectimaxConfig {
+            depth: 2,
+            move_filter: MoveFilter { accept: 4, extra: 4, threshold: 0.16 },
+        }
+    }
+}
+
+impl ExpectimaxConfig {
+    pub fn from_config(conf: &Config) -> Result<Self, ConfigError> {
+        Ok(ExpectimaxConfig {
+            depth: conf.get_int("expectimax_depth")? as usize,
+            move_filter: MoveFilter {
+                accept: conf.get_int("move_filter_accept")? as usize,
+                extra: conf.get_int("move_filter_extra")? as usize,
+                threshold: conf.get_float("move_filter_threshold")? as f32,
+            },
+        })
+    }
+}
+
+fn best_value<M>(move_values: &[(M, f32)]) -> Option<f32> {
+    move_values
+        .iter()
+        .map(|(_, value)| *value)
+        .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+}
+
+// Values of the states for the player to move in each state, states without valid moves are evaluated directly
+fn state_values<T: LearnableGame>(evaluator: &impl Evaluator<T>, states: &[T], depth: usize, move_filter: &MoveFilter) -> Vec<f32> {
+    if depth == 0 {
+        return evaluate_states(evaluator, states);
+    }
+    let searched = search_batch(evaluator, states, depth, move_filter);
+    let passing_states = states
+        .iter()
+        .zip(searched.iter())
+        .filter(|(_, move_values)| move_values.is_empty())
+        .map(|(state, _)| *state)
+        .collect_vec();
+    let mut passing_values = evaluate_states(evaluator, &passing_states).into_iter();
+
+    searched
+        .iter()
+        .map(|move_values| match best_value(move_values) {
+            Some(value) => value,
+            None => passing_values.next().unwrap(),
+        })
+        .collect_vec()
+}
+
+fn search_batch<T: LearnableGame>(evaluator: &impl Evaluator<T>, states: &[T], depth: usize, move_filter: &MoveFilter) -> Vec<Vec<(T::Move, f32)>> {
+    let shallow = batch_move_values(evaluator, states);
+    if depth <= 1 {
+        return shallow;
+    }
+    let mut results = shallow.into_iter().map(|move_values| move_filter.apply(move_values)).collect_vec();
+
+    // Second plays of doubles, searched to the same depth
+    let mut continuations: Vec<T> = vec![];
+    let mut continuation_refs: Vec<(usize, usize)> = vec![];
+    // Opponent states after each roll, searched to depth - 1
+    let mut replies: Vec<T> = vec![];
+    let mut reply_refs: Vec<(usize, usize, f32)> = vec![];
+
+    for (state_idx, (state, move_values)) in states.iter().zip(results.iter_mut()).enumerate() {
+        let player = state.get_player();
+        for (move_idx, (candidate, value)) in move_values.iter_mut().enumerate() {
+            let mut next_state = *state;
+            next_state.apply_move(candidate);
+            if next_state.check_winner().is_some() {
+                // Depth 1 value of a winning move is already exact
+                continue;
+            }
+            *value = 0.;
+            if next_state.get_player() == player {
+                continuations.push(next_state);
+                continuation_refs.push((state_idx, move_idx));
+            } else {
+                for (outcome, prob) in next_state.chance_outcomes() {
+                    replies.push(outcome);
+                    reply_refs.push((state_idx, move_idx, -prob));
+                }
+            }
+        }
+    }
+
+    let continuation_values = state_values(evaluator, &continuations, depth, move_filter);
+    for (&(state_idx, move_idx), value) in continuation_refs.iter().zip(continuation_values) {
+        results[state_idx][move_idx].1 = value;
+    }
+    let reply_values = state_values(evaluator, &replies, depth - 1, move_filter);
+    for (&(state_idx, move_idx, weight), value) in reply_refs.iter().zip(reply_values) {
+        results[state_idx][move_idx].1 += weight * value;
+    }
+    results
+}
+
+/**
+ * Returns the moves of each state that survived the move filter, with their values searched to config.depth.
+ * Values are from the perspective of the player to move in each state.
+ */
+pub fn expectimax_search<T: LearnableGame>(evaluator: &impl Evaluator<T>, states: &[T], config: &ExpectimaxConfig) -> Vec<Vec<(T::Move, f32)>> {
+    search_batch(evaluator, states, config.depth, &config.move_filter)
+}
+
+pub fn expectimax_actions<T: LearnableGame>(evaluator: &impl Evaluator<T>, states: &[T], config: &ExpectimaxConfig) -> Vec<T::Move> {
+    expectimax_search(evaluator, states, config)
+        .into_iter()
+        .map(|move_values| {
+            move_values
+                .into_iter()
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+                .map(|(best_move, _)| best_move)
+                .unwrap_or(T::EMPTY_MOVE)
+        })
+        .collect_vec()
+}
@@ -1,14 +1,32 @@
+use std::sync::Arc;
+
+use backgammon::bearoff::BearoffDatabase;
+use base::LearnableGame;
 use config::{Config, ConfigError};
+use expectimax::ExpectimaxConfig;
+use mcts::{gumbel::GumbelConfig, move_selection::MoveSelection, puct::PuctConfig};
+use move_guard::MoveGuardConfig;
 
 #[macro_use]
 extern crate lazy_static;
 
 pub mod alphazero;
+pub mod analysis;
+pub mod annotation;
 pub mod backgammon;
+pub mod evaluator;
+pub mod expectimax;
 pub mod mcts;
+pub mod move_guard;
+pub mod rollout;
+pub mod selfcheck;
+pub mod settings;
 pub mod versus;
 pub mod base;
 pub mod tictactoe;
+pub mod error;
+
+pub use error::Error;
 
 /*
 
@@ -36,7 +54,24 @@ pub struct MctsConfig {
     c: f32,
     simulate_round_limit: usize,
     dirichlet_alpha: f32,
-    dirichlet_epsilon: f32
+    dirichlet_epsilon: f32,
+    dirichlet_alpha_total: f32,
+    eval_cache_size: usize,
+    tree_reuse: bool,
+    leaf_batch_size: usize,
+    virtual_loss: f32,
+    time_limit_ms: u64,
+    max_nodes: usize,
+    early_stop: bool,
+    move_selection: MoveSelection,
+    self_play_move_selection: MoveSelection,
+    puct: PuctConfig,
+    gumbel: Option<GumbelConfig>,
+    expectimax: ExpectimaxConfig,
+    search_stats_top_k: usize,
+    move_guard: MoveGuardConfig,
+    // Database the leaves in bear-off races are valued with instead of the evaluator, loaded from bearoff_db_path by the caller
+    pub bearoff: Option<Arc<BearoffDatabase>>,
 }
 
 impl MctsConfig {
@@ -47,6 +82,39 @@ impl MctsConfig {
             simulate_round_limit: conf.get_int("simulate_round_limit")? as usize,
             dirichlet_alpha: conf.get_float("dirichlet_alpha")? as f32,
             dirichlet_epsilon: conf.get_float("dirichlet_epsilon")? as f32,
+            dirichlet_alpha_total: conf.get_float("dirichlet_alpha_total")? as f32,
+            eval_cache_size: conf.get_int("eval_cache_size")? as usize,
+            tree_reuse: conf.get_bool("tree_reuse")?,
+            leaf_batch_size: conf.get_int("leaf_batch_size")? as usize,
+            virtual_loss: conf.get_float("virtual_loss")? as f32,
+            time_limit_ms: conf.get_int("time_limit_ms")? as u64,
+            max_nodes: conf.get_int("max_nodes")? as usize,
+            early_stop: conf.get_bool("early_stop")?,
+            move_selection: MoveSelection::from_config(conf, "move_selection")?,
+            self_play_move_selection: MoveSelection::from_config(conf, "self_play_move_selection")?,
+            puct: PuctConfig::from_config(conf)?,
+            gumbel: GumbelConfig::from_config(conf)?,
+            expectimax: ExpectimaxConfig::from_config(conf)?,
+            search_stats_top_k: conf.get_int("search_stats_top_k")? as usize,
+            move_guard: MoveGuardConfig::from_config(conf)?,
+            bearoff: None,
         })
     }
+
+    /**
+     * Dirichlet alpha of a root with n_moves legal moves,
+     * dirichlet_alpha_total is spread over the moves if it is set, dirichlet_alpha is used otherwise
+     */
+    // Value of the state for its player to move if it is known without evaluating (see LearnableGame::exact_value)
+    pub fn exact_value<T: LearnableGame>(&self, state: &T) -> Option<f32> {
+        state.exact_value(self.bearoff.as_deref())
+    }
+
+    pub fn dirichlet_alpha_for(&self, n_moves: usize) -> f32 {
+        if self.dirichlet_alpha_total > 0. && n_moves > 0 {
+            self.dirichlet_alpha_total / n_moves as f32
+        } else {
+            self.dirichlet_alpha
+        }
+    }
 }
@@ -1,11 +1,11 @@
-use core::panic;
 use std::{
-    path::{Path, PathBuf}, fs, io,
+    path::{Path, PathBuf}, fs, io, process, sync::Arc,
 };
 
 use config::Config;
 use die_e::{
-    alphazero::{alphazero::{AlphaZero, MemoryFragment}, nnet::ResNet}, MctsConfig, versus::{Agent, Player, play, save_game, print_game, PlayResult}, backgammon::backgammon_logic::Backgammon, tictactoe::TicTacToe, base::LearnableGame
+    alphazero::{alphazero::{AlphaZero, MemoryFragment}, nnet::ResNet}, analysis::{analyze, parse_position, parse_roll, AnalysisSearch}, annotation::{annotate_game, save_annotated_game, ErrorThresholds, PlayerStats}, MctsConfig, versus::{Agent, Player, play, save_game, print_game, load_game, load_all_games, PlayResult}, backgammon::{backgammon_logic::Backgammon, bearoff::{self, BearoffDatabase}}, tictactoe::TicTacToe, base::LearnableGame,
+    evaluator::uniform::UniformEvaluator, rollout::{rollout, RolloutConfig}, selfcheck::self_check, settings::{load_config, parse_override}, Error
 };
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 
@@ -18,6 +18,10 @@ struct Args {
     #[arg(short, long, value_name = "FILE")]
     config: Option<PathBuf>,
 
+    // overrides a key of the config, e.g. --set learn_iterations=10, can be given multiple times
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
     #[clap(short, long, value_enum)]
     game: LearnableGames,
 
@@ -25,6 +29,18 @@ struct Args {
     #[arg(short, long)]
     n_cpus: Option<usize>,
 
+    // time limit of each search in milliseconds, overrides time_limit_ms in the config
+    #[arg(long)]
+    time_limit_ms: Option<u64>,
+
+    // max number of nodes in the search tree of each state, overrides max_nodes in the config
+    #[arg(long)]
+    max_nodes: Option<u64>,
+
+    // stop searching once the best move can no longer change, overrides early_stop in the config
+    #[arg(long)]
+    early_stop: Option<bool>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -38,16 +54,16 @@ enum Commands {
         model_path: Option<PathBuf>
     },
     Play {
-        // Agent one's type, can be 'random', 'mcts', 'model'
+        // Agent one's type, can be 'random', 'mcts', 'model', 'policy', 'value', 'expectimax'
         #[arg(short, long)]
         agent_one: Option<String>,
-        // Path of model to play if agent one is of type 'model'
+        // Path of model to play if agent one is of type 'model', 'policy', 'value' or 'expectimax'
         #[arg(short, long)]
         model_path_one: Option<PathBuf>,
-        // Agent two's type, can be 'random', 'mcts', 'model'
+        // Agent two's type, can be 'random', 'mcts', 'model', 'policy', 'value', 'expectimax'
         #[arg(long)]
         agent_two: Option<String>,
-        // Path of model to play if agent two is of type 'model'
+        // Path of model to play if agent two is of type 'model', 'policy', 'value' or 'expectimax'
         #[arg(long)]
         model_path_two: Option<PathBuf>,
         // Path to output game after playing
@@ -75,6 +91,84 @@ enum Commands {
         // path of the game to load
         #[arg(short, long)]
         game_path: PathBuf
+    },
+    // Ranks every valid move of a position
+    Analyze {
+        // Path of the model to analyze with
+        #[arg(short, long)]
+        model_path: PathBuf,
+        // The position as a path to a JSON file, inline JSON or a position id
+        #[arg(short, long, allow_hyphen_values = true)]
+        position: String,
+        // The roll to play, e.g. 6-4, replaces the roll of the position, required for games with dice if the position has no roll
+        #[arg(short, long)]
+        roll: Option<String>,
+        // Search used to rank the moves, can be 'mcts', 'expectimax', 'value'
+        #[arg(short, long, default_value = "mcts")]
+        search: String,
+        // Print the analysis as JSON instead of a table
+        #[arg(short, long)]
+        json: bool,
+    },
+    // Analyzes every turn of saved games and classifies the mistakes of the players
+    Annotate {
+        // Path of the model to analyze with
+        #[arg(short, long)]
+        model_path: PathBuf,
+        // Path of a saved game or a directory of saved games
+        #[arg(short, long)]
+        game_path: PathBuf,
+        // Directory to save the annotated games, defaults to an annotated directory next to the games
+        #[arg(short, long)]
+        output_path: Option<PathBuf>,
+        // Search used to find the best moves, can be 'mcts', 'expectimax', 'value'
+        #[arg(short, long, default_value = "mcts")]
+        search: String,
+    },
+    // Estimates the equity of a position by playing it out many times
+    Rollout {
+        // Path of the model of the agent, only the random agent can play without a model
+        #[arg(short, long)]
+        model_path: Option<PathBuf>,
+        // The position as a path to a JSON file, inline JSON or a position id
+        #[arg(short, long, allow_hyphen_values = true)]
+        position: String,
+        // The roll to play, e.g. 6-4, the position is rolled out before rolling if not given
+        #[arg(short, long)]
+        roll: Option<String>,
+        // Agent playing the games, can be 'random', 'policy', 'value'
+        #[arg(short, long, default_value = "value")]
+        agent: String,
+        // Number of games to play, overrides rollout_games in the config
+        #[arg(long)]
+        games: Option<usize>,
+        // Seed of the dice, overrides rollout_seed in the config
+        #[arg(long)]
+        seed: Option<u64>,
+        // Print the result as JSON
+        #[arg(short, long)]
+        json: bool,
+    },
+    // Checks that every valid move of random games encodes to a unique index and decodes back to itself
+    Selfcheck {
+        // Number of random games to check
+        #[arg(long, default_value_t = 100)]
+        games: usize,
+        // Seed of the dice and moves of the games
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        // Print the report as JSON
+        #[arg(short, long)]
+        json: bool,
+    },
+    // Generates the backgammon bear-off database
+    Bearoff {
+        // Path to save the database
+        #[arg(short, long)]
+        out_path: PathBuf,
+        // Max number of checkers of the positions in the database
+        #[arg(short, long, default_value_t = bearoff::MAX_CHECKERS)]
+        max_checkers: u8,
     }
 }
 #[derive(ValueEnum, Debug, Clone)]
@@ -83,25 +177,38 @@ enum LearnableGames {
 }
 
 fn main() {
-    let args = Args::parse();
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
 
-    // Load config
-    let config_path = args.config.unwrap_or(
-        PathBuf::from("./config")
-    );
-    let builder = Config::builder()
-        .add_source(config::File::new(config_path.to_str().unwrap(), config::FileFormat::Toml));
+fn run() -> Result<(), Error> {
+    let args = Args::parse();
 
-    let config = match builder.build() {
-        Ok(config) => config,
-        Err(e) => panic!("Unable to build config, caught error {}", e),
+    // Load config, ./config is optional as every key has a default
+    let (config_path, required) = match args.config {
+        Some(path) => (path, true),
+        None => (PathBuf::from("./config"), false),
     };
+    let mut overrides = args.overrides.iter().map(|arg| parse_override(arg)).collect::<Result<Vec<_>, Error>>()?;
+    overrides.extend(
+        [
+            ("time_limit_ms", args.time_limit_ms.map(|v| v.to_string())),
+            ("max_nodes", args.max_nodes.map(|v| v.to_string())),
+            ("early_stop", args.early_stop.map(|v| v.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key.to_string(), value))),
+    );
+    let config = load_config(&config_path, required, &overrides)?;
 
     let n_cpus_in_device = num_cpus::get();
     let n_cpus = match args.n_cpus {
-        Some(n) => if n > n_cpus_in_device {
-            panic!("Value provided in n_cpus flag ({}) is larger than total cpus in the device ({})!", n, n_cpus_in_device)
-        } else {n},
+        Some(n) if n > n_cpus_in_device => return Err(Error::InvalidInput(format!(
+            "Value provided in n_cpus flag ({}) is larger than total cpus in the device ({})!", n, n_cpus_in_device
+        ))),
+        Some(n) => n,
         None => n_cpus_in_device / 2,
     };
     rayon::ThreadPoolBuilder::new()
@@ -111,62 +218,66 @@ fn main() {
     println!("Number of CPU's to use {}", n_cpus);
 
     match args.game {
-        LearnableGames::Backgammon => handle_command::<Backgammon>(args.command, &config),
-        LearnableGames::TicTacToe => handle_command::<TicTacToe>(args.command, &config)
+        LearnableGames::Backgammon => {
+            let bearoff = match args.command {
+                Commands::Bearoff { .. } => None,
+                _ => load_bearoff_database(&config)?,
+            };
+            handle_command::<Backgammon>(args.command, &config, bearoff)
+        },
+        LearnableGames::TicTacToe => handle_command::<TicTacToe>(args.command, &config, None)
     }
 }
 
-fn handle_command<T: LearnableGame>(command: Commands, conf: &Config) {
+// bearoff is the database the searches and rollouts value bear-off races with, see load_bearoff_database
+fn handle_command<T: LearnableGame>(command: Commands, conf: &Config, bearoff: Option<Arc<BearoffDatabase>>) -> Result<(), Error> {
+    // The search settings of the config with the bear-off database
+    let mcts_config = || -> Result<MctsConfig, Error> {
+        let mut mcts_config = MctsConfig::from_config(conf)?;
+        mcts_config.bearoff = bearoff.clone();
+        Ok(mcts_config)
+    };
     match command {
         Commands::Learn { model_path } => {
-            let mut az = AlphaZero::from_config::<T>(model_path, conf);
-            az.learn_parallel::<T>();
+            let mut az = AlphaZero::from_config::<T>(model_path, conf)?;
+            az.mcts_config.bearoff = bearoff.clone();
+            az.learn_parallel::<T>()?;
         },
         Commands::Play { agent_one, model_path_one, agent_two, model_path_two, output_path } => {
             let agent_one_type = match agent_one {
-                Some(agent) => match agent.to_ascii_lowercase().as_str() {
-                    "model" => Agent::Model,
-                    "mcts" => Agent::Mcts,
-                    "random" => Agent::Random,
-                    _ => panic!("Incorrect specification for agent one's type.")
-                }
-                None => panic!("Must define a type for agent one.")
+                Some(agent) => parse_agent(&agent)?,
+                None => return Err(Error::InvalidInput(String::from("Must define a type for agent one."))),
             };
-
             let agent_two_type = match agent_two {
-                Some(agent) => match agent.to_ascii_lowercase().as_str() {
-                    "model" => Agent::Model,
-                    "mcts" => Agent::Mcts,
-                    "random" => Agent::Random,
-                    _ => panic!("Incorrect specification for agent two's type.")
-                }
-                None => panic!("Must define a type for agent two.")
+                Some(agent) => parse_agent(&agent)?,
+                None => return Err(Error::InvalidInput(String::from("Must define a type for agent two."))),
             };
 
             let output_path = match output_path {
                 Some(output) => output,
-                None => panic!("No output path given.")
+                None => return Err(Error::InvalidInput(String::from("No output path given."))),
             };
-            assert!(output_path.is_dir(), "Output path is not a directory or does not exist!");
+            if !output_path.is_dir() {
+                return Err(Error::NotFound(output_path));
+            }
 
             let model_one = model_path_one
-                .map(|model_path| ResNet::from_path::<T>(&model_path));
+                .map(|model_path| ResNet::from_path::<T>(&model_path))
+                .transpose()?;
 
             let model_two = model_path_two
-                .map(|model_path| ResNet::from_path::<T>(&model_path));
+                .map(|model_path| ResNet::from_path::<T>(&model_path))
+                .transpose()?;
 
             let player1 = Player{player_type: agent_one_type, model: model_one};
             let player2 = Player{player_type: agent_two_type, model: model_two};
 
-            let temp = match conf.get_float("temperature") {
-                Ok(temperature) => temperature,
-                Err(e) => panic!("unable to load temperature value, check config.toml!, error: {}", e),
-            };
+            let temp = conf.get_float("temperature")?;
 
-            let play_result: PlayResult<T> = play::<T>(player1, player2, &MctsConfig::from_config(conf).unwrap(), temp);
+            let play_result: PlayResult<T> = play::<T>(player1, player2, &mcts_config()?, temp, &output_path);
             println!("{}\n Saving games...", play_result);
             for game in play_result.games {
-                save_game(&game, output_path.to_str().unwrap()).unwrap()
+                save_game(&game, &output_path.to_string_lossy())?;
             }
         },
         Commands::Train { model_path, out_path,  run_id, learn, self_play } => {
@@ -178,41 +289,179 @@ fn handle_command<T: LearnableGame>(command: Commands, conf: &Config) {
                 (Some(id), None, None) => format!("{}/run-{}", base_path_str, id),
                 (Some(id), Some(learn_id), None) => format!("{}/run-{}/lrn-{}", base_path_str, id, learn_id),
                 (Some(id), Some(learn_id), Some(sp_id)) => format!("{}/run-{}/lrn-{}/sp-{}", base_path_str, id, learn_id, sp_id),
-                _ => panic!("the request for the training data is incorrect, run die-e learn --help for more info")
+                _ => return Err(Error::InvalidInput(String::from(
+                    "the request for the training data is incorrect, run die-e learn --help for more info"
+                ))),
             };
             let data_path = Path::new(&data_path);
             if !data_path.exists() {
-                panic!("[TRAIN] the specified path {} does not exist!", data_path.to_str().unwrap());
+                return Err(Error::NotFound(data_path.to_path_buf()));
             }
-            println!("Loading all data under {}", data_path.to_str().unwrap());
+            println!("Loading all data under {}", data_path.display());
             let mut paths_to_load = vec![];
-            let _ = get_all_paths_rec(data_path, &mut paths_to_load);
-            let mut training_data: Vec<MemoryFragment> = paths_to_load.par_iter().flat_map(|p| 
-                AlphaZero::load_training_data(p.as_path())
-            ).collect();
+            get_all_paths_rec(data_path, &mut paths_to_load)?;
+            let mut training_data: Vec<MemoryFragment> = paths_to_load
+                .par_iter()
+                .map(|p| AlphaZero::load_training_data(p.as_path()))
+                .collect::<Result<Vec<_>, Error>>()?
+                .into_iter()
+                .flatten()
+                .collect();
 
             println!("Total memory fragments: {}", &training_data.len());
             
             // Create AZ instance for training
-            let mut az = AlphaZero::from_config::<T>(model_path, conf);
+            let mut az = AlphaZero::from_config::<T>(model_path, conf)?;
 
             // Train and save model
             az.train(&mut training_data);
             let model_path_str = format!("./models/{}/trained_model.ot", T::name());
             let final_out_path = out_path.unwrap_or(Path::new(&model_path_str).to_path_buf());
-            match az.model.vs.save(&final_out_path) {
-                Ok(_) => println!("Trained model saved successfully, saved to {}", final_out_path.to_str().unwrap()),
-                Err(e) => panic!("unable to save trained model {}", e),
-            }
+            az.model.vs.save(&final_out_path)?;
+            println!("Trained model saved successfully, saved to {}", final_out_path.display());
         },
         Commands::Replay { game_path } => {
-            match print_game::<T>(game_path, true) {
-                Ok(_) => (),
-                Err(e) => panic!("unable to print game, {}", e),
+            print_game::<T>(game_path, true)?;
+        },
+        Commands::Analyze { model_path, position, roll, search, json } => {
+            let state: T = read_position(&position, roll)?;
+            let search: AnalysisSearch = search.parse().map_err(Error::InvalidInput)?;
+            let model = ResNet::from_path::<T>(&model_path)?;
+            let analysis = analyze(&state, &model, &mcts_config()?, search)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&analysis)?);
+            } else {
+                println!("{}", analysis);
+            }
+        },
+        Commands::Annotate { model_path, game_path, output_path, search } => {
+            let games = if game_path.is_dir() {
+                load_all_games::<T>(game_path.clone())?
+            } else {
+                vec![load_game::<T>(game_path.clone())?]
+            };
+            // Annotations are kept out of the games directory by default, load_all_games would read them as games
+            let output_path = match output_path {
+                Some(output_path) => output_path,
+                None => {
+                    let games_dir = if game_path.is_dir() {
+                        game_path.clone()
+                    } else {
+                        game_path.parent().map(Path::to_path_buf).unwrap_or_default()
+                    };
+                    let annotated_dir = games_dir.join("annotated");
+                    fs::create_dir_all(&annotated_dir)?;
+                    annotated_dir
+                }
+            };
+            if !output_path.is_dir() {
+                return Err(Error::NotFound(output_path));
+            }
+            let search: AnalysisSearch = search.parse().map_err(Error::InvalidInput)?;
+            let thresholds = ErrorThresholds::from_config(conf)?;
+            let mcts_config = mcts_config()?;
+            let model = ResNet::from_path::<T>(&model_path)?;
+
+            let mut total_p1 = PlayerStats::default();
+            let mut total_p2 = PlayerStats::default();
+            for game in games.iter() {
+                let annotated = annotate_game(game, &model, &mcts_config, search, &thresholds)?;
+                println!("Game {}, winner: {:?}", annotated.game_id, annotated.winner);
+                println!("\tPlayer 1 ({:?}): {}", annotated.player1, annotated.stats_p1);
+                println!("\tPlayer 2 ({:?}): {}", annotated.player2, annotated.stats_p2);
+                total_p1.merge(&annotated.stats_p1);
+                total_p2.merge(&annotated.stats_p2);
+                save_annotated_game(&annotated, &output_path)?;
+            }
+            println!("Annotated {} games, saved to {}", games.len(), output_path.display());
+            println!("Player 1: {}", total_p1);
+            println!("Player 2: {}", total_p2);
+        },
+        Commands::Rollout { model_path, position, roll, agent, games, seed, json } => {
+            let state: T = read_position(&position, roll)?;
+            let agent = parse_agent(&agent)?;
+            let mut rollout_config = RolloutConfig::from_config(conf)?;
+            rollout_config.n_games = games.unwrap_or(rollout_config.n_games);
+            rollout_config.seed = seed.unwrap_or(rollout_config.seed);
+            rollout_config.bearoff = bearoff.clone();
+
+            let result = match model_path {
+                Some(model_path) => rollout(&state, &agent, &ResNet::from_path::<T>(&model_path)?, &rollout_config)?,
+                None if agent == Agent::Random => {
+                    // Without a model there is no luck to remove and unfinished games count as draws
+                    rollout(&state, &agent, &UniformEvaluator, &rollout_config)?
+                },
+                None => return Err(Error::InvalidInput(format!("A model path must be given for the {:?} agent", agent))),
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&result)?);
+            } else {
+                println!("{}\nPosition: {}\n{}", state.to_pretty_str(), state.position_id(), result);
+            }
+        },
+        Commands::Selfcheck { games, seed, json } => {
+            let report = self_check::<T>(games, seed);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", report);
+            }
+            if !report.is_ok() {
+                process::exit(1);
             }
+        },
+        Commands::Bearoff { out_path, max_checkers } => {
+            if max_checkers > bearoff::MAX_CHECKERS {
+                return Err(Error::InvalidInput(format!("A player has at most {} checkers", bearoff::MAX_CHECKERS)));
+            }
+            println!("Generating bear-off database with up to {} checkers", max_checkers);
+            let database = BearoffDatabase::generate(max_checkers);
+            database.save(&out_path)?;
+            println!("Saved {} positions to {}", database.len(), out_path.display());
+        }
+    }
+    Ok(())
+}
+
+// Agent given on the command line, can be 'random', 'mcts', 'model', 'policy', 'value', 'expectimax'
+fn parse_agent(agent: &str) -> Result<Agent, Error> {
+    match agent.to_ascii_lowercase().as_str() {
+        "model" => Ok(Agent::Model),
+        "mcts" => Ok(Agent::Mcts),
+        "random" => Ok(Agent::Random),
+        "policy" => Ok(Agent::Policy),
+        "value" => Ok(Agent::Value),
+        "expectimax" => Ok(Agent::Expectimax),
+        _ => Err(Error::InvalidInput(format!(
+            "Incorrect agent {}, can be 'random', 'mcts', 'model', 'policy', 'value' or 'expectimax'", agent
+        ))),
+    }
+}
+
+// Reads the position given on the command line and replaces its roll with the given roll
+fn read_position<T: LearnableGame>(position: &str, roll: Option<String>) -> Result<T, Error> {
+    let mut state: T = parse_position(position)?;
+    if let Some(roll) = roll {
+        if T::IS_DETERMINISTIC {
+            return Err(Error::InvalidInput(format!("{} has no dice, remove the roll {}", T::name(), roll)));
+        }
+        match parse_roll(&roll) {
+            Some(roll) => state.set_roll(roll),
+            None => return Err(Error::InvalidInput(format!("Incorrect roll {}, expected two dice such as 6-4", roll))),
         }
     }
+    Ok(state)
+}
 
+// Loads the database in bearoff_db_path so backgammon races are evaluated exactly, None if the path is empty
+fn load_bearoff_database(conf: &Config) -> Result<Option<Arc<BearoffDatabase>>, Error> {
+    let path = conf.get_string("bearoff_db_path")?;
+    if path.is_empty() {
+        return Ok(None);
+    }
+    let database = BearoffDatabase::load(Path::new(&path))?;
+    println!("Loaded bear-off database with up to {} checkers from {}", database.max_checkers(), path);
+    Ok(Some(Arc::new(database)))
 }
 
 fn get_all_paths_rec(dir: &Path, res: &mut Vec<PathBuf>) -> io::Result<()> {
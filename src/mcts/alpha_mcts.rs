@@ -1,202 +1,256 @@
-use std::{cmp::Ordering, collections::HashSet};
+use std::collections::{HashMap, HashSet};
 
 
-use indicatif::{ProgressIterator, ProgressBar};
+use indicatif::ProgressBar;
 use itertools::Itertools;
+use rayon::prelude::*;
 
 use tch::Tensor;
 
-use crate::{alphazero::nnet::ResNet, constants::DEVICE, mcts::{noise::apply_dirichlet, utils::{turn_policy_to_probs_tensor, turn_policy_to_probs_tensor_parallel}}, MctsConfig, base::LearnableGame};
+use crate::{constants::DEVICE, evaluator::Evaluator, mcts::{utils::{turn_policy_to_probs_tensor, turn_policy_to_probs_tensor_parallel}}, MctsConfig, base::LearnableGame};
 
-use super::{node_store::NodeStore, node::Node, simple_mcts::backpropagate, utils::{turn_policy_to_probs, get_prob_tensor}};
+use super::{budget::SearchBudget, gumbel::GumbelRoot, node_store::NodeStore, puct::PuctConfig, utils::{turn_policy_to_probs, get_prob_tensor}};
 
 
-fn alpha_select_leaf_node<T: LearnableGame>(node_idx: usize, store: &NodeStore<T>, c: f32) -> usize {
-    let node = store.get_node(node_idx);
-    if node.children.is_empty() {
-        return node.idx;
+fn alpha_select_leaf_node<T: LearnableGame>(node_idx: usize, store: &NodeStore<T>, puct: &PuctConfig) -> usize {
+    let mut idx = node_idx;
+    while !store.get_node_ref(idx).children.is_empty() {
+        idx = store.select_alpha(idx, puct);
     }
-    alpha_select_leaf_node(select_alpha(node_idx, store, c).idx, store, c)
+    idx
 }
 
-fn select_alpha<T: LearnableGame>(node_idx: usize, store: &NodeStore<T>, c: f32) -> Node<T> {
-    let node = store.get_node(node_idx);
-    node.children
-        .iter()
-        .map(|child_idx| store.get_node(*child_idx))
-        .max_by(|a, b| {
-            a.alpha_ucb(store, c)
-                .partial_cmp(&b.alpha_ucb(store, c))
-                .unwrap_or(Ordering::Equal)
-        })
-        .expect("select_alpha called on node without children!")
-}
-
-pub fn apply_dirichlet_to_root<T: LearnableGame>(root_idx: usize, store: &mut NodeStore<T>, net: &ResNet, state: &impl LearnableGame, mcts_config: &MctsConfig) {
-    let mut root_node = store.get_node(root_idx);
-    
-    let policy = net.forward_policy(&state.as_tensor().to_device(*DEVICE), false);
-
-    let policy_vec = turn_policy_to_probs(&policy, &root_node);
-    root_node.alpha_expand(store, policy_vec);
-    if root_node.children.len() < 2 {
+/*
+    Mixes Dirichlet noise into the policies of the legal moves of an expanded root,
+    alpha depends on the number of legal moves (see MctsConfig::dirichlet_alpha_for)
+*/
+fn apply_noise_to_root<T: LearnableGame>(root_idx: usize, store: &mut NodeStore<T>, mcts_config: &MctsConfig) {
+    let n_moves = store.children(root_idx).len();
+    if n_moves < 2 {
         // Don't apply dirichlet on a node with a single child
         return
     }
-    // Set visits to 1
-    root_node.visits = 1.;
-    // Apply dirichlet to root policies
-    root_node.apply_dirichlet(mcts_config.dirichlet_alpha, mcts_config.dirichlet_epsilon, store);
-    store.set_node(&root_node)
+    if store.visits(root_idx) == 0. {
+        store.set_visits(root_idx, 1.);
+    }
+    store.apply_dirichlet(root_idx, mcts_config.dirichlet_alpha_for(n_moves), mcts_config.dirichlet_epsilon);
+}
+
+pub fn apply_dirichlet_to_root<T: LearnableGame>(root_idx: usize, store: &mut NodeStore<T>, evaluator: &impl Evaluator<T>, state: &T, mcts_config: &MctsConfig) {
+    let policy = evaluator.policy(&[*state]).get(0).to_device(tch::Device::Cpu);
+
+    let policy_vec = turn_policy_to_probs(&policy, store.get_node_ref(root_idx));
+    store.alpha_expand(root_idx, &policy_vec);
+    apply_noise_to_root(root_idx, store, mcts_config);
 }
 
-pub fn alpha_mcts<T: LearnableGame>(state: &T, net: &ResNet, mcts_config: &MctsConfig) -> Option<Tensor> {
+/*
+    Tree parallel search of a single state, runs in rounds of mcts_config.leaf_batch_size leaves:
+        - leaves are selected by rayon workers at the same time, each with a virtual loss on its path
+        - the selected leaves are evaluated in a single batch and expanded
+        - the results are backed up by the workers at the same time
+    Returns the searched tree with the root at index 0, the root is not expanded if the state is terminal
+*/
+pub fn alpha_mcts_search<T: LearnableGame>(state: &T, evaluator: &impl Evaluator<T>, mcts_config: &MctsConfig) -> NodeStore<T> {
     // Set no_grad_guard
     let _guard = tch::no_grad_guard();
+    let budget = SearchBudget::new(mcts_config);
     
+    let mut store = NodeStore::new();
+    let root_node_idx = store.add_node(*state, None, None, 0.0);
     // Check if game already is terminal at root
     if state.check_winner().is_some() {
-        return None;
+        return store;
     }
-    let mut store = NodeStore::new();
-    let root_node_idx = store.add_node(*state, None, None, 0.0);
     
-    apply_dirichlet_to_root(root_node_idx, &mut store, net, state, mcts_config);
-    
-    let pb_iter = (0..mcts_config.iterations).progress().with_message("AlphaMCTS");
-    for _ in pb_iter {
-        // Don't forget to save the node later into the store
-        let idx = alpha_select_leaf_node(root_node_idx, &store, mcts_config.c);
-        let mut selected_node = store.get_node(idx);
-
-        let value = if !selected_node.is_terminal() {
-            let (policy, eval) = net.forward_t(&selected_node.state.as_tensor().to_device(*DEVICE), false);
-
-            let policy_vec = turn_policy_to_probs(&policy, &selected_node);
-            selected_node.alpha_expand(&mut store, policy_vec);
-            eval.double_value(&[0]) as f32
-        } else {
-            ((state.check_winner().unwrap() + 1) / 2) as f32
-        };
-
-        backpropagate(idx, value, &mut store);
+    apply_dirichlet_to_root(root_node_idx, &mut store, evaluator, state, mcts_config);
+
+    let pb = ProgressBar::new(mcts_config.iterations as u64).with_message("AlphaMCTS");
+    let mut n_searched = 0;
+    while !budget.is_exhausted(n_searched, store.len()) && !budget.is_decided(&store, root_node_idx, n_searched) {
+        let n_leaves = mcts_config.leaf_batch_size.clamp(1, mcts_config.iterations - n_searched);
+
+        // Workers select leaves at the same time, virtual losses spread them over the tree
+        let leaves: Vec<usize> = (0..n_leaves)
+            .into_par_iter()
+            .map(|_| store.select_leaf_virtual(root_node_idx, &mcts_config.puct, mcts_config.virtual_loss))
+            .collect();
+
+        // A leaf selected by multiple workers is evaluated and expanded once
+        let to_expand = leaves
+            .iter()
+            .copied()
+            .unique()
+            .filter(|&idx| !store.get_node_ref(idx).is_terminal())
+            .collect_vec();
+        let mut leaf_values: HashMap<usize, f32> = HashMap::with_capacity(to_expand.len());
+        if !to_expand.is_empty() {
+            let selected_states = to_expand.iter().map(|&idx| store.get_node_ref(idx).state).collect_vec();
+            let (policy, eval) = evaluator.evaluate(&selected_states);
+            let (policy, eval) = (policy.to_device(tch::Device::Cpu), eval.to_device(tch::Device::Cpu));
+            for (processed_idx, &idx) in to_expand.iter().enumerate() {
+                let policy_vec = turn_policy_to_probs(&policy.get(processed_idx as i64), store.get_node_ref(idx));
+                store.alpha_expand(idx, &policy_vec);
+                let value = mcts_config.exact_value(&store.get_node_ref(idx).state)
+                    .unwrap_or_else(|| eval.double_value(&[processed_idx as i64, 0]) as f32);
+                leaf_values.insert(idx, value);
+            }
+        }
+
+        // Backups of all workers run at the same time, values are from the view of the player to move at the leaf
+        leaves.par_iter().for_each(|&idx| {
+            let value = match leaf_values.get(&idx) {
+                Some(value) => *value,
+                None => store.terminal_value(idx).unwrap_or(0.),
+            };
+            store.backpropagate_virtual(idx, value);
+        });
+
+        n_searched += n_leaves;
+        pb.inc(n_leaves as u64);
+    }
+    pb.finish_and_clear();
+    store
+}
+
+pub fn alpha_mcts<T: LearnableGame>(state: &T, evaluator: &impl Evaluator<T>, mcts_config: &MctsConfig) -> Option<Tensor> {
+    // Check if game already is terminal at root
+    if state.check_winner().is_some() {
+        return None;
     }
-    get_prob_tensor(state, root_node_idx, &store)
+    get_prob_tensor(state, 0, &alpha_mcts_search(state, evaluator, mcts_config))
 }
 
 
 /*
     Similar to alpha_mcts, however this function mutates the NodeStore rather than returning probabilities
+    The store is either empty or holds the roots of the states at indices 0..states.len() (see NodeStore::reroot),
+    roots that are already expanded keep their subtrees from the previous search
+    Returns the child of each root left by the sequential halving of a Gumbel search, the move to play,
+    None for every root of a PUCT search and for roots without children
 */
-pub fn alpha_mcts_parallel<T: LearnableGame>(store: &mut NodeStore<T>, states: &[T], net: &ResNet, mcts_config: &MctsConfig, pb: Option<ProgressBar>) {
+pub fn alpha_mcts_parallel<T: LearnableGame>(store: &mut NodeStore<T>, states: &[T], evaluator: &impl Evaluator<T>, mcts_config: &MctsConfig, pb: Option<ProgressBar>) -> Vec<Option<usize>> {
     // Set no_grad_guard
     let _guard = tch::no_grad_guard();
-    assert!(store.is_empty(), "AlphaMCTS paralel expects an empty store");
-    
-    // Convert all states a tensor
-    let states_vec = states.iter().map(|state| state.as_tensor()).collect_vec();
-    let states_tensor = Tensor::stack(
-        &states_vec,
-        0
-    ).squeeze_dim(1).to_device(*DEVICE);
-
-    // Get policy tensor
-    let policy = net.forward_policy(&states_tensor, false);
-
-    // Apply dirichlet to root policy tensor
-    let policy_dir = apply_dirichlet(&policy, mcts_config.dirichlet_alpha, mcts_config.dirichlet_epsilon);
+    let budget = SearchBudget::new(mcts_config);
 
     // Create root node for each game state
-    for (_, state) in states.iter().enumerate() {
-        store.add_node(*state, None, None, 0.0);
+    if store.is_empty() {
+        for state in states.iter() {
+            store.add_node(*state, None, None, 0.0);
+        }
     }
+    assert!(
+        store.len() >= states.len() && (0..states.len()).all(|i| store.get_node_ref(i).parent.is_none()),
+        "AlphaMCTS parallel expects the roots of the states at the start of the store"
+    );
+    let (reused_idxs, new_idxs): (Vec<usize>, Vec<usize>) = (0..states.len())
+        .partition(|&i| !store.get_node_ref(i).children.is_empty());
+    
+    // Get policy tensor
+    let policy = evaluator.policy(states).to_device(*DEVICE);
 
-    // Expand root node for each game state
+    // Expand root node for each new game state, roots kept by a reroot for another roll are expanded again for their own roll
     // Move policy to CPU because alpha expand makes multiple double_value(idx) calls,
     // Takes too long when tensors are in GPU
-    let prob_tensor = turn_policy_to_probs_tensor_parallel(store, &(0..states.len()).collect_vec(), &policy_dir)
+    let prob_tensor = turn_policy_to_probs_tensor_parallel(store, &new_idxs, &policy)
         .to_device(tch::Device::Cpu);
-    for i in 0..states.len() {
-        // Create root node for each game state
-        let mut root = store.get_node(i);
-
-        root.visits = 1.;
-        // let prob_tensor = turn_policy_to_probs_tensor(&policy_dir.get(i as i64), &root);
+    for &i in new_idxs.iter() {
+        if store.visits(i) == 0. {
+            store.set_visits(i, 1.);
+        }
         // Expand root
-        root.alpha_expand_tensor(store, &prob_tensor.get(i as i64));
+        store.alpha_expand_tensor(i, &prob_tensor.get(i as i64));
+    }
+    // Each root gets its own noise over its legal moves, reused roots keep their children and only get new noise
+    // Gumbel roots explore with their gumbel noise instead
+    if mcts_config.gumbel.is_none() {
+        for i in new_idxs.into_iter().chain(reused_idxs) {
+            apply_noise_to_root(i, store, mcts_config);
+        }
     }
     
     /*
         Create two vectors:
             - Games:
                 - values ranging from 0 to N_SELF_PLAY_BATCHES
-                - used to track how many games are still not completed
+                - used to track which games are still searching, games are removed once their best move is decided
             - Selected_nodes:
-                - N_SELF_PLAY_BATCHES node values
-                - selected_nodes[i] refers to the node that was last selected for game[i]
+                - created in each iteration
+                - the non terminal leaves selected for the games in that iteration
 
     */
-    let games: HashSet<usize> = HashSet::from_iter(0..states.len());
+    let mut games: HashSet<usize> = HashSet::from_iter(0..states.len());
 
-    // Game id to node idx mapping
-    let mut selected_nodes_idxs = vec![0; states.len()];
+    // Sequential halving state of each root, simulations of a game start from the child given by its gumbel root
+    let mut gumbel_roots: HashMap<usize, GumbelRoot> = match &mcts_config.gumbel {
+        Some(gumbel_config) => (0..states.len())
+            .filter(|&i| !store.children(i).is_empty())
+            .map(|i| (i, GumbelRoot::new(store, i, mcts_config.iterations, gumbel_config)))
+            .collect(),
+        None => HashMap::new(),
+    };
 
     let pb = match pb {
         Some(p) => p,
         None => ProgressBar::new(mcts_config.iterations as u64)
     };
 
-    for _ in 0..mcts_config.iterations {
-        // Clear selected node indices
-        let mut node_selected = false;
+    let mut iteration = 0;
+    while !budget.is_exhausted(iteration, store.len() / states.len().max(1)) {
+        // Games whose best move is already decided stop searching
+        games.retain(|&game_idx| !budget.is_decided(store, game_idx, iteration));
+        if games.is_empty() {
+            break;
+        }
+        iteration += 1;
         pb.inc(1);
+        let mut selected_nodes_idxs = Vec::with_capacity(games.len());
         for &game_idx in games.iter() {
-            let idx = alpha_select_leaf_node(game_idx, store, mcts_config.c);
-            let selected_node = store.get_node(idx);
+            let start_idx = match (&mcts_config.gumbel, gumbel_roots.get_mut(&game_idx)) {
+                (Some(gumbel_config), Some(gumbel_root)) => gumbel_root.next_child(store, gumbel_config).unwrap_or(game_idx),
+                _ => game_idx,
+            };
+            let idx = alpha_select_leaf_node(start_idx, store, &mcts_config.puct);
             
-            if let Some(winner) = selected_node.state.check_winner() {
-                let game_root = store.get_node(game_idx);
-                let root_player = game_root.state.get_player();
-                let value = if winner == root_player {1.}
-                    else if winner == -root_player {-1.}
-                    else {0.};
-                backpropagate(idx, value as f32, store);
+            if let Some(value) = store.terminal_value(idx) {
+                store.backpropagate(idx, value);
             } else {
-                node_selected = true;
-                selected_nodes_idxs[game_idx] = selected_node.idx;
+                selected_nodes_idxs.push(idx);
             }
         }
 
-        if !node_selected {
+        if selected_nodes_idxs.is_empty() {
             continue;
         }
             
-        // Convert ongoing (not terminal) games into a tensor vec
-        let selected_states_vec = selected_nodes_idxs.iter().map(|node_idx| {
-            let node = store.get_node(*node_idx);
-            node.state.as_tensor()
+        // Ongoing (not terminal) games to evaluate
+        let selected_states = selected_nodes_idxs.iter().map(|node_idx| {
+            store.get_node_ref(*node_idx).state
         }).collect_vec();
 
-        let selected_states_tensor = Tensor::stack(
-            &selected_states_vec,
-            0
-        ).squeeze_dim(1).to_device(*DEVICE);
-
         // Calculate policies
-        let (policy, eval) = net.forward_t(&selected_states_tensor, false);
+        let (policy, eval) = evaluator.evaluate(&selected_states);
         
         // Expand and backprop selected nodes with their respective calculated policies and evals
         let eval = eval.to_device(tch::Device::Cpu);
         let policy = policy.to_device(tch::Device::Cpu);
 
         for (processed_idx, &node_idx) in selected_nodes_idxs.iter().enumerate() {
-            let mut node = store.get_node(node_idx);
             let (policy_i, eval_i) = (policy.get(processed_idx as i64), eval.get(processed_idx as i64));
-            let prob_tensor = turn_policy_to_probs_tensor(&policy_i, &node);
+            let prob_tensor = turn_policy_to_probs_tensor(&policy_i, store.get_node_ref(node_idx));
             // Expand selected node
-            node.alpha_expand_tensor(store, &prob_tensor);
-            let value = eval_i.double_value(&[0]) as f32;
-            backpropagate(node.idx, value, store);
+            store.alpha_expand_tensor(node_idx, &prob_tensor);
+            let value = mcts_config.exact_value(&store.get_node_ref(node_idx).state)
+                .unwrap_or_else(|| eval_i.double_value(&[0]) as f32);
+            store.backpropagate(node_idx, value);
         }
     }
+
+    (0..states.len())
+        .map(|game_idx| match (&mcts_config.gumbel, gumbel_roots.get(&game_idx)) {
+            (Some(gumbel_config), Some(gumbel_root)) => gumbel_root.best_child(store, gumbel_config),
+            _ => None,
+        })
+        .collect_vec()
 }
@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+use crate::{base::LearnableGame, MctsConfig};
+
+use super::node_store::NodeStore;
+
+/*
+    Stopping criteria of a search, the search stops as soon as one of them is met
+        - iterations: number of iterations (always set)
+        - time_limit: wall clock time since the start of the search
+        - max_nodes: number of nodes in the tree of each searched state
+        - early_stop: the most visited move of a root can no longer be overtaken in the remaining iterations
+*/
+pub struct SearchBudget {
+    start: Instant,
+    iterations: usize,
+    time_limit: Option<Duration>,
+    max_nodes: Option<usize>,
+    early_stop: bool,
+}
+
+impl SearchBudget {
+    pub fn new(mcts_config: &MctsConfig) -> Self {
+        SearchBudget {
+            start: Instant::now(),
+            iterations: mcts_config.iterations,
+            time_limit: (mcts_config.time_limit_ms > 0).then_some(Duration::from_millis(mcts_config.time_limit_ms)),
+            max_nodes: (mcts_config.max_nodes > 0).then_some(mcts_config.max_nodes),
+            early_stop: mcts_config.early_stop,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /**
+     * True if the search should stop after the given number of iterations with n_nodes nodes per searched state
+     */
+    pub fn is_exhausted(&self, iteration: usize, n_nodes: usize) -> bool {
+        iteration >= self.iterations
+            || matches!(self.time_limit, Some(limit) if self.start.elapsed() >= limit)
+            || matches!(self.max_nodes, Some(max_nodes) if n_nodes >= max_nodes)
+    }
+
+    /**
+     * True if early_stop is set and the search of root_idx can no longer change the most visited move,
+     * either because the root has a single move or the lead of the most visited move is larger than the remaining iterations
+     */
+    pub fn is_decided<T: LearnableGame>(&self, store: &NodeStore<T>, root_idx: usize, iteration: usize) -> bool {
+        if !self.early_stop {
+            return false;
+        }
+        let children = store.children(root_idx);
+        if children.len() == 1 {
+            return true;
+        }
+        let (mut best, mut second) = (0., 0.);
+        for child_idx in children {
+            let visits = store.visits(child_idx);
+            if visits > best {
+                second = best;
+                best = visits;
+            } else if visits > second {
+                second = visits;
+            }
+        }
+        best - second > self.iterations.saturating_sub(iteration) as f32
+    }
+}
@@ -0,0 +1,197 @@
+use std::cmp::Ordering;
+
+use config::{Config, ConfigError};
+use itertools::Itertools;
+use rand::thread_rng;
+use rand_distr::{Distribution, Gumbel};
+use tch::Tensor;
+
+use crate::{base::LearnableGame, constants::DEVICE};
+
+use super::node_store::NodeStore;
+
+/*
+    Root move selection of Gumbel MuZero (Danihelka et al. 2022, Policy improvement by planning with Gumbel)
+        - n_sampled moves of the root are sampled without replacement with the Gumbel-top-k trick on the log policy
+        - the simulations are split with sequential halving, in each phase the remaining moves get the same number
+          of simulations, then the worse half by g + log P + sigma(q) is dropped
+        - the policy target is softmax(log P + sigma(completed q)), it improves on the policy even with few simulations
+    sigma(q) = (c_visit + max visits of a child) * c_scale * q, with q normalized to [0, 1]
+    Nodes below the root are still selected with PUCT, the move played is the candidate left by the halving
+    Only the batched search of self play and versus (alpha_mcts_parallel) selects its roots this way,
+    the tree parallel search of a single state (alpha_mcts_search) always uses PUCT
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct GumbelConfig {
+    pub n_sampled: usize,
+    pub c_visit: f32,
+    pub c_scale: f32,
+}
+
+impl GumbelConfig {
+    // None if root_selection is 'puct'
+    pub fn from_config(conf: &Config) -> Result<Option<Self>, ConfigError> {
+        match conf.get_string("root_selection")?.to_ascii_lowercase().as_str() {
+            "puct" => Ok(None),
+            "gumbel" => Ok(Some(GumbelConfig {
+                n_sampled: conf.get_int("gumbel_sampled_moves")? as usize,
+                c_visit: conf.get_float("gumbel_c_visit")? as f32,
+                c_scale: conf.get_float("gumbel_c_scale")? as f32,
+            })),
+            other => Err(ConfigError::Message(format!("root_selection must be 'puct' or 'gumbel', got '{}'", other))),
+        }
+    }
+
+    fn sigma<T: LearnableGame>(&self, store: &NodeStore<T>, root_idx: usize, q: f32) -> f32 {
+        let max_visits = store
+            .children(root_idx)
+            .map(|child_idx| store.visits(child_idx))
+            .fold(0., f32::max);
+        (self.c_visit + max_visits) * self.c_scale * q
+    }
+}
+
+// Log of the policy, moves with a policy of 0 get a very low but finite logit
+fn log_policy(policy: f32) -> f32 {
+    policy.max(1e-12).ln()
+}
+
+// Q of a child normalized from [-1, 1] to [0, 1], unvisited children use the mean value of the root
+fn completed_q<T: LearnableGame>(store: &NodeStore<T>, root_idx: usize, child_idx: usize) -> f32 {
+    let idx = if store.visits(child_idx) > 0. { child_idx } else { root_idx };
+    let q = if store.visits(idx) > 0. { store.value(idx) / store.visits(idx) } else { 0. };
+    ((q + 1.) / 2.).clamp(0., 1.)
+}
+
+/*
+    Sequential halving state of a single root, simulations of the root are started from the child given by next_child
+*/
+pub struct GumbelRoot {
+    root_idx: usize,
+    // g + log P of each child of the root, in the order of the children
+    gumbel_logits: Vec<f32>,
+    // Children still in the halving and the simulations they got in the current phase
+    candidates: Vec<usize>,
+    phase_sims: Vec<usize>,
+    sims_per_candidate: usize,
+    budget: usize,
+    n_phases: usize,
+}
+
+impl GumbelRoot {
+    /**
+     * Samples the candidate moves of an expanded root, budget is the number of simulations of the search
+     */
+    pub fn new<T: LearnableGame>(store: &NodeStore<T>, root_idx: usize, budget: usize, config: &GumbelConfig) -> Self {
+        let children = store.children(root_idx);
+        let gumbel = Gumbel::new(0_f32, 1.).unwrap();
+        let mut rng = thread_rng();
+        let gumbel_logits = children
+            .clone()
+            .map(|child_idx| gumbel.sample(&mut rng) + log_policy(store.policy(child_idx)))
+            .collect_vec();
+
+        let n_sampled = config.n_sampled.clamp(1, children.len().max(1));
+        let candidates = children
+            .clone()
+            .sorted_by(|&a, &b| {
+                gumbel_logits[b - children.start]
+                    .partial_cmp(&gumbel_logits[a - children.start])
+                    .unwrap_or(Ordering::Equal)
+            })
+            .take(n_sampled)
+            .collect_vec();
+        let n_phases = (n_sampled as f32).log2().ceil().max(1.) as usize;
+
+        GumbelRoot {
+            root_idx,
+            gumbel_logits,
+            phase_sims: vec![0; candidates.len()],
+            sims_per_candidate: (budget / (n_phases * candidates.len().max(1))).max(1),
+            candidates,
+            budget,
+            n_phases,
+        }
+    }
+
+    fn score<T: LearnableGame>(&self, store: &NodeStore<T>, child_idx: usize, config: &GumbelConfig) -> f32 {
+        let offset = child_idx - store.children(self.root_idx).start;
+        self.gumbel_logits[offset] + config.sigma(store, self.root_idx, completed_q(store, self.root_idx, child_idx))
+    }
+
+    fn halve<T: LearnableGame>(&mut self, store: &NodeStore<T>, config: &GumbelConfig) {
+        // Half of the candidates rounded up, written out as usize::div_ceil needs Rust 1.73
+        let keep = self.candidates.len() - self.candidates.len() / 2;
+        self.candidates = self
+            .candidates
+            .iter()
+            .copied()
+            .sorted_by(|&a, &b| {
+                self.score(store, b, config)
+                    .partial_cmp(&self.score(store, a, config))
+                    .unwrap_or(Ordering::Equal)
+            })
+            .take(keep)
+            .collect_vec();
+        self.phase_sims = vec![0; keep];
+        self.sims_per_candidate = (self.budget / (self.n_phases * keep)).max(1);
+    }
+
+    /**
+     * Child of the root the next simulation goes through, None if the root has no children
+     */
+    pub fn next_child<T: LearnableGame>(&mut self, store: &NodeStore<T>, config: &GumbelConfig) -> Option<usize> {
+        if self.candidates.len() > 1 && self.phase_sims.iter().all(|&sims| sims >= self.sims_per_candidate) {
+            self.halve(store, config);
+        }
+        let (position, _) = self.phase_sims.iter().enumerate().min_by_key(|(_, &sims)| sims)?;
+        self.phase_sims[position] += 1;
+        Some(self.candidates[position])
+    }
+
+    /**
+     * Remaining candidate with the highest g + log P + sigma(q), the move Gumbel MuZero plays
+     */
+    pub fn best_child<T: LearnableGame>(&self, store: &NodeStore<T>, config: &GumbelConfig) -> Option<usize> {
+        self.candidates.iter().copied().max_by(|&a, &b| {
+            self.score(store, a, config)
+                .partial_cmp(&self.score(store, b, config))
+                .unwrap_or(Ordering::Equal)
+        })
+    }
+}
+
+/**
+ * Improved policy softmax(log P + sigma(completed q)) over the children of root_idx as (child idx, probability)
+ */
+pub fn improved_policy<T: LearnableGame>(store: &NodeStore<T>, root_idx: usize, config: &GumbelConfig) -> Vec<(usize, f32)> {
+    let logits = store
+        .children(root_idx)
+        .map(|child_idx| {
+            let q = completed_q(store, root_idx, child_idx);
+            (child_idx, log_policy(store.policy(child_idx)) + config.sigma(store, root_idx, q))
+        })
+        .collect_vec();
+    let max_logit = logits.iter().map(|(_, logit)| *logit).fold(f32::NEG_INFINITY, f32::max);
+    let exps = logits.iter().map(|(child_idx, logit)| (*child_idx, (logit - max_logit).exp())).collect_vec();
+    let sum: f32 = exps.iter().map(|(_, exp)| exp).sum();
+    exps.into_iter().map(|(child_idx, exp)| (child_idx, exp / sum)).collect_vec()
+}
+
+/**
+ * Improved policies of the roots as a tensor of size N x ACTION_SPACE_SIZE, rows of roots without children are zeros
+ */
+pub fn improved_policy_tensor_parallel<T: LearnableGame>(store: &NodeStore<T>, root_idxs: &[usize], config: &GumbelConfig) -> Tensor {
+    let action_space = T::ACTION_SPACE_SIZE as usize;
+    let mut values = vec![0_f32; root_idxs.len() * action_space];
+    for (processed_idx, &root_idx) in root_idxs.iter().enumerate() {
+        let root_state = store.get_node_ref(root_idx).state;
+        for (child_idx, prob) in improved_policy(store, root_idx, config) {
+            let action = store.get_node_ref(child_idx).action_taken.as_ref().unwrap();
+            values[processed_idx * action_space + root_state.encode(action) as usize] = prob;
+        }
+    }
+    Tensor::from_slice(&values)
+        .view([root_idxs.len() as i64, T::ACTION_SPACE_SIZE])
+        .to_device(*DEVICE)
+}
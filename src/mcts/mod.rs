@@ -2,5 +2,9 @@ pub mod node;
 pub mod node_store;
 pub mod simple_mcts;
 pub mod alpha_mcts;
+pub mod budget;
+pub mod move_selection;
+pub mod puct;
+pub mod gumbel;
 pub mod utils;
 mod noise;
\ No newline at end of file
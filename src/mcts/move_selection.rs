@@ -0,0 +1,135 @@
+use config::{Config, ConfigError};
+use rand::{distributions::WeightedIndex, prelude::Distribution, thread_rng};
+use serde::Deserialize;
+
+use crate::base::LearnableGame;
+
+use super::node_store::NodeStore;
+
+/*
+    How the move to play is picked from the children of a searched root
+        - MostVisits: the most visited move
+        - BestQ: the move with the highest mean value, unvisited moves are skipped
+        - Lcb: the move with the highest lower confidence bound of its mean value, q - z / sqrt(visits)
+        - Temperature: samples from the visit counts raised to 1/temperature, the temperature depends on the move number
+          (see TemperatureSchedule), a temperature of 0 plays the most visited move
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoveSelection {
+    MostVisits,
+    BestQ,
+    Lcb { z: f32 },
+    Temperature(TemperatureSchedule),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TemperatureStep {
+    pub from_move: usize,
+    pub temperature: f64,
+}
+
+/*
+    Temperature by move number, each step applies from its from_move until the from_move of the next step
+    Ex. [{ from_move = 0, temperature = 1.0 }, { from_move = 30, temperature = 0.0 }]
+    explores the first 30 moves and plays the most visited move after that
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemperatureSchedule {
+    steps: Vec<TemperatureStep>,
+}
+
+impl TemperatureSchedule {
+    pub fn new(mut steps: Vec<TemperatureStep>) -> Self {
+        steps.sort_by_key(|step| step.from_move);
+        TemperatureSchedule { steps }
+    }
+
+    pub fn constant(temperature: f64) -> Self {
+        TemperatureSchedule::new(vec![TemperatureStep { from_move: 0, temperature }])
+    }
+
+    // Moves before the first step use the temperature of the first step, an empty schedule is always 1
+    pub fn temperature_at(&self, move_number: usize) -> f64 {
+        self.steps
+            .iter()
+            .take_while(|step| step.from_move <= move_number)
+            .last()
+            .or(self.steps.first())
+            .map_or(1., |step| step.temperature)
+    }
+}
+
+impl MoveSelection {
+    /**
+     * Parses the selection policy under key, one of 'most_visits', 'best_q', 'lcb' or 'temperature'.
+     * Lcb uses lcb_z and temperature uses temperature_schedule from the config.
+     */
+    pub fn from_config(conf: &Config, key: &str) -> Result<Self, ConfigError> {
+        match conf.get_string(key)?.to_ascii_lowercase().as_str() {
+            "most_visits" => Ok(MoveSelection::MostVisits),
+            "best_q" => Ok(MoveSelection::BestQ),
+            "lcb" => Ok(MoveSelection::Lcb { z: conf.get_float("lcb_z")? as f32 }),
+            "temperature" => Ok(MoveSelection::Temperature(TemperatureSchedule::new(
+                conf.get::<Vec<TemperatureStep>>("temperature_schedule")?,
+            ))),
+            other => Err(ConfigError::Message(format!(
+                "{} must be one of 'most_visits', 'best_q', 'lcb' or 'temperature', got '{}'",
+                key, other
+            ))),
+        }
+    }
+
+    /**
+     * Returns the idx of the child of root_idx to play, None if the root has no children.
+     * move_number is the number of moves played in the game so far.
+     */
+    pub fn select_child<T: LearnableGame>(&self, store: &NodeStore<T>, root_idx: usize, move_number: usize) -> Option<usize> {
+        let children = store.children(root_idx);
+        if children.is_empty() {
+            return None;
+        }
+        // Q of the children is from the view of the player to move at the root (see NodeStore)
+        let q_value = |idx: usize| store.win_pct(idx);
+        match self {
+            MoveSelection::MostVisits => store.select_most_visits(root_idx),
+            MoveSelection::BestQ => best_by(children, |idx| (store.visits(idx) > 0.).then(|| q_value(idx)))
+                .or_else(|| store.select_most_visits(root_idx)),
+            MoveSelection::Lcb { z } => best_by(children, |idx| {
+                (store.visits(idx) > 0.).then(|| q_value(idx) - z / store.visits(idx).sqrt())
+            })
+            .or_else(|| store.select_most_visits(root_idx)),
+            MoveSelection::Temperature(schedule) => {
+                let temperature = schedule.temperature_at(move_number);
+                if temperature <= 0. {
+                    return store.select_most_visits(root_idx);
+                }
+                let weights = children
+                    .clone()
+                    .map(|idx| (store.visits(idx) as f64).powf(1.0 / temperature))
+                    .collect::<Vec<f64>>();
+                match WeightedIndex::new(&weights) {
+                    Ok(dist) => Some(children.start + dist.sample(&mut thread_rng())),
+                    // No visits or an infinite weight, fall back to the most visited move
+                    Err(_) => store.select_most_visits(root_idx),
+                }
+            }
+        }
+    }
+
+    /**
+     * Same as select_child but returns the move, T::EMPTY_MOVE if the root has no children
+     */
+    pub fn select_move<T: LearnableGame>(&self, store: &NodeStore<T>, root_idx: usize, move_number: usize) -> T::Move {
+        self.select_child(store, root_idx, move_number)
+            .and_then(|child_idx| store.get_node_ref(child_idx).action_taken.clone())
+            .unwrap_or(T::EMPTY_MOVE)
+    }
+}
+
+// Idx with the highest score, indices without a score are skipped
+fn best_by(indices: impl Iterator<Item = usize>, score: impl Fn(usize) -> Option<f32>) -> Option<usize> {
+    indices
+        .filter_map(|idx| score(idx).map(|value| (idx, value)))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(idx, _)| idx)
+}
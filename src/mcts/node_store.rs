@@ -1,13 +1,58 @@
-use std::fmt;
+use std::{fmt, ops::Range, sync::atomic::{AtomicU32, Ordering}};
 
 use itertools::Itertools;
+use tch::Tensor;
 
 use crate::base::LearnableGame;
 
-use super::node::Node;
+use super::{node::Node, puct::{Fpu, PuctConfig}};
 
+/*
+    f32 that can be updated from multiple threads, stored as its bits
+*/
+struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    fn new(value: f32) -> Self {
+        AtomicF32(AtomicU32::new(value.to_bits()))
+    }
+
+    #[inline]
+    fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&mut self, value: f32) {
+        *self.0.get_mut() = value.to_bits();
+    }
+
+    #[inline]
+    fn add(&self, value: f32) {
+        let _ = self.0.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f32::from_bits(bits) + value).to_bits())
+        });
+    }
+}
+
+/*
+    Arena of the search tree
+        - nodes: per node data (state, parent, action taken, range of the children)
+        - visits (N), values (W), policies (P): statistics of the edge leading to each node, stored as separate arrays
+        - virtual_losses: number of searches currently passing through each node (see select_leaf_virtual)
+    The value of a node is from the view of the player who moved into it, the player to move at its parent,
+    so the Q of the children of a node is from the view of the player choosing between them.
+    The root has no such player, its value is from the view of its own player to move.
+    The children of a node are expanded at once so their statistics are next to each other,
+    selection only reads these arrays by reference and does not clone or allocate.
+    Visits, values and virtual losses are atomic so that selection and backups can run on multiple threads,
+    expansion needs a mutable store and happens in between.
+*/
 pub struct NodeStore<T: LearnableGame> {
     nodes: Vec<Node<T>>,
+    visits: Vec<AtomicF32>,
+    values: Vec<AtomicF32>,
+    policies: Vec<f32>,
+    virtual_losses: Vec<AtomicU32>,
 }
 
 impl <T: LearnableGame> fmt::Display for NodeStore<T> {
@@ -24,7 +69,17 @@ impl <T: LearnableGame> Default for NodeStore<T> {
 
 impl <T: LearnableGame> NodeStore<T> {
     pub fn new() -> Self {
-        NodeStore { nodes: vec![] }
+        NodeStore { nodes: vec![], visits: vec![], values: vec![], policies: vec![], virtual_losses: vec![] }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        NodeStore {
+            nodes: Vec::with_capacity(capacity),
+            visits: Vec::with_capacity(capacity),
+            values: Vec::with_capacity(capacity),
+            policies: Vec::with_capacity(capacity),
+            virtual_losses: Vec::with_capacity(capacity),
+        }
     }
 
     pub fn get_root_nodes(&self) -> Vec<&Node<T>> {
@@ -39,31 +94,361 @@ impl <T: LearnableGame> NodeStore<T> {
         policy: f32,
     ) -> usize {
         let idx = self.nodes.len();
-        let new_node = Node::new(state, idx, parent, action_taken, policy);
-        self.nodes.push(new_node);
+        self.nodes.push(Node::new(state, idx, parent, action_taken));
+        self.visits.push(AtomicF32::new(0.));
+        self.values.push(AtomicF32::new(0.));
+        self.policies.push(policy);
+        self.virtual_losses.push(AtomicU32::new(0));
         idx
     }
 
-    pub fn set_node(&mut self, node: &Node<T>) {
-        self.nodes[node.idx] = node.clone();
-    }
-
-    pub fn get_node(&self, idx: usize) -> Node<T> {
-        self.nodes.get(idx).unwrap().clone()
-    }
-
     pub fn get_node_ref(&self, idx: usize) -> &Node<T> {
-        self.nodes.get(idx).unwrap()
+        &self.nodes[idx]
     }
 
     pub fn get_node_as_mut(&mut self, idx: usize) -> &mut Node<T> {
         &mut self.nodes[idx]
     }
 
+    pub fn children(&self, idx: usize) -> Range<usize> {
+        self.nodes[idx].children.clone()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.nodes.is_empty()
     }
 
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn visits(&self, idx: usize) -> f32 {
+        self.visits[idx].get()
+    }
+
+    pub fn value(&self, idx: usize) -> f32 {
+        self.values[idx].get()
+    }
+
+    pub fn policy(&self, idx: usize) -> f32 {
+        self.policies[idx]
+    }
+
+    pub fn virtual_loss(&self, idx: usize) -> u32 {
+        self.virtual_losses[idx].load(Ordering::Relaxed)
+    }
+
+    pub fn set_visits(&mut self, idx: usize, visits: f32) {
+        self.visits[idx].set(visits)
+    }
+
+    pub fn set_value(&mut self, idx: usize, value: f32) {
+        self.values[idx].set(value)
+    }
+
+    pub fn policy_mut(&mut self, idx: usize) -> &mut f32 {
+        &mut self.policies[idx]
+    }
+
+    // Player whose view the value of node idx is from, see NodeStore
+    #[inline]
+    fn mover(&self, idx: usize) -> i8 {
+        let mover_idx = self.nodes[idx].parent.unwrap_or(idx);
+        self.nodes[mover_idx].state.get_player()
+    }
+
+    // Turns result, from the view of player, to the view of the mover of node idx
+    #[inline]
+    fn value_for_mover(&self, idx: usize, player: i8, result: f32) -> f32 {
+        if self.mover(idx) == player { result } else { -result }
+    }
+
+    /**
+     * Value of the finished game at node idx from the view of its player to move, None if the game is not over
+     */
+    pub fn terminal_value(&self, idx: usize) -> Option<f32> {
+        let state = &self.nodes[idx].state;
+        let player = state.get_player();
+        state.check_winner().map(|winner| {
+            if winner == player { 1. }
+            else if winner == -player { -1. }
+            else { 0. }
+        })
+    }
+
+    pub fn win_pct(&self, idx: usize) -> f32 {
+        self.value(idx) / self.visits(idx)
+    }
+
+    pub fn ucb(&self, idx: usize, c: f32) -> f32 {
+        match self.nodes[idx].parent {
+            // Unvisited children are tried first
+            Some(_) if self.visits(idx) == 0. => f32::INFINITY,
+            Some(parent_idx) => {
+                let exploitation = self.value(idx) / self.visits(idx);
+                let exploration = (c * self.visits(parent_idx).ln() / self.visits(idx)).sqrt();
+                exploitation + exploration
+            }
+            None => f32::INFINITY,
+        }
+    }
+
+    pub fn alpha_ucb(&self, idx: usize, puct: &PuctConfig) -> f32 {
+        match self.nodes[idx].parent {
+            Some(parent_idx) => {
+                let parent_visits = self.visits(parent_idx);
+                let exploration = puct.c_puct(parent_visits) * parent_visits.sqrt();
+                self.puct(idx, exploration, self.fpu_value(parent_idx, puct, 0.), 0.)
+            }
+            None => f32::INFINITY,
+        }
+    }
+
+    /**
+     * Q used for the unvisited children of node idx, from the view of the player to move at idx
+     */
+    pub fn fpu_value(&self, idx: usize, puct: &PuctConfig, virtual_loss: f32) -> f32 {
+        match puct.fpu {
+            Fpu::Absolute(value) => value,
+            Fpu::Reduction(reduction) => {
+                let pending = self.virtual_loss(idx) as f32 * virtual_loss;
+                let visits = self.visits(idx) + pending;
+                let parent_q = if visits == 0. { 0. } else { (self.value(idx) - pending) / visits };
+                // The value of idx is from the view of the player who moved into it
+                let parent_q = self.value_for_mover(idx, self.nodes[idx].state.get_player(), parent_q);
+                let visited_policy: f32 = self
+                    .children(idx)
+                    .filter(|&child_idx| self.visits(child_idx) > 0. || self.virtual_loss(child_idx) > 0)
+                    .map(|child_idx| self.policies[child_idx])
+                    .sum();
+                parent_q - reduction * visited_policy.sqrt()
+            }
+        }
+    }
+
+    // Every search passing through a node counts as a lost visit of weight virtual_loss until it is backed up
+    // exploration is c_puct * sqrt(N_parent), fpu_q is the Q of an unvisited child
+    #[inline]
+    fn puct(&self, idx: usize, exploration: f32, fpu_q: f32, virtual_loss: f32) -> f32 {
+        let pending = self.virtual_loss(idx) as f32 * virtual_loss;
+        let visits = self.visits(idx) + pending;
+        let q_value = if visits == 0. { fpu_q } else { (self.value(idx) - pending) / visits };
+        q_value + (exploration / (visits + 1.)) * self.policies[idx]
+    }
+
+    // Index of the child with the highest score, the last one wins ties
+    #[inline]
+    fn select_child(&self, idx: usize, score: impl Fn(usize) -> f32) -> Option<usize> {
+        let mut best: Option<(usize, f32)> = None;
+        for child_idx in self.nodes[idx].children.clone() {
+            let child_score = score(child_idx);
+            match best {
+                Some((_, best_score)) if child_score < best_score => {}
+                _ => best = Some((child_idx, child_score)),
+            }
+        }
+        best.map(|(child_idx, _)| child_idx)
+    }
+
+    pub fn select_alpha(&self, idx: usize, puct: &PuctConfig) -> usize {
+        self.select_alpha_virtual(idx, puct, 0.)
+    }
+
+    fn select_alpha_virtual(&self, idx: usize, puct: &PuctConfig, virtual_loss: f32) -> usize {
+        let parent_visits = self.visits(idx) + self.virtual_loss(idx) as f32 * virtual_loss;
+        let exploration = puct.c_puct(parent_visits) * parent_visits.sqrt();
+        let fpu_q = self.fpu_value(idx, puct, virtual_loss);
+        self.select_child(idx, |child_idx| self.puct(child_idx, exploration, fpu_q, virtual_loss))
+            .expect("select_alpha called on node without children!")
+    }
+
+    /**
+     * Selects a leaf under node_idx with PUCT and adds a virtual loss to every node on the path,
+     * so that searches running at the same time are pushed towards other leaves.
+     * The virtual loss is removed by backpropagate_virtual.
+     */
+    pub fn select_leaf_virtual(&self, node_idx: usize, puct: &PuctConfig, virtual_loss: f32) -> usize {
+        let mut idx = node_idx;
+        self.virtual_losses[idx].fetch_add(1, Ordering::Relaxed);
+        while !self.nodes[idx].children.is_empty() {
+            idx = self.select_alpha_virtual(idx, puct, virtual_loss);
+            self.virtual_losses[idx].fetch_add(1, Ordering::Relaxed);
+        }
+        idx
+    }
+
+    pub fn select_ucb(&self, idx: usize, c: f32) -> usize {
+        self.select_child(idx, |child_idx| self.ucb(child_idx, c))
+            .expect("select_ucb called on node without children!")
+    }
+
+    pub fn select_most_visits(&self, idx: usize) -> Option<usize> {
+        self.select_child(idx, |child_idx| self.visits(child_idx))
+    }
+
+    pub fn select_win_pct(&self, idx: usize) -> Option<usize> {
+        self.select_child(idx, |child_idx| self.win_pct(child_idx))
+    }
+
+    // Creates a child for every expandable move of the node, prior gives the policy of each move
+    // Expanding a node again keeps its children
+    fn expand_with(&mut self, idx: usize, mut prior: impl FnMut(&T, &T::Move) -> f32) -> Range<usize> {
+        if self.nodes[idx].is_fully_expanded() {
+            return self.children(idx);
+        }
+        let moves = std::mem::take(&mut self.nodes[idx].expandable_moves);
+        let state = self.nodes[idx].state;
+        let start = self.nodes.len();
+        for action in moves {
+            let policy = prior(&state, &action);
+            let mut next_state = state;
+            next_state.apply_move(&action);
+            self.add_node(next_state, Some(idx), Some(action), policy);
+        }
+        self.nodes[idx].children = start..self.nodes.len();
+        self.children(idx)
+    }
+
+    pub fn expand(&mut self, idx: usize) -> Range<usize> {
+        self.expand_with(idx, |_, _| 0.)
+    }
+
+    pub fn alpha_expand(&mut self, idx: usize, policy: &[f32]) -> Range<usize> {
+        self.expand_with(idx, |state, action| policy[state.encode(action) as usize])
+    }
+
+    pub fn alpha_expand_tensor(&mut self, idx: usize, policy: &Tensor) -> Range<usize> {
+        self.expand_with(idx, |state, action| policy.double_value(&[state.encode(action).into()]) as f32)
+    }
+
+    /**
+     * Adds a visit with the value of the leaf idx to every node from idx up to its root.
+     * result is from the view of the player to move at idx (a network value or exact_value),
+     * it is negated for the nodes whose mover is the other player, the second play of a double keeps its sign.
+     */
+    pub fn backpropagate(&mut self, idx: usize, result: f32) {
+        let player = self.nodes[idx].state.get_player();
+        let mut curr = Some(idx);
+        while let Some(node_idx) = curr {
+            let visits = self.visits(node_idx);
+            let value = self.value(node_idx) + self.value_for_mover(node_idx, player, result);
+            self.visits[node_idx].set(visits + 1.);
+            self.values[node_idx].set(value);
+            curr = self.nodes[node_idx].parent;
+        }
+    }
+
+    /**
+     * Same as backpropagate for a leaf selected with select_leaf_virtual, also removes its virtual loss.
+     * Can be called from multiple threads at once.
+     */
+    pub fn backpropagate_virtual(&self, idx: usize, result: f32) {
+        let player = self.nodes[idx].state.get_player();
+        let mut curr = Some(idx);
+        while let Some(node_idx) = curr {
+            self.visits[node_idx].add(1.);
+            self.values[node_idx].add(self.value_for_mover(node_idx, player, result));
+            self.virtual_losses[node_idx].fetch_sub(1, Ordering::Relaxed);
+            curr = self.nodes[node_idx].parent;
+        }
+    }
+
+    /**
+     * Searches the descendants of node_idx up to max_depth levels down for a node with the same position as state
+     *
+     * Positions are matched by LearnableGame::position_id, the board and the player to move without the roll,
+     * of the matching nodes one rolled the same as state (the same LearnableGame::position_key) is preferred.
+     * The tree rolls the dice at random, so after a turn of the opponent the node found is usually rolled differently,
+     * NodeStore::reroot keeps the statistics of such a node and expands it again for the roll of state.
+     */
+    pub fn find_descendant(&self, node_idx: usize, state: &T, max_depth: usize) -> Option<usize> {
+        let key = state.position_key();
+        let position_id = state.position_id();
+        let mut frontier = vec![node_idx];
+        for _ in 0..max_depth {
+            frontier = frontier
+                .iter()
+                .flat_map(|&idx| self.children(idx))
+                .collect_vec();
+            let same_position = frontier
+                .iter()
+                .copied()
+                .filter(|&idx| self.nodes[idx].state.position_id() == position_id)
+                .collect_vec();
+            let same_roll = same_position.iter().find(|&&idx| self.nodes[idx].state.position_key() == key);
+            if let Some(&found_idx) = same_roll.or(same_position.first()) {
+                return Some(found_idx);
+            }
+        }
+        None
+    }
+
+    // Copies the node with its edge statistics from another store, without its children
+    fn copy_node(&mut self, other: &NodeStore<T>, other_idx: usize, parent: Option<usize>) -> usize {
+        let idx = self.nodes.len();
+        let mut node = other.nodes[other_idx].clone();
+        node.idx = idx;
+        node.parent = parent;
+        node.children = 0..0;
+        self.nodes.push(node);
+        self.visits.push(AtomicF32::new(other.visits(other_idx)));
+        self.values.push(AtomicF32::new(other.value(other_idx)));
+        self.policies.push(other.policies[other_idx]);
+        self.virtual_losses.push(AtomicU32::new(0));
+        idx
+    }
+
+    /**
+     * Builds a new store to search the given states, the root of states[i] is placed at index i.
+     * If roots[i] is Some, the subtree under that node is kept with its visits and values
+     * (the value of the new root is turned to the view of its player to move),
+     * otherwise a new root is created for states[i].
+     * A node rolled differently than states[i] only keeps its own visits and value, its children were found for the
+     * other roll and it is left to be expanded again for the roll of states[i].
+     * Every other node is dropped and the kept nodes are compacted after the roots.
+     */
+    pub fn reroot(&self, roots: &[Option<usize>], states: &[T]) -> NodeStore<T> {
+        assert_eq!(roots.len(), states.len(), "reroot expects a root for each state");
+        let mut new_store = NodeStore::new();
+        // (old idx, new idx) of the kept nodes, children of each node are copied next to each other
+        let mut kept: Vec<(usize, usize)> = vec![];
+
+        for (root, state) in roots.iter().zip(states.iter()) {
+            match root {
+                Some(old_idx) => {
+                    let new_idx = new_store.copy_node(self, *old_idx, None);
+                    // A root's value is from the view of its own player instead of the player who moved into it
+                    let root_value = self.value_for_mover(*old_idx, state.get_player(), self.value(*old_idx));
+                    new_store.set_value(new_idx, root_value);
+                    let node = new_store.get_node_as_mut(new_idx);
+                    node.action_taken = None;
+                    node.state = *state;
+                    if self.nodes[*old_idx].state.position_key() == state.position_key() {
+                        kept.push((*old_idx, new_idx));
+                    } else {
+                        node.expandable_moves = state.get_valid_moves();
+                    }
+                }
+                None => {
+                    new_store.add_node(*state, None, None, 0.0);
+                }
+            }
+        }
+
+        let mut next = 0;
+        while next < kept.len() {
+            let (old_idx, new_idx) = kept[next];
+            next += 1;
+            let start = new_store.len();
+            for old_child_idx in self.children(old_idx) {
+                let child_idx = new_store.copy_node(self, old_child_idx, Some(new_idx));
+                kept.push((old_child_idx, child_idx));
+            }
+            new_store.nodes[new_idx].children = start..new_store.len();
+        }
+        new_store
+    }
+
     pub fn pretty_print(&self, index: usize, depth: usize, c: f32) {
         self._pretty_print(index, depth, 0, c)
     }
@@ -74,7 +459,7 @@ impl <T: LearnableGame> NodeStore<T> {
             return;
         }
     
-        let node = self.get_node(index);
+        let node = self.get_node_ref(index);
         let indent = "  ".repeat(current_depth);
     
         println!(
@@ -82,14 +467,14 @@ impl <T: LearnableGame> NodeStore<T> {
             indent,
             index,
             node.action_taken,
-            node.visits,
-            node.value,
-            node.ucb(self, c),
-            node.win_pct()
+            self.visits(index),
+            self.value(index),
+            self.ucb(index, c),
+            self.win_pct(index)
         );
     
-        for &child_index in &node.children {
+        for child_index in node.children.clone() {
             self._pretty_print(child_index, depth, current_depth + 1, c);
         }
     }
-}
\ No newline at end of file
+}
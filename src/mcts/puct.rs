@@ -0,0 +1,54 @@
+use config::{Config, ConfigError};
+
+/*
+    Value used as the Q of children that have not been visited yet (first play urgency)
+        - Absolute: a fixed value
+        - Reduction: the mean value of the parent minus reduction * sqrt(sum of the policies of the visited children)
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fpu {
+    Absolute(f32),
+    Reduction(f32),
+}
+
+/*
+    Parameters of q + c_puct * sqrt(N_parent) / (1 + N) * P
+    c_puct is c if c_base is 0, otherwise it grows with the visits of the parent as in AlphaZero:
+        c_puct = c + ln((1 + N_parent + c_base) / c_base)
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct PuctConfig {
+    pub c: f32,
+    pub c_base: f32,
+    pub fpu: Fpu,
+}
+
+impl PuctConfig {
+    // Constant c with unvisited children valued at 0
+    pub fn constant(c: f32) -> Self {
+        PuctConfig { c, c_base: 0., fpu: Fpu::Absolute(0.) }
+    }
+
+    pub fn from_config(conf: &Config) -> Result<Self, ConfigError> {
+        let fpu_value = conf.get_float("fpu_value")? as f32;
+        let fpu = match conf.get_string("fpu")?.to_ascii_lowercase().as_str() {
+            "absolute" => Fpu::Absolute(fpu_value),
+            "reduction" => Fpu::Reduction(fpu_value),
+            other => return Err(ConfigError::Message(format!("fpu must be 'absolute' or 'reduction', got '{}'", other))),
+        };
+        Ok(PuctConfig {
+            c: conf.get_float("exploration_const")? as f32,
+            c_base: conf.get_float("c_puct_base")? as f32,
+            fpu,
+        })
+    }
+
+    #[inline]
+    pub fn c_puct(&self, parent_visits: f32) -> f32 {
+        if self.c_base > 0. {
+            self.c + ((1. + parent_visits + self.c_base) / self.c_base).ln()
+        } else {
+            self.c
+        }
+    }
+}
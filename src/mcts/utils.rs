@@ -14,7 +14,7 @@ pub fn get_prob_tensor<T: LearnableGame>(
     root_node_idx: usize,
     store: &NodeStore<T>
 ) -> Option<Tensor> {
-    let children = store.get_node(root_node_idx).children;
+    let children = store.children(root_node_idx);
     if children.is_empty() {
         return None;
     }
@@ -22,10 +22,10 @@ pub fn get_prob_tensor<T: LearnableGame>(
     let mut idxs: Vec<i64> = vec![];
     let mut visits: Vec<f32> = vec![];
     for child in children {
-        let child_node = store.get_node(child);
-        let encoded_action = state.encode(&child_node.action_taken.unwrap()) as i64;
+        let child_node = store.get_node_ref(child);
+        let encoded_action = state.encode(child_node.action_taken.as_ref().unwrap()) as i64;
         idxs.push(encoded_action);
-        visits.push(child_node.visits)
+        visits.push(store.visits(child))
     }
     let visits_tensor = Tensor::from_slice(&visits).to_device(*DEVICE);
     let indices_tensor = Tensor::from_slice(&idxs).to_device(*DEVICE);
@@ -42,10 +42,10 @@ pub fn get_prob_tensor<T: LearnableGame>(
 pub fn get_prob_tensor_parallel<T: LearnableGame>(nodes: &[&Node<T>], store: &NodeStore<T>) -> Tensor {
     let mut result = Tensor::zeros([nodes.len() as i64, T::ACTION_SPACE_SIZE], (DEFAULT_TYPE, *DEVICE));
     let (xs, ys, vals): (Vec<i32>, Vec<i32>, Vec<f32>) = multiunzip(nodes.iter().enumerate().flat_map(|(processed_idx, &node)| {
-        node.children.iter().map(move |&child_idx| {
-            let child_node = store.get_node(child_idx);
+        node.children.clone().map(move |child_idx| {
+            let child_node = store.get_node_ref(child_idx);
             // Idx of tensor N_i, the expandable move encoded, the value
-            (processed_idx as i32, node.state.encode(&child_node.action_taken.unwrap()) as i32, child_node.visits)
+            (processed_idx as i32, node.state.encode(child_node.action_taken.as_ref().unwrap()) as i32, store.visits(child_idx))
         })
     }));
     let xs_tensor = Tensor::from_slice(&xs);
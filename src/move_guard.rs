@@ -0,0 +1,179 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use config::{Config, ConfigError};
+use itertools::Itertools;
+use rand::{seq::SliceRandom, thread_rng};
+use serde::Serialize;
+
+use crate::{base::LearnableGame, Error};
+
+/*
+Checks the moves picked by the agents of the play loops (versus and self play) before they are applied.
+A move that is not valid in its position, e.g. because of an encoding bug, is written to a diagnostics file
+as a JSON line and then handled by the policy instead of stopping the whole batch:
+    - Skip: the player skips the turn
+    - Replace: a uniformly random valid move is played instead, the turn is skipped if there is none
+    - Panic: stops as the play loops used to, after the move is recorded
+The empty move is only valid when there are no valid moves.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IllegalMovePolicy {
+    Skip,
+    Replace,
+    Panic,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveGuardConfig {
+    pub policy: IllegalMovePolicy,
+    // JSON lines file the illegal moves are appended to, None to not record them.
+    // A relative path is relative to the directory of the run, see MoveGuardConfig::in_dir
+    pub log_path: Option<PathBuf>,
+}
+
+impl Default for MoveGuardConfig {
+    fn default() -> Self {
+        MoveGuardConfig { policy: IllegalMovePolicy::Replace, log_path: None }
+    }
+}
+
+impl MoveGuardConfig {
+    pub fn from_config(conf: &Config) -> Result<Self, ConfigError> {
+        let policy = match conf.get_string("illegal_move_policy")?.to_ascii_lowercase().as_str() {
+            "skip" => IllegalMovePolicy::Skip,
+            "replace" => IllegalMovePolicy::Replace,
+            "panic" => IllegalMovePolicy::Panic,
+            other => {
+                return Err(ConfigError::Message(format!(
+                    "illegal_move_policy must be one of 'skip', 'replace' or 'panic', got '{}'",
+                    other
+                )))
+            }
+        };
+        let log_path = conf.get_string("illegal_move_log")?;
+        Ok(MoveGuardConfig {
+            policy,
+            log_path: if log_path.is_empty() { None } else { Some(PathBuf::from(log_path)) },
+        })
+    }
+
+    // The config with a relative log path resolved against dir, the directory the play loop saves its output to
+    pub fn in_dir(&self, dir: &Path) -> Self {
+        MoveGuardConfig { policy: self.policy, log_path: self.log_path.as_ref().map(|path| dir.join(path)) }
+    }
+}
+
+// What to do with the move picked by an agent
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuardedMove<M> {
+    Play(M),
+    Skip,
+}
+
+// A line of the diagnostics file
+#[derive(Debug, Serialize)]
+#[serde(bound = "")]
+pub struct IllegalMoveRecord<T: LearnableGame> {
+    // The play loop the move was picked in, e.g. "versus" or "self_play"
+    pub context: String,
+    pub position_id: String,
+    pub state: T,
+    pub roll: Option<(u8, u8)>,
+    pub action: T::Move,
+    // Index of the action and the move the index decodes to, None where encode or decode fail
+    pub encoded: Option<u32>,
+    pub decoded: Option<T::Move>,
+    // The valid moves with their indices
+    pub valid_moves: Vec<(T::Move, u32)>,
+    pub policy: IllegalMovePolicy,
+    // The move played instead, None if the turn was skipped
+    pub replaced_by: Option<T::Move>,
+}
+
+// Runs f, None if it panics. The encoding asserts on moves it can not encode
+pub(crate) fn catch_panic<R>(f: impl FnOnce() -> R) -> Option<R> {
+    panic::catch_unwind(AssertUnwindSafe(f)).ok()
+}
+
+impl <T: LearnableGame> IllegalMoveRecord<T> {
+    pub fn new(context: &str, state: &T, action: &T::Move, policy: IllegalMovePolicy, replaced_by: Option<T::Move>) -> Self {
+        let encoded = catch_panic(|| state.encode(action));
+        IllegalMoveRecord {
+            context: context.to_string(),
+            position_id: state.position_id(),
+            state: *state,
+            roll: state.get_roll(),
+            action: action.clone(),
+            encoded,
+            decoded: encoded.and_then(|idx| catch_panic(|| state.decode(idx))),
+            valid_moves: state.get_valid_moves().into_iter().map(|valid| (valid.clone(), state.encode(&valid))).collect_vec(),
+            policy,
+            replaced_by,
+        }
+    }
+
+    // Appends the record as a line of JSON to the file at path
+    pub fn append_to(&self, path: &Path) -> Result<(), Error> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+pub struct MoveGuard {
+    config: MoveGuardConfig,
+    context: String,
+    // Number of illegal moves seen
+    n_illegal: usize,
+}
+
+impl MoveGuard {
+    pub fn new(config: &MoveGuardConfig, context: &str) -> Self {
+        MoveGuard { config: config.clone(), context: context.to_string(), n_illegal: 0 }
+    }
+
+    pub fn n_illegal(&self) -> usize {
+        self.n_illegal
+    }
+
+    /**
+     * Returns the move to play for the action picked in state, the action itself if it is valid.
+     * Otherwise the action is recorded and the move is skipped or replaced according to the policy
+     */
+    pub fn check<T: LearnableGame>(&mut self, state: &T, action: &T::Move) -> GuardedMove<T::Move> {
+        let valid_moves = state.get_valid_moves();
+        if valid_moves.contains(action) {
+            return GuardedMove::Play(action.clone());
+        }
+        if valid_moves.is_empty() && *action == T::EMPTY_MOVE {
+            return GuardedMove::Skip;
+        }
+
+        self.n_illegal += 1;
+        let replaced_by = match self.config.policy {
+            IllegalMovePolicy::Replace => valid_moves.choose(&mut thread_rng()).cloned(),
+            IllegalMovePolicy::Skip | IllegalMovePolicy::Panic => None,
+        };
+        if let Some(path) = &self.config.log_path {
+            let record = IllegalMoveRecord::new(&self.context, state, action, self.config.policy, replaced_by.clone());
+            if let Err(e) = record.append_to(path) {
+                eprintln!("unable to record illegal move to {}: {}", path.display(), e);
+            }
+        }
+        match (self.config.policy, replaced_by) {
+            (IllegalMovePolicy::Panic, _) => panic!(
+                "{}",
+                Error::IllegalMove { position_id: state.position_id(), action: format!("{:?}", action) }
+            ),
+            (_, Some(replacement)) => GuardedMove::Play(replacement),
+            (_, None) => GuardedMove::Skip,
+        }
+    }
+}
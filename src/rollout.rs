@@ -0,0 +1,301 @@
+use std::{fmt, sync::Arc};
+
+use config::{Config, ConfigError};
+use itertools::Itertools;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::Serialize;
+
+use crate::{
+    alphazero::net_agents::{evaluate_states, policy_actions, value_actions},
+    backgammon::{bearoff::BearoffDatabase, dice::{DiceSource, SeededDice}},
+    base::LearnableGame,
+    evaluator::Evaluator,
+    versus::Agent,
+    Error,
+};
+
+/*
+Rollouts estimate the equity of a position by playing it out many times with a cheap agent:
+    - Random: a uniformly random valid move
+    - Policy: the most likely valid move of the policy head
+    - Value: the valid move with the best value head equity (1-ply)
+All games are played in lockstep so the agents evaluate the positions of every game in a single batch.
+
+    - Dice: game i rolls with dice seeded by seed + i, so a rollout is reproducible
+    - Stratification: the first two rolls of the dice are not drawn but spread evenly over the 36 rolls,
+      every 1296 games play each pair of first rolls exactly once (see stratified_rolls). These are the rolls
+      of the first two turns, or of the two turns after the roll of the position if it is already rolled
+    - Variance reduction: each roll is scored by its luck, the value of the position after the roll
+      minus the mean value over all rolls (see LearnableGame::chance_outcomes). Removing the luck of a game
+      from its result keeps the mean but removes most of the noise of the dice
+    - Games that reach max_turns are scored by the evaluator, games reaching a position with an exact value
+      (see LearnableGame::exact_value and RolloutConfig::bearoff) end with it
+Values are from the perspective of the player to move in the rolled out position.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RolloutConfig {
+    pub n_games: usize,
+    // Max number of moves played in a game, a double counts as two moves
+    pub max_turns: usize,
+    pub seed: u64,
+    pub stratify: bool,
+    pub variance_reduction: bool,
+    // Database bear-off races are ended with, loaded from bearoff_db_path by the caller
+    pub bearoff: Option<Arc<BearoffDatabase>>,
+}
+
+impl RolloutConfig {
+    pub fn from_config(conf: &Config) -> Result<Self, ConfigError> {
+        Ok(RolloutConfig {
+            n_games: conf.get_int("rollout_games")? as usize,
+            max_turns: conf.get_int("rollout_max_turns")? as usize,
+            seed: conf.get_int("rollout_seed")? as u64,
+            stratify: conf.get_bool("rollout_stratify")?,
+            variance_reduction: conf.get_bool("rollout_variance_reduction")?,
+            bearoff: None,
+        })
+    }
+
+    // Value of the state for its player to move if it is known without evaluating (see LearnableGame::exact_value)
+    pub fn exact_value<T: LearnableGame>(&self, state: &T) -> Option<f32> {
+        state.exact_value(self.bearoff.as_deref())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RolloutResult {
+    pub n_games: usize,
+    // Mean result of the games with the luck removed, the same as raw_equity without variance reduction
+    pub equity: f32,
+    pub std_error: f32,
+    // Mean result of the games as they were played
+    pub raw_equity: f32,
+    pub raw_std_error: f32,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    // Games that were scored by a value instead of being played to the end
+    pub estimated: usize,
+}
+
+impl fmt::Display for RolloutResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Games: {}", self.n_games)?;
+        writeln!(f, "Equity: {:.4} ± {:.4}\tRaw equity: {:.4} ± {:.4}", self.equity, self.std_error, self.raw_equity, self.raw_std_error)?;
+        write!(f, "Wins: {}\tDraws: {}\tLosses: {}\tEstimated: {}", self.wins, self.draws, self.losses, self.estimated)
+    }
+}
+
+// The 36 rolls of two dice, each order is a separate roll
+const N_ROLLS: usize = 36;
+
+fn nth_roll(idx: usize) -> (u8, u8) {
+    ((idx / 6) as u8 + 1, (idx % 6) as u8 + 1)
+}
+
+/**
+ * The first two rolls of the dice of the game_idx-th game of a stratified rollout, a roll the position already has is kept.
+ * The second roll is shifted by 7 per game, so both rolls are spread evenly over every 36 games as well
+ */
+pub fn stratified_rolls(game_idx: usize) -> [(u8, u8); 2] {
+    let first = game_idx % N_ROLLS;
+    let second = (game_idx / N_ROLLS + 7 * first) % N_ROLLS;
+    [nth_roll(first), nth_roll(second)]
+}
+
+// Seeded dice that start with the stratified rolls
+struct RolloutDice {
+    // In reverse order, the next roll is last
+    stratified: Vec<(u8, u8)>,
+    dice: SeededDice,
+}
+
+impl DiceSource for RolloutDice {
+    fn roll(&mut self) -> (u8, u8) {
+        self.stratified.pop().unwrap_or_else(|| self.dice.roll())
+    }
+}
+
+struct RolloutGame<T: LearnableGame> {
+    state: T,
+    dice: RolloutDice,
+    // Picks the moves of the random agent
+    rng: StdRng,
+    // Total luck of the rolls for the player of the rolled out position
+    luck: f32,
+    result: Option<f32>,
+    estimated: bool,
+}
+
+impl <T: LearnableGame> RolloutGame<T> {
+    fn new(state: &T, game_idx: usize, config: &RolloutConfig) -> Self {
+        let seed = config.seed.wrapping_add(game_idx as u64);
+        let stratified = if config.stratify { stratified_rolls(game_idx).into_iter().rev().collect_vec() } else { vec![] };
+        RolloutGame {
+            state: *state,
+            dice: RolloutDice { stratified, dice: SeededDice::new(seed) },
+            // A different seed than the dice, so the moves do not follow the rolls
+            rng: StdRng::seed_from_u64(!seed),
+            luck: 0.,
+            result: None,
+            estimated: false,
+        }
+    }
+}
+
+// Value of each state for its player to move, exact values are used where known
+fn estimate<T: LearnableGame>(evaluator: &impl Evaluator<T>, states: &[T], config: &RolloutConfig) -> Vec<f32> {
+    let exact = states.iter().map(|state| config.exact_value(state)).collect_vec();
+    let unknown = states.iter().zip(exact.iter()).filter(|(_, exact)| exact.is_none()).map(|(state, _)| *state).collect_vec();
+    let mut values = evaluate_states(evaluator, &unknown).into_iter();
+    exact.into_iter().map(|exact| exact.unwrap_or_else(|| values.next().unwrap())).collect_vec()
+}
+
+// Sign that turns a value for the player to move into a value for the player of the rolled out position
+fn perspective<T: LearnableGame>(state: &T, player: i8) -> f32 {
+    if state.get_player() == player { 1. } else { -1. }
+}
+
+// The state has no roll yet, the player to move has to roll before it can move
+pub(crate) fn needs_roll<T: LearnableGame>(state: &T) -> bool {
+    !T::IS_DETERMINISTIC && matches!(state.get_roll(), None | Some((0, 0)))
+}
+
+/**
+ * Rolls the dice for the player to move in the games, adds the luck of each roll if variance_reduction is set
+ * and ends the games whose position has an exact value
+ */
+fn roll_dice<T: LearnableGame>(
+    games: &mut [RolloutGame<T>],
+    game_idxs: &[usize],
+    player: i8,
+    evaluator: &impl Evaluator<T>,
+    config: &RolloutConfig,
+) {
+    for &idx in game_idxs {
+        let game = &mut games[idx];
+        let roll = game.dice.roll();
+        game.state.set_roll(roll);
+    }
+
+    if config.variance_reduction {
+        // The state as rolled followed by every roll of the state
+        let outcomes = game_idxs.iter().map(|&idx| games[idx].state.chance_outcomes()).collect_vec();
+        let states = game_idxs
+            .iter()
+            .zip(outcomes.iter())
+            .flat_map(|(&idx, outcomes)| std::iter::once(games[idx].state).chain(outcomes.iter().map(|(outcome, _)| *outcome)))
+            .collect_vec();
+        let mut values = estimate(evaluator, &states, config).into_iter();
+        for (&idx, outcomes) in game_idxs.iter().zip(outcomes.iter()) {
+            let rolled = values.next().unwrap();
+            let expected: f32 = outcomes.iter().map(|(_, prob)| prob * values.next().unwrap()).sum();
+            let game = &mut games[idx];
+            game.luck += perspective(&game.state, player) * (rolled - expected);
+        }
+    }
+
+    for &idx in game_idxs {
+        let game = &mut games[idx];
+        if let Some(value) = config.exact_value(&game.state) {
+            game.result = Some(perspective(&game.state, player) * value);
+            game.estimated = true;
+        }
+    }
+}
+
+// Mean of the values and its standard error
+fn mean_and_std_error(values: &[f32]) -> (f32, f32) {
+    let n = values.len() as f32;
+    if values.is_empty() {
+        return (0., 0.);
+    }
+    let mean = values.iter().sum::<f32>() / n;
+    if values.len() < 2 {
+        return (mean, 0.);
+    }
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f32>() / (n - 1.);
+    (mean, (variance / n).sqrt())
+}
+
+/**
+ * Plays config.n_games games from the state with the agent, which can be Random, Policy or Value.
+ * The evaluator picks the moves of the Policy and Value agents, scores the luck of the rolls
+ * and the games that reach max_turns. Fails with InvalidInput for the agents that search.
+ */
+pub fn rollout<T: LearnableGame>(state: &T, agent: &Agent, evaluator: &impl Evaluator<T>, config: &RolloutConfig) -> Result<RolloutResult, Error> {
+    if !matches!(agent, Agent::Random | Agent::Policy | Agent::Value) {
+        return Err(Error::InvalidInput(format!("{:?} agents can not play rollouts, use 'random', 'policy' or 'value'", agent)));
+    }
+    let player = state.get_player();
+    let mut games = (0..config.n_games).map(|game_idx| RolloutGame::new(state, game_idx, config)).collect_vec();
+
+    if needs_roll(state) {
+        let game_idxs = (0..games.len()).collect_vec();
+        roll_dice(&mut games, &game_idxs, player, evaluator, config);
+    }
+
+    for turn in 0..=config.max_turns {
+        let active = games.iter().positions(|game| game.result.is_none()).collect_vec();
+        if active.is_empty() {
+            break;
+        }
+        let states = active.iter().map(|&idx| games[idx].state).collect_vec();
+        if turn == config.max_turns {
+            for (&idx, value) in active.iter().zip(estimate(evaluator, &states, config)) {
+                let game = &mut games[idx];
+                game.result = Some(perspective(&game.state, player) * value);
+                game.estimated = true;
+            }
+            break;
+        }
+
+        let actions = match agent {
+            Agent::Random => active
+                .iter()
+                .map(|&idx| {
+                    let game = &mut games[idx];
+                    game.state.get_valid_moves().choose(&mut game.rng).cloned().unwrap_or(T::EMPTY_MOVE)
+                })
+                .collect_vec(),
+            Agent::Policy => policy_actions(evaluator, &states, 0.),
+            _ => value_actions(evaluator, &states),
+        };
+
+        let mut to_roll = vec![];
+        for (&idx, action) in active.iter().zip(actions) {
+            let game = &mut games[idx];
+            let player_before = game.state.get_player();
+            if action == T::EMPTY_MOVE {
+                game.state.skip_turn();
+            } else {
+                game.state.apply_move(&action);
+            }
+            match game.state.check_winner() {
+                Some(winner) => game.result = Some(if winner == player { 1. } else if winner == -player { -1. } else { 0. }),
+                // The roll made by the game is replaced by the roll of the rollout dice
+                None if !T::IS_DETERMINISTIC && game.state.get_player() != player_before => to_roll.push(idx),
+                None => (),
+            }
+        }
+        roll_dice(&mut games, &to_roll, player, evaluator, config);
+    }
+
+    let raw = games.iter().map(|game| game.result.unwrap()).collect_vec();
+    let reduced = games.iter().map(|game| game.result.unwrap() - game.luck).collect_vec();
+    let (raw_equity, raw_std_error) = mean_and_std_error(&raw);
+    let (equity, std_error) = mean_and_std_error(&reduced);
+    let played = games.iter().filter(|game| !game.estimated).collect_vec();
+    Ok(RolloutResult {
+        n_games: games.len(),
+        equity,
+        std_error,
+        raw_equity,
+        raw_std_error,
+        wins: played.iter().filter(|game| game.result == Some(1.)).count(),
+        draws: played.iter().filter(|game| game.result == Some(0.)).count(),
+        losses: played.iter().filter(|game| game.result == Some(-1.)).count(),
+        estimated: games.len() - played.len(),
+    })
+}
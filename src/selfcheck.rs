@@ -0,0 +1,161 @@
+use std::{collections::HashMap, fmt};
+
+use itertools::Itertools;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::Serialize;
+
+use crate::{
+    backgammon::dice::{DiceSource, SeededDice},
+    base::LearnableGame,
+    move_guard::catch_panic,
+    rollout::needs_roll,
+};
+
+/*
+Checks that the encoding of the moves of a game is a bijection between the valid moves of a position and their indices,
+the policy head of the network can only be trained on and played from if it is:
+    - every valid move encodes to an index in 0..ACTION_SPACE_SIZE
+    - decoding the index of a valid move gives the move back
+    - no two valid moves of a position encode to the same index
+Positions are taken from random games played with seeded dice and moves, so a check is reproducible.
+Each position is checked with its roll and with every other roll of the dice (see LearnableGame::chance_outcomes).
+The empty move is not checked, it is never chosen by the policy.
+*/
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(bound = "")]
+pub enum EncodingFault<T: LearnableGame> {
+    // decode(encode(action)) is not the action, encoded or decoded is None if encode or decode panicked
+    RoundTrip { position_id: String, roll: Option<(u8, u8)>, action: T::Move, encoded: Option<u32>, decoded: Option<T::Move> },
+    // The index of the action is not in the action space
+    OutOfRange { position_id: String, roll: Option<(u8, u8)>, action: T::Move, encoded: u32 },
+    // Two valid moves of the position encode to the same index
+    Collision { position_id: String, roll: Option<(u8, u8)>, first: T::Move, second: T::Move, encoded: u32 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(bound = "")]
+pub struct SelfCheckReport<T: LearnableGame> {
+    // Number of positions and rolls checked
+    pub n_positions: usize,
+    // Number of valid moves checked over all positions
+    pub n_moves: usize,
+    pub faults: Vec<EncodingFault<T>>,
+}
+
+impl <T: LearnableGame> SelfCheckReport<T> {
+    pub fn is_ok(&self) -> bool {
+        self.faults.is_empty()
+    }
+}
+
+impl <T: LearnableGame> fmt::Display for SelfCheckReport<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Positions: {}\tMoves: {}\tFaults: {}", self.n_positions, self.n_moves, self.faults.len())?;
+        for fault in self.faults.iter() {
+            match fault {
+                EncodingFault::RoundTrip { position_id, roll, action, encoded, decoded } => writeln!(
+                    f, "\tRound trip\t{} {:?}\t{:?} encodes to {:?}, decodes to {:?}", position_id, roll, action, encoded, decoded
+                )?,
+                EncodingFault::OutOfRange { position_id, roll, action, encoded } => writeln!(
+                    f, "\tOut of range\t{} {:?}\t{:?} encodes to {}, the action space is {}", position_id, roll, action, encoded, T::ACTION_SPACE_SIZE
+                )?,
+                EncodingFault::Collision { position_id, roll, first, second, encoded } => writeln!(
+                    f, "\tCollision\t{} {:?}\t{:?} and {:?} both encode to {}", position_id, roll, first, second, encoded
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Checks the encoding of every valid move of the state as it is rolled.
+ * Returns the faults found and the number of valid moves checked
+ */
+pub fn check_encoding<T: LearnableGame>(state: &T) -> (Vec<EncodingFault<T>>, usize) {
+    let position_id = state.position_id();
+    let roll = state.get_roll();
+    let valid_moves = state.get_valid_moves();
+    let mut faults = vec![];
+    let mut encoded_moves: HashMap<u32, T::Move> = HashMap::new();
+    for action in valid_moves.iter() {
+        let encoded = catch_panic(|| state.encode(action));
+        let decoded = encoded.and_then(|encoded| catch_panic(|| state.decode(encoded)));
+        if decoded.as_ref() != Some(action) {
+            faults.push(EncodingFault::RoundTrip { position_id: position_id.clone(), roll, action: action.clone(), encoded, decoded });
+        }
+        let Some(encoded) = encoded else { continue };
+        if encoded as i64 >= T::ACTION_SPACE_SIZE {
+            faults.push(EncodingFault::OutOfRange { position_id: position_id.clone(), roll, action: action.clone(), encoded });
+        }
+        match encoded_moves.get(&encoded) {
+            Some(first) => faults.push(EncodingFault::Collision {
+                position_id: position_id.clone(),
+                roll,
+                first: first.clone(),
+                second: action.clone(),
+                encoded,
+            }),
+            None => {
+                encoded_moves.insert(encoded, action.clone());
+            }
+        }
+    }
+    (faults, valid_moves.len())
+}
+
+/**
+ * Plays n_games random games and checks the encoding of every position reached, with its roll and every other roll.
+ * Game i plays with dice and moves seeded by seed + i
+ */
+pub fn self_check<T: LearnableGame>(n_games: usize, seed: u64) -> SelfCheckReport<T> {
+    let mut report = SelfCheckReport { n_positions: 0, n_moves: 0, faults: vec![] };
+    let mut check = |state: &T| {
+        let (faults, n_moves) = check_encoding(state);
+        report.n_positions += 1;
+        report.n_moves += n_moves;
+        report.faults.extend(faults);
+    };
+
+    for game_idx in 0..n_games {
+        let game_seed = seed.wrapping_add(game_idx as u64);
+        let mut dice = SeededDice::new(game_seed);
+        // A different seed than the dice, so the moves do not follow the rolls
+        let mut rng = StdRng::seed_from_u64(!game_seed);
+        let mut state = T::new();
+        if needs_roll(&state) {
+            state.set_roll(dice.roll());
+        }
+
+        while state.check_winner().is_none() {
+            check(&state);
+            if !T::IS_DETERMINISTIC {
+                for (outcome, _) in state.chance_outcomes().into_iter().filter(|(outcome, _)| outcome.get_roll() != state.get_roll()) {
+                    check(&outcome);
+                }
+            }
+
+            let player = state.get_player();
+            match state.get_valid_moves().choose(&mut rng) {
+                Some(action) => state.apply_move(action),
+                None => state.skip_turn(),
+            }
+            // The roll made by the game is replaced by a roll of the seeded dice
+            if !T::IS_DETERMINISTIC && state.get_player() != player {
+                state.set_roll(dice.roll());
+            }
+        }
+    }
+    report
+}
+
+// Checks every valid move of each state, the states can come from a proptest strategy or a list of known positions
+pub fn check_states<T: LearnableGame>(states: &[T]) -> SelfCheckReport<T> {
+    let checked = states.iter().map(check_encoding).collect_vec();
+    SelfCheckReport {
+        n_positions: states.len(),
+        n_moves: checked.iter().map(|(_, n_moves)| n_moves).sum(),
+        faults: checked.into_iter().flat_map(|(faults, _)| faults).collect_vec(),
+    }
+}
@@ -0,0 +1,224 @@
+use std::{collections::HashMap, fs, path::Path, sync::OnceLock};
+
+use config::{Config, Environment, File, FileFormat};
+use itertools::Itertools;
+
+use crate::{
+    alphazero::alphazero::{AlphaZeroConfig, OptimizerParams},
+    annotation::ErrorThresholds,
+    rollout::RolloutConfig,
+    Error, MctsConfig,
+};
+
+/*
+The configuration is read in layers, each layer overrides the keys of the layers before it:
+    1. the defaults, the values of config-example.toml
+    2. the config file, optional unless its path is given explicitly
+    3. environment variables named DIE_E_<KEY>, e.g. DIE_E_LEARN_ITERATIONS=10
+    4. key=value overrides, e.g. from --set on the command line
+Values of overrides are read as TOML, so strings do not need quotes but lists and tables can be given as well.
+Every key must be one of the keys of the defaults and in its range, see validate.
+*/
+
+pub const DEFAULTS: &str = include_str!("../config-example.toml");
+pub const ENV_PREFIX: &str = "DIE_E";
+
+// The range a numeric key has to be in
+#[derive(Debug, Clone, Copy)]
+enum Range {
+    // > 0
+    Positive,
+    // >= 0
+    NonNegative,
+    // >= 1
+    AtLeastOne,
+    // In [0, 1]
+    Probability,
+}
+
+const RANGES: &[(&str, Range)] = &[
+    ("temperature", Range::Positive),
+    ("learn_iterations", Range::AtLeastOne),
+    ("num_epochs", Range::AtLeastOne),
+    ("training_batch_size", Range::AtLeastOne),
+    ("self_play_iterations", Range::AtLeastOne),
+    ("num_self_play_batches", Range::AtLeastOne),
+    ("random_opening_moves", Range::NonNegative),
+    ("random_start_fraction", Range::Probability),
+    ("iterations", Range::AtLeastOne),
+    ("exploration_const", Range::NonNegative),
+    ("simulate_round_limit", Range::AtLeastOne),
+    ("dirichlet_alpha", Range::Positive),
+    ("dirichlet_epsilon", Range::Probability),
+    ("dirichlet_alpha_total", Range::NonNegative),
+    ("eval_cache_size", Range::NonNegative),
+    ("leaf_batch_size", Range::AtLeastOne),
+    ("virtual_loss", Range::NonNegative),
+    ("time_limit_ms", Range::NonNegative),
+    ("max_nodes", Range::NonNegative),
+    ("lcb_z", Range::NonNegative),
+    ("c_puct_base", Range::NonNegative),
+    ("gumbel_sampled_moves", Range::AtLeastOne),
+    ("gumbel_c_visit", Range::Positive),
+    ("gumbel_c_scale", Range::Positive),
+    ("search_stats_top_k", Range::NonNegative),
+    ("expectimax_depth", Range::AtLeastOne),
+    ("move_filter_accept", Range::AtLeastOne),
+    ("move_filter_extra", Range::NonNegative),
+    ("move_filter_threshold", Range::NonNegative),
+    ("annotate_doubtful", Range::NonNegative),
+    ("annotate_error", Range::NonNegative),
+    ("annotate_blunder", Range::NonNegative),
+    ("rollout_games", Range::AtLeastOne),
+    ("rollout_max_turns", Range::AtLeastOne),
+    ("rollout_seed", Range::NonNegative),
+    ("wd", Range::NonNegative),
+    ("lr", Range::Positive),
+];
+
+impl Range {
+    fn contains(&self, value: f64) -> bool {
+        match self {
+            Range::Positive => value > 0.,
+            Range::NonNegative => value >= 0.,
+            Range::AtLeastOne => value >= 1.,
+            Range::Probability => (0. ..=1.).contains(&value),
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Range::Positive => "greater than 0",
+            Range::NonNegative => "at least 0",
+            Range::AtLeastOne => "at least 1",
+            Range::Probability => "between 0 and 1",
+        }
+    }
+}
+
+fn invalid(key: &str, message: String) -> Error {
+    Error::InvalidConfig { key: key.to_string(), message }
+}
+
+/**
+ * Splits an override given as key=value
+ */
+pub fn parse_override(arg: &str) -> Result<(String, String), Error> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.trim().to_string())),
+        _ => Err(Error::InvalidInput(format!("Incorrect override {}, expected key=value", arg))),
+    }
+}
+
+fn keys(conf: &Config) -> Result<Vec<String>, Error> {
+    let values: HashMap<String, serde_json::Value> = conf.clone().try_deserialize()?;
+    Ok(values.into_keys().collect_vec())
+}
+
+/**
+ * Builds the configuration from the defaults, the config file at path, the environment and the overrides,
+ * and validates it. The file must exist if required is set.
+ */
+pub fn load_config(path: &Path, required: bool, overrides: &[(String, String)]) -> Result<Config, Error> {
+    load_config_with_env(path, required, overrides, None)
+}
+
+/**
+ * Same as load_config, with the environment variables read from environment instead of the process if given
+ */
+pub fn load_config_with_env(
+    path: &Path,
+    required: bool,
+    overrides: &[(String, String)],
+    environment: Option<HashMap<String, String>>,
+) -> Result<Config, Error> {
+    if required && !path.is_file() {
+        return Err(Error::NotFound(path.to_path_buf()));
+    }
+    let mut builder = Config::builder()
+        .add_source(File::from_str(DEFAULTS, FileFormat::Toml))
+        .add_source(File::new(&path.to_string_lossy(), FileFormat::Toml).required(false))
+        .add_source(Environment::with_prefix(ENV_PREFIX).try_parsing(true).source(environment));
+    for (key, value) in overrides {
+        let as_toml = format!("{} = {}", key, value);
+        builder = if Config::builder().add_source(File::from_str(&as_toml, FileFormat::Toml)).build().is_ok() {
+            builder.add_source(File::from_str(&as_toml, FileFormat::Toml))
+        } else {
+            // Not a TOML value, e.g. an unquoted string
+            builder.set_override(key.as_str(), value.as_str())?
+        };
+    }
+    let conf = builder.build()?;
+    validate(&conf)?;
+    Ok(conf)
+}
+
+// Keys of the defaults, parsed once
+fn default_keys() -> Result<&'static [String], Error> {
+    static DEFAULT_KEYS: OnceLock<Vec<String>> = OnceLock::new();
+    if let Some(defaults) = DEFAULT_KEYS.get() {
+        return Ok(defaults);
+    }
+    let defaults = keys(&Config::builder().add_source(File::from_str(DEFAULTS, FileFormat::Toml)).build()?)?;
+    Ok(DEFAULT_KEYS.get_or_init(|| defaults))
+}
+
+/**
+ * Checks that every key is known, that the numeric keys are in their ranges
+ * and that every section of the configuration can be read, returns the sections
+ */
+pub fn validate(conf: &Config) -> Result<Settings, Error> {
+    let defaults = default_keys()?;
+    if let Some(unknown) = keys(conf)?.into_iter().filter(|key| !defaults.contains(key)).sorted().next() {
+        return Err(invalid(&unknown, String::from("unknown key")));
+    }
+
+    for (key, range) in RANGES {
+        let value = conf.get_float(key).map_err(|e| invalid(key, e.to_string()))?;
+        if !range.contains(value) {
+            return Err(invalid(key, format!("{} must be {}", value, range.describe())));
+        }
+    }
+
+    let thresholds = ErrorThresholds::from_config(conf)?;
+    if thresholds.doubtful > thresholds.error || thresholds.error > thresholds.blunder {
+        return Err(invalid("annotate_error", String::from("thresholds must grow from annotate_doubtful to annotate_blunder")));
+    }
+
+    Settings::read(conf)
+}
+
+// Every section of the configuration
+#[derive(Debug)]
+pub struct Settings {
+    pub alphazero: AlphaZeroConfig,
+    pub mcts: MctsConfig,
+    pub optimizer: OptimizerParams,
+    pub thresholds: ErrorThresholds,
+    pub rollout: RolloutConfig,
+}
+
+impl Settings {
+    pub fn from_config(conf: &Config) -> Result<Self, Error> {
+        validate(conf)
+    }
+
+    fn read(conf: &Config) -> Result<Self, Error> {
+        Ok(Settings {
+            alphazero: AlphaZeroConfig::from_config(conf)?,
+            mcts: MctsConfig::from_config(conf)?,
+            optimizer: OptimizerParams::from_config(conf)?,
+            thresholds: ErrorThresholds::from_config(conf)?,
+            rollout: RolloutConfig::from_config(conf)?,
+        })
+    }
+}
+
+/**
+ * Writes every key of the configuration with its effective value as JSON
+ */
+pub fn save_config(conf: &Config, path: &Path) -> Result<(), Error> {
+    let values: serde_json::Value = conf.clone().try_deserialize()?;
+    fs::write(path, serde_json::to_string_pretty(&values)?)?;
+    Ok(())
+}
@@ -1,6 +1,8 @@
+use std::{collections::hash_map::DefaultHasher, hash::{Hash, Hasher}};
+
 use serde::{Serialize, Deserialize};
 
-use crate::{base::LearnableGame, constants::DEFAULT_TYPE};
+use crate::{base::{LearnableGame, NoPhase}, constants::DEFAULT_TYPE};
 
 #[derive(Clone, Copy, Serialize, Deserialize, Debug)]
 pub struct TicTacToe {
@@ -24,6 +26,7 @@ impl LearnableGame for TicTacToe {
     const N_RES_BLOCKS: i64 = 4;
 
     const IS_DETERMINISTIC: bool = true;
+    type Phase = NoPhase;
 
     fn new() -> Self {
         TicTacToe { player: -1, board: [0; 9], id: 0 }
@@ -33,6 +36,10 @@ impl LearnableGame for TicTacToe {
         String::from("tictactoe")
     }
 
+    fn set_roll(&mut self, _roll: (u8, u8)) {
+        panic!("set_roll called on deterministic game!")
+    }
+
     fn get_valid_moves(&self) -> Vec<Self::Move> {
         let mut moves = vec![];
         for (i, &v) in self.board.iter().enumerate() {
@@ -99,6 +106,40 @@ impl LearnableGame for TicTacToe {
         *action as u32
     }
 
+    fn position_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.board, self.player).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // The 9 cells as x (-1), o (1) or - (empty), followed by the player to move, e.g. x---o----:-1
+    fn position_id(&self) -> String {
+        let cells: String = self.board.iter().map(|&v| match v {
+            -1 => 'x',
+            1 => 'o',
+            _ => '-',
+        }).collect();
+        format!("{}:{}", cells, self.player)
+    }
+
+    fn from_position_id(id: &str) -> Option<Self> {
+        let (cells, player) = id.trim().split_once(':')?;
+        let player: i8 = player.parse().ok()?;
+        if (player != -1 && player != 1) || cells.chars().count() != 9 {
+            return None;
+        }
+        let mut board = [0; 9];
+        for (cell, c) in board.iter_mut().zip(cells.chars()) {
+            *cell = match c {
+                'x' => -1,
+                'o' => 1,
+                '-' => 0,
+                _ => return None,
+            };
+        }
+        Some(TicTacToe { player, board, id: 0 })
+    }
+
     fn get_id(&self) -> usize {
         self.id
     }
@@ -6,7 +6,7 @@ use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use serde::{Serialize, Deserialize};
 use nanoid::nanoid;
 
-use crate::{mcts::{simple_mcts::mct_search, utils::get_prob_tensor_parallel, alpha_mcts::alpha_mcts_parallel, node_store::NodeStore}, alphazero::{alphazero::AlphaZero, nnet::ResNet}, MctsConfig, base::LearnableGame};
+use crate::{expectimax::expectimax_actions, mcts::{simple_mcts::mct_search, alpha_mcts::alpha_mcts_parallel, node_store::NodeStore}, alphazero::{nnet::ResNet, net_agents::{policy_actions, value_actions}}, MctsConfig, backgammon::dice::ScriptedDice, base::LearnableGame, move_guard::{GuardedMove, MoveGuard}, Error};
 
 
 /*
@@ -16,22 +16,89 @@ Small functions to pit basic agents into eachother and display how a game went i
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Agent {
-    Random, Mcts, Model, None
+    Random, Mcts, Model, Policy, Value, Expectimax, None
 }
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Turn<T> {
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MoveStats<M> {
+    pub action: M,
+    pub visits: f32,
+    // Mean value of the move from the view of the player who picked it
+    pub q: f32,
+    pub prior: f32,
+}
+
+// What the search of a Model agent saw at the root when it picked its move
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchStats<M> {
+    // Mean value of the root from the view of its player to move
+    pub root_value: f32,
+    pub root_visits: f32,
+    // The top_k most visited moves, most visited first
+    pub moves: Vec<MoveStats<M>>,
+}
+
+impl <M: Clone> SearchStats<M> {
+    // None if the root has no children or top_k is 0
+    pub fn from_store<T: LearnableGame<Move = M>>(store: &NodeStore<T>, root_idx: usize, top_k: usize) -> Option<Self> {
+        let children = store.children(root_idx);
+        if children.is_empty() || top_k == 0 {
+            return None;
+        }
+        let moves = children
+            .sorted_by(|&a, &b| store.visits(b).partial_cmp(&store.visits(a)).unwrap_or(std::cmp::Ordering::Equal))
+            .take(top_k)
+            .map(|idx| MoveStats {
+                action: store.get_node_ref(idx).action_taken.clone().unwrap(),
+                visits: store.visits(idx),
+                q: if store.visits(idx) > 0. { store.win_pct(idx) } else { 0. },
+                prior: store.policy(idx),
+            })
+            .collect_vec();
+        let root_visits = store.visits(root_idx);
+        Some(SearchStats {
+            root_value: if root_visits > 0. { store.win_pct(root_idx) } else { 0. },
+            root_visits,
+            moves,
+        })
+    }
+}
+
+impl <M: fmt::Debug> fmt::Display for SearchStats<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Root value: {:.4}\tRoot visits: {}", self.root_value, self.root_visits)?;
+        for stats in self.moves.iter() {
+            writeln!(f, "\t{:?}\tvisits: {}\tq: {:.4}\tprior: {:.4}", stats.action, stats.visits, stats.q, stats.prior)?;
+        }
+        Ok(())
+    }
+}
+
+// The action an agent picked and the stats of its search
+type AgentAction<M> = (M, Option<SearchStats<M>>);
+
+// A turn without valid moves has the EMPTY_MOVE as its action
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Turn<M> {
     pub roll: Option<(u8, u8)>,
-    pub action: Vec<T>,
-    pub player: Agent
+    pub action: M,
+    pub player: Agent,
+    // The action was illegal and the move guard skipped the turn, the action is the one the agent picked
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+    // Only recorded for agents that search, see MctsConfig::search_stats_top_k
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<SearchStats<M>>,
 }
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Game<T> {
-    id: String,
-    player1: Agent,
-    player2: Agent,
-    pub turns: Vec<Turn<T>>,
+// LearnableGame already requires serde for the state and its moves
+#[serde(bound = "")]
+pub struct Game<T: LearnableGame> {
+    pub id: String,
+    pub player1: Agent,
+    pub player2: Agent,
+    pub turns: Vec<Turn<T::Move>>,
     pub winner: Agent,
-    initial_state: T 
+    pub initial_state: T 
 }
 
 // impl <T: LearnableGame> DeserializeOwned for Game<T> {
@@ -49,9 +116,47 @@ impl <T: LearnableGame> Game<T> {
             initial_state: state,
         }
     }
+
+    /**
+     * Replays the turns from the initial state and returns the state before each turn followed by the final state,
+     * the roll of each state is the roll recorded in its turn. The dice are scripted from the recorded rolls so
+     * a replay is the same every time, the final state keeps the roll of the last turn.
+     * Fails on the first turn whose action is not valid, a turn may only skip if there is no valid move
+     * or if the move guard skipped its illegal action
+     */
+    pub fn replay(&self) -> Result<Vec<T>, Error> {
+        let mut state = self.initial_state;
+        let mut states = Vec::with_capacity(self.turns.len() + 1);
+        for (turn_idx, turn) in self.turns.iter().enumerate() {
+            if let Some(roll) = turn.roll {
+                state.set_roll(roll);
+            }
+            states.push(state);
+            // The player after this turn plays with the roll recorded in the next turn
+            let next_roll = self.turns.get(turn_idx + 1).unwrap_or(turn).roll;
+            let mut dice = ScriptedDice::new(next_roll.into_iter().collect());
+            let valid_moves = state.get_valid_moves();
+            if turn.skipped || (turn.action == T::EMPTY_MOVE && valid_moves.is_empty()) {
+                state.skip_turn_with(&mut dice);
+            } else if valid_moves.contains(&turn.action) {
+                state.apply_move_with(&turn.action, &mut dice);
+            } else {
+                return Err(Error::IllegalMove { position_id: state.position_id(), action: format!("{:?}", turn.action) });
+            }
+        }
+        states.push(state);
+        Ok(states)
+    }
+
+    // The state before each turn, see Game::replay
+    pub fn states(&self) -> Result<Vec<T>, Error> {
+        let mut states = self.replay()?;
+        states.pop();
+        Ok(states)
+    }
 }
 
-pub fn save_game<T: LearnableGame>(game: &Game<T>, game_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub fn save_game<T: LearnableGame>(game: &Game<T>, game_path: &str) -> Result<(), Error> {
     let path = Path::new(game_path).join(format!("{}.json", &game.id));
     let file = File::create(path)?;
 
@@ -62,7 +167,10 @@ pub fn save_game<T: LearnableGame>(game: &Game<T>, game_path: &str) -> Result<()
     Ok(())
 }
 
-pub fn load_game<T: LearnableGame>(path: PathBuf) -> Result<Game<T>, Box<dyn std::error::Error>> {
+pub fn load_game<T: LearnableGame>(path: PathBuf) -> Result<Game<T>, Error> {
+    if !path.is_file() {
+        return Err(Error::NotFound(path));
+    }
     let file = File::open(path)?;
 
     let _contents = String::new();
@@ -72,49 +180,43 @@ pub fn load_game<T: LearnableGame>(path: PathBuf) -> Result<Game<T>, Box<dyn std
     Ok(game)
 }
 
-pub fn print_game<T: LearnableGame>(path: PathBuf, wait_user_input: bool) -> Result<(), Box<dyn std::error::Error>> {
-    let game: Game<T> = match load_game(path) {
-        Ok(game) => game,
-        Err(e) => panic!("Failed to load the game: {:?}", e),
-    };
+pub fn print_game<T: LearnableGame>(path: PathBuf, wait_user_input: bool) -> Result<(), Error> {
+    let game: Game<T> = load_game(path)?;
     println!("Game ID: {}", game.id);
     println!("Player 1: {:?}, Player 2: {:?}", game.player1, game.player2);
     println!("Game winner: {:?}", game.winner);
 
     println!("Initial State:");
-    let current_state = game.initial_state;
-    println!("{}", current_state.to_pretty_str());
+    println!("{}", game.initial_state.to_pretty_str());
 
-    for turn in game.turns {
+    for (turn, current_state) in game.turns.iter().zip(game.replay()?.into_iter().skip(1)) {
         println!("Player: {:?}", turn.player);
         println!("Roll: {:?}", turn.roll);
-        // current_state.roll = turn.roll;
+        if let Some(search) = &turn.search {
+            println!("Search: {}", search);
+        }
         println!("Action: {:?}", turn.action);
-        // current_state.apply_move(&turn.action);
         println!("State after action has been played:");
         println!("{}", current_state.to_pretty_str());
 
         if wait_user_input {
             println!("Press Enter to continue...");
             let mut input = String::new();
-            std::io::stdin().read_line(&mut input).expect("Failed to read input");
+            std::io::stdin().read_line(&mut input)?;
         }
     }
 
     Ok(())
 }
 
-pub fn load_all_games<T: LearnableGame>(path: PathBuf) -> Result<Vec<Game<T>>, Box<dyn std::error::Error>> {
+pub fn load_all_games<T: LearnableGame>(path: PathBuf) -> Result<Vec<Game<T>>, Error> {
     let mut games = Vec::new();
 
     for entry in fs::read_dir(path)? {
         let entry = entry?;
-        if entry.path().is_file() {
-            let file_name = entry.file_name().into_string().unwrap();
-            if file_name.ends_with(".json") {
-                let game = load_game(entry.path())?;
-                games.push(game);
-            }
+        if entry.path().is_file() && entry.file_name().to_string_lossy().ends_with(".json") {
+            let game = load_game(entry.path())?;
+            games.push(game);
         }
     }
 
@@ -157,7 +259,34 @@ impl Player {
     }
 }
 
-pub fn play<T: LearnableGame>(player1: Player, player2: Player, mcts_config: &MctsConfig, temp: f64) -> PlayResult<T> {
+/**
+ * Checks the action picked by agent with the guard, records the turn in game and plays it on state.
+ * An illegal action is recorded as picked, with the move played instead or as skipped, so the game can be replayed.
+ * Returns false if the turn was skipped
+ */
+pub fn play_turn<T: LearnableGame>(guard: &mut MoveGuard, state: &mut T, game: &mut Game<T>, agent: &Agent, action: &T::Move, search: Option<SearchStats<T::Move>>) -> bool {
+    let n_illegal = guard.n_illegal();
+    let roll = state.get_roll();
+    match guard.check(state, action) {
+        GuardedMove::Play(played) => {
+            game.turns.push(Turn { roll, action: played.clone(), player: agent.clone(), skipped: false, search });
+            state.apply_move(&played);
+            true
+        }
+        GuardedMove::Skip => {
+            let skipped = guard.n_illegal() > n_illegal;
+            game.turns.push(Turn { roll, action: action.clone(), player: agent.clone(), skipped, search });
+            state.skip_turn();
+            false
+        }
+    }
+}
+
+/*
+    Plays the agents of player1 and player2 against each other,
+    illegal moves are recorded in run_dir if mcts_config sets a relative illegal_move_log
+*/
+pub fn play<T: LearnableGame>(player1: Player, player2: Player, mcts_config: &MctsConfig, temp: f64, run_dir: &Path) -> PlayResult<T> {
     println!("\nStarting play!");
     let pb_play = MultiProgress::new();
     let sty = ProgressStyle::with_template(
@@ -188,6 +317,11 @@ pub fn play<T: LearnableGame>(player1: Player, player2: Player, mcts_config: &Mc
     let pb_games =
         pb_play.add(ProgressBar::new(num_games.try_into().unwrap()).with_style(sty.clone()));
 
+    // Search trees of the previous round for each player, reused if tree_reuse is set
+    let mut tree_p1: NodeStore<T> = NodeStore::new();
+    let mut tree_p2: NodeStore<T> = NodeStore::new();
+
+    let mut guard = MoveGuard::new(&mcts_config.move_guard.in_dir(run_dir), "versus");
     let mut round_count = 0;
     while !games.is_empty() {
         pb_games.set_position((num_games - games.len()) as u64);
@@ -203,9 +337,9 @@ pub fn play<T: LearnableGame>(player1: Player, player2: Player, mcts_config: &Mc
         );
         actions_pb.enable_steady_tick(Duration::from_millis(200));
         actions_pb.set_message(format!("Calculating actions for player1: {:?}", player1.player_type));
-        let actions_p1 = get_actions_for_player(&player1, &games_p1, mcts_config, temp);
+        let actions_p1 = get_actions_for_player(&player1, &games_p1, mcts_config, temp, round_count, &mut tree_p1);
         actions_pb.set_message(format!("Calculating actions for player2: {:?}", player2.player_type));
-        let actions_p2 = get_actions_for_player(&player2, &games_p2, mcts_config, temp);
+        let actions_p2 = get_actions_for_player(&player2, &games_p2, mcts_config, temp, round_count, &mut tree_p2);
         actions_pb.set_message("playing moves...");
 
         let actions_and_games = actions_p1
@@ -215,18 +349,14 @@ pub fn play<T: LearnableGame>(player1: Player, player2: Player, mcts_config: &Mc
 
         let mut games_to_remove = vec![];
         round_count += 1;
-        for (action, game) in actions_and_games {
+        for ((action, search), game) in actions_and_games {
             // The key of the game on the games map given on creation
             let initial_idx = game.get_id();
             let (game_mut, curr_game) = games.get_mut(&initial_idx).unwrap();
-
-            if action.eq(&T::EMPTY_MOVE) {
-                game_mut.skip_turn();
+            let agent = if game_mut.get_player() == player_p1 { &player1.player_type } else { &player2.player_type };
+            if !play_turn(&mut guard, game_mut, curr_game, agent, action, search.clone()) {
                 continue;
             }
-            assert!(game_mut.get_valid_moves().contains(action));
-
-            game_mut.apply_move(action);
 
             let winner = match game_mut.check_winner() {
                 Some(winner) => Some(winner),
@@ -252,6 +382,9 @@ pub fn play<T: LearnableGame>(player1: Player, player2: Player, mcts_config: &Mc
             games_played.push(game);
         }
     }
+    if guard.n_illegal() > 0 {
+        println!("Illegal moves: {}", guard.n_illegal());
+    }
     let winrate = wins_p1 / num_games as f64;
     let wins_p1 = wins_p1 as usize;
     let wins_p2 = wins_p2 as usize;
@@ -267,7 +400,9 @@ pub fn play<T: LearnableGame>(player1: Player, player2: Player, mcts_config: &Mc
     }
 }
 
-fn get_actions_for_player<T: LearnableGame>(player: &Player, games: &[T], mcts_config: &MctsConfig, temp: f64) -> Vec<T::Move> {
+// move_number is the number of moves played in the games so far
+// Returns the action of each game with the stats of its search if the agent searches
+fn get_actions_for_player<T: LearnableGame>(player: &Player, games: &[T], mcts_config: &MctsConfig, temp: f64, move_number: usize, tree: &mut NodeStore<T>) -> Vec<AgentAction<T::Move>> {
     if games.is_empty() {
         return vec![];
     }
@@ -275,36 +410,46 @@ fn get_actions_for_player<T: LearnableGame>(player: &Player, games: &[T], mcts_c
     match player.player_type {
         Agent::Model => {
             let model = player.model.as_ref().unwrap();
-            let mut store = NodeStore::new();
-            alpha_mcts_parallel(&mut store, games, model, mcts_config, Some(ProgressBar::hidden()));
-            let roots = store.get_root_nodes();
-            let prob_tensor = get_prob_tensor_parallel(&roots, &store)
-                .pow_(1.0 / temp)
-                .to_device(tch::Device::Cpu);
-
-            roots
-                .iter()
-                .enumerate()
-                .map(|(processed_idx, &root)| {
-                    let curr_prob_tensor = prob_tensor.get(processed_idx as i64);
-
-                    // If prob tensor of the current state is all zeros then skip turn, has_children check just in case
-                    if !curr_prob_tensor.sum(None).is_nonzero() || root.children.is_empty() {
-                        return T::EMPTY_MOVE;
-                    }
-
-                    // Select an action from probabilities
-                    let selected_action = AlphaZero::weighted_select_tensor_idx(&curr_prob_tensor);
-                    // Decode and play selected action
-                    root.state.decode(selected_action as u32)
-                })
-                .collect_vec()
+            let mut store = if mcts_config.tree_reuse && !tree.is_empty() {
+                // Games are matched to the roots of the previous search by their ids,
+                // the current state is at most 4 plies (two turns with doubles) below the previous root
+                let prev_roots: HashMap<usize, usize> = tree
+                    .get_root_nodes()
+                    .iter()
+                    .map(|root| (root.state.get_id(), root.idx))
+                    .collect();
+                let roots = games
+                    .iter()
+                    .map(|game| prev_roots.get(&game.get_id()).and_then(|&root_idx| tree.find_descendant(root_idx, game, 4)))
+                    .collect_vec();
+                tree.reroot(&roots, games)
+            } else {
+                NodeStore::new()
+            };
+            let gumbel_choices = alpha_mcts_parallel(&mut store, games, model, mcts_config, Some(ProgressBar::hidden()));
+            // Roots of the games are at 0..games.len(), a root without children skips its turn
+            let actions = (0..games.len())
+                .map(|root_idx| (
+                    match gumbel_choices[root_idx] {
+                        Some(child_idx) => store.get_node_ref(child_idx).action_taken.clone().unwrap(),
+                        None => mcts_config.move_selection.select_move(&store, root_idx, move_number),
+                    },
+                    SearchStats::from_store(&store, root_idx, mcts_config.search_stats_top_k),
+                ))
+                .collect_vec();
+            if mcts_config.tree_reuse {
+                *tree = store;
+            }
+            actions
         }
-        Agent::Mcts => games
+        Agent::Policy => without_stats(policy_actions(player.model.as_ref().unwrap(), games, temp)),
+        Agent::Value => without_stats(value_actions(player.model.as_ref().unwrap(), games)),
+        Agent::Expectimax => without_stats(expectimax_actions(player.model.as_ref().unwrap(), games, &mcts_config.expectimax)),
+        Agent::Mcts => without_stats(games
             .par_iter()
-            .map(|game| mct_search(*game, game.get_player(), mcts_config))
-            .collect(),
-        Agent::Random => games
+            .map(|game| mct_search(*game, mcts_config))
+            .collect()),
+        Agent::Random => without_stats(games
             .par_iter()
             .map(|game| {
                 let valid_moves = game.get_valid_moves();
@@ -313,7 +458,11 @@ fn get_actions_for_player<T: LearnableGame>(player: &Player, games: &[T], mcts_c
                     None => T::EMPTY_MOVE,
                 }
             })
-            .collect(),
+            .collect()),
         Agent::None => unreachable!(),
     }
-}
\ No newline at end of file
+}
+
+fn without_stats<M>(actions: Vec<M>) -> Vec<AgentAction<M>> {
+    actions.into_iter().map(|action| (action, None)).collect_vec()
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::test_config;
+use die_e::{
+    analysis::{analyze, parse_position, parse_roll, AnalysisSearch},
+    backgammon::backgammon_logic::Backgammon,
+    base::LearnableGame,
+    evaluator::uniform::UniformEvaluator,
+    tictactoe::TicTacToe,
+    Error, MctsConfig,
+};
+
+fn mcts_config() -> MctsConfig {
+    MctsConfig::from_config(&test_config(&["iterations=50"])).unwrap()
+}
+
+#[cfg(test)]
+mod parsing {
+    use super::*;
+
+    #[test]
+    fn position_id_round_trips() {
+        let mut bg = Backgammon::new();
+        bg.board.0[11] -= 1;
+        bg.board.1 = (0, 1);
+        bg.board.0[5] += 1;
+        bg.board.0[4] = -1;
+        bg.player = 1;
+        bg.is_second_play = true;
+        let parsed: Backgammon = parse_position(&bg.position_id()).unwrap();
+        assert_eq!(parsed.board, bg.board);
+        assert_eq!(parsed.player, 1);
+        assert!(parsed.is_second_play);
+        assert!(!Backgammon::from_position_id(&Backgammon::new().position_id()).unwrap().is_second_play);
+
+        let mut ttt = TicTacToe::new();
+        ttt.apply_move(&4);
+        let parsed: TicTacToe = parse_position(&ttt.position_id()).unwrap();
+        assert_eq!(parsed.board, ttt.board);
+        assert_eq!(parsed.get_player(), ttt.get_player());
+    }
+
+    #[test]
+    fn position_id_needs_fifteen_checkers_a_player() {
+        let mut bg = Backgammon::new();
+        bg.board.0[0] += 1;
+        assert!(Backgammon::from_position_id(&bg.position_id()).is_none());
+        bg.board.0[0] -= 1;
+        bg.board.2 = (1, 0);
+        assert!(Backgammon::from_position_id(&bg.position_id()).is_none());
+        assert!(Backgammon::from_position_id(&format!("{}:more", Backgammon::new().position_id())).is_none());
+    }
+
+    #[test]
+    fn position_reads_json() {
+        let ttt = TicTacToe::from_position_id("xo-------:-1").unwrap();
+        let parsed: TicTacToe = parse_position(&serde_json::to_string(&ttt).unwrap()).unwrap();
+        assert_eq!(parsed.board, ttt.board);
+        assert!(matches!(parse_position::<TicTacToe>("xo-:-1"), Err(Error::InvalidInput(_))));
+        assert!(matches!(parse_position::<TicTacToe>("{ \"board\": 1 }"), Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn roll_accepts_common_formats() {
+        assert_eq!(parse_roll("6-4"), Some((6, 4)));
+        assert_eq!(parse_roll("3,1"), Some((3, 1)));
+        assert_eq!(parse_roll("55"), Some((5, 5)));
+        assert_eq!(parse_roll("7-1"), None);
+        assert_eq!(parse_roll("6"), None);
+    }
+}
+
+#[cfg(test)]
+mod analyze_position {
+    use super::*;
+    use super::common::CenterEvaluator;
+
+    #[test]
+    fn it_should_rank_every_valid_move() {
+        let state = TicTacToe::from_position_id("xo-------:-1").unwrap();
+        let analysis = analyze(&state, &UniformEvaluator, &mcts_config(), AnalysisSearch::Value).unwrap();
+        assert_eq!(analysis.moves.len(), state.get_valid_moves().len());
+        assert_eq!(analysis.moves[0].diff, Some(0.));
+        assert!(analysis.moves.iter().all(|analysis| analysis.diff.unwrap() <= 0.));
+        let prior_sum: f32 = analysis.moves.iter().map(|analysis| analysis.prior).sum();
+        assert!((prior_sum - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn mcts_should_rank_by_visits() {
+        let state = TicTacToe::from_position_id("xx-oo----:-1").unwrap();
+        let analysis = analyze(&state, &UniformEvaluator, &mcts_config(), AnalysisSearch::Mcts).unwrap();
+        let visits = analysis.moves.iter().map(|analysis| analysis.visits.unwrap()).collect::<Vec<_>>();
+        assert!(visits.windows(2).all(|pair| pair[0] >= pair[1]));
+        // Completing the row wins on the spot
+        assert_eq!(analysis.moves[0].action, 2);
+    }
+
+    #[test]
+    fn mcts_should_agree_with_value_on_an_obvious_position() {
+        // Only the center has value, taking it is worth 0.5 to x
+        let state = TicTacToe::new();
+        let value = analyze(&state, &CenterEvaluator, &mcts_config(), AnalysisSearch::Value).unwrap();
+        let mcts = analyze(&state, &CenterEvaluator, &mcts_config(), AnalysisSearch::Mcts).unwrap();
+        assert_eq!(value.moves[0].action, 4);
+        assert_eq!(value.moves[0].equity, 0.5);
+        assert_eq!(mcts.moves[0].action, value.moves[0].action);
+        // Q is from the view of x, the player to move at the root
+        assert!(mcts.moves[0].q.unwrap() > 0.);
+        assert!(mcts.moves.iter().skip(1).all(|analysis| !matches!(analysis.diff, Some(diff) if diff >= 0.)));
+    }
+
+    #[test]
+    fn it_should_reject_positions_without_a_roll() {
+        let bg: Backgammon = parse_position(&Backgammon::new().position_id()).unwrap();
+        let analysis = analyze(&bg, &UniformEvaluator, &mcts_config(), AnalysisSearch::Value);
+        assert!(matches!(analysis, Err(Error::InvalidInput(_))));
+    }
+}
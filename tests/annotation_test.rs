@@ -0,0 +1,102 @@
+mod common;
+
+use common::{test_config, CenterEvaluator};
+use die_e::{
+    analysis::AnalysisSearch,
+    annotation::{annotate_game, ErrorClass, ErrorThresholds, PlayerStats},
+    base::LearnableGame,
+    tictactoe::TicTacToe,
+    versus::{Agent, Game, Turn},
+    Error, MctsConfig,
+};
+
+#[cfg(test)]
+mod classify {
+    use super::*;
+
+    #[test]
+    fn it_should_classify_by_equity_loss() {
+        let thresholds = ErrorThresholds::default();
+        assert_eq!(thresholds.classify(0.01), None);
+        assert_eq!(thresholds.classify(0.04), Some(ErrorClass::Doubtful));
+        assert_eq!(thresholds.classify(0.1), Some(ErrorClass::Error));
+        assert_eq!(thresholds.classify(0.5), Some(ErrorClass::Blunder));
+    }
+
+    #[test]
+    fn it_should_count_error_rates() {
+        let thresholds = ErrorThresholds::default();
+        let mut stats = PlayerStats::default();
+        for loss in [0., 0.05, 0.1, 0.2] {
+            stats.add(loss, thresholds.classify(loss));
+        }
+        assert_eq!((stats.decisions, stats.doubtful, stats.errors, stats.blunders), (4, 1, 1, 1));
+        assert_eq!(stats.error_rate(), 0.5);
+
+        let mut total = PlayerStats::default();
+        total.merge(&stats);
+        total.merge(&stats);
+        assert_eq!(total.decisions, 8);
+        assert!((total.mean_equity_loss() - 0.0875).abs() < 1e-6);
+    }
+}
+
+#[cfg(test)]
+mod replay {
+    use super::*;
+
+    #[test]
+    fn it_should_return_the_state_before_each_turn() {
+        let mut game = Game::new(Agent::Random, Agent::Random, TicTacToe::new());
+        for action in [4, 0, 8] {
+            game.turns.push(Turn { roll: None, action, player: Agent::Random, skipped: false, search: None });
+        }
+        let states = game.states().unwrap();
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[0].board, [0; 9]);
+        assert_eq!(states[2].board, [1, 0, 0, 0, -1, 0, 0, 0, 0]);
+        assert_eq!(states[2].get_player(), -1);
+    }
+
+    #[test]
+    fn it_should_fail_on_an_illegal_move() {
+        let mut game = Game::new(Agent::Random, Agent::Random, TicTacToe::new());
+        for action in [4, 4] {
+            game.turns.push(Turn { roll: None, action, player: Agent::Random, skipped: false, search: None });
+        }
+        let mut before = TicTacToe::new();
+        before.apply_move(&4);
+        match game.states() {
+            Err(Error::IllegalMove { position_id, action }) => {
+                assert_eq!(position_id, before.position_id());
+                assert_eq!(action, "4");
+            }
+            other => panic!("expected an illegal move, got {:?}", other.map(|states| states.len())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod annotate {
+    use super::*;
+
+    #[test]
+    fn it_should_find_the_blunders_of_both_players() {
+        // Only the center has value, x gives it away and o takes it
+        let mut game = Game::new(Agent::Random, Agent::Random, TicTacToe::new());
+        for action in [0, 4] {
+            game.turns.push(Turn { roll: None, action, player: Agent::Random, skipped: false, search: None });
+        }
+        let mcts_config = MctsConfig::from_config(&test_config(&["iterations=50"])).unwrap();
+        let thresholds = ErrorThresholds::default();
+        let annotated = annotate_game(&game, &CenterEvaluator, &mcts_config, AnalysisSearch::Mcts, &thresholds).unwrap();
+
+        assert_eq!(annotated.turns[0].best, 4);
+        assert!(annotated.turns[0].equity_loss >= 0.5);
+        assert_eq!(annotated.turns[0].class, Some(ErrorClass::Blunder));
+        assert_eq!(annotated.turns[1].best, 4);
+        assert_eq!((annotated.turns[1].played_rank, annotated.turns[1].class), (1, None));
+        assert_eq!((annotated.stats_p1.decisions, annotated.stats_p1.blunders), (1, 1));
+        assert_eq!((annotated.stats_p2.decisions, annotated.stats_p2.blunders), (1, 0));
+    }
+}
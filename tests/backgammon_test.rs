@@ -924,3 +924,142 @@ mod get_valid_moves {
         assert_eq!(bg.get_valid_moves(), expected);
     }
 }
+
+#[cfg(test)]
+mod dice {
+    use super::*;
+    use die_e::backgammon::dice::{DiceSource, ScriptedDice, SeededDice};
+
+    #[test]
+    fn it_should_roll_next_player_from_scripted_dice() {
+        let mut bg = Backgammon::new();
+        let mut dice = ScriptedDice::new(vec![(3, 1), (6, 5)]);
+        bg.roll_die_with(&mut dice);
+        assert_eq!(bg.roll, (3, 1));
+        bg.apply_move_with(&vec![(7, 4), (5, 4)], &mut dice);
+        assert_eq!(bg.player, 1);
+        assert_eq!(bg.roll, (6, 5));
+        assert_eq!(dice.remaining(), 0);
+    }
+
+    #[test]
+    fn it_should_not_roll_on_first_play_of_doubles() {
+        let mut bg = Backgammon::new();
+        bg.roll = (4, 4);
+        let mut dice = ScriptedDice::new(vec![(2, 1)]);
+        bg.apply_move_with(&vec![(12, 8), (12, 8)], &mut dice);
+        assert!(bg.is_second_play);
+        assert_eq!(bg.roll, (4, 4));
+        assert_eq!(dice.remaining(), 1);
+        bg.skip_turn_with(&mut dice);
+        assert!(!bg.is_second_play);
+        assert_eq!(bg.player, 1);
+        assert_eq!(bg.roll, (2, 1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_should_panic_when_scripted_dice_exhausted() {
+        let mut dice = ScriptedDice::new(vec![(1, 1)]);
+        dice.roll();
+        dice.roll();
+    }
+
+    #[test]
+    fn it_should_roll_same_sequence_for_same_seed() {
+        let mut dice_1 = SeededDice::new(42);
+        let mut dice_2 = SeededDice::new(42);
+        for _ in 0..100 {
+            let roll = dice_1.roll();
+            assert!((1..=6).contains(&roll.0) && (1..=6).contains(&roll.1));
+            assert_eq!(roll, dice_2.roll());
+        }
+    }
+
+    #[test]
+    fn it_should_read_rolls_from_file() {
+        let path = std::env::temp_dir().join(format!("die-e-dice-{}.txt", std::process::id()));
+        std::fs::write(&path, "# opening\n3 1\n\n6,5\n").unwrap();
+        let dice = ScriptedDice::from_file(&path).unwrap();
+        std::fs::write(&path, "3 7\n").unwrap();
+        let invalid = ScriptedDice::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dice, ScriptedDice::new(vec![(3, 1), (6, 5)]));
+        assert!(invalid.is_err());
+    }
+}
+
+#[cfg(test)]
+mod chance_outcomes {
+    use super::*;
+
+    #[test]
+    fn it_should_return_all_rolls_with_probabilities() {
+        let bg = Backgammon::new();
+        let outcomes = bg.chance_outcomes();
+        assert_eq!(outcomes.len(), 21);
+        let total: f32 = outcomes.iter().map(|(_, prob)| prob).sum();
+        assert!((total - 1.).abs() < 1e-5);
+        assert!(outcomes.iter().all(|(state, _)| state.board == bg.board && state.player == bg.player));
+        let doubles = outcomes.iter().filter(|(state, _)| state.roll.0 == state.roll.1).count();
+        assert_eq!(doubles, 6);
+    }
+}
+
+#[cfg(test)]
+mod position {
+    use super::*;
+
+    #[test]
+    fn it_should_count_the_initial_position() {
+        let bg = Backgammon::new();
+        for player in [-1, 1] {
+            assert_eq!(bg.pip_count(player), 167);
+            assert_eq!(bg.blots(player), 0);
+            assert_eq!(bg.made_points(player), 4);
+            assert_eq!(bg.longest_prime(player), 1);
+            assert_eq!(bg.checkers_back(player), 2);
+        }
+        assert!(bg.has_contact());
+    }
+
+    #[test]
+    fn it_should_count_checkers_on_the_bar() {
+        let mut bg = Backgammon::new();
+        bg.board.0[23] = -1;
+        bg.board.1 = (1, 0);
+        assert_eq!(bg.pip_count(-1), 167 + 1);
+        assert_eq!(bg.blots(-1), 1);
+        assert_eq!(bg.checkers_back(-1), 2);
+        assert!(bg.has_contact());
+    }
+
+    #[test]
+    fn it_should_detect_a_race() {
+        let mut bg = Backgammon::new();
+        bg.board.0 = [0; 24];
+        bg.board.0[2] = -2;
+        bg.board.0[20] = 3;
+        assert!(bg.is_race());
+        assert_eq!(bg.pip_count(-1), 6);
+        assert_eq!(bg.pip_count(1), 12);
+
+        bg.board.0[21] = -1;
+        assert!(bg.has_contact());
+    }
+
+    #[test]
+    fn it_should_find_the_longest_prime() {
+        let mut bg = Backgammon::new();
+        bg.board.0 = [0; 24];
+        for point in 3..=7 {
+            bg.board.0[point] = -2;
+        }
+        bg.board.0[9] = -3;
+        bg.board.0[10] = -1;
+        assert_eq!(bg.longest_prime(-1), 5);
+        assert_eq!(bg.made_points(-1), 6);
+        assert_eq!(bg.blots(-1), 1);
+        assert_eq!(bg.longest_prime(1), 0);
+    }
+}
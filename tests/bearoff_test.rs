@@ -0,0 +1,124 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::test_config;
+use die_e::{
+    backgammon::{
+        backgammon_logic::Backgammon,
+        bearoff::{home_counts, BearoffDatabase, MAX_TURNS},
+    },
+    base::LearnableGame,
+    rollout::RolloutConfig,
+    MctsConfig,
+};
+
+#[cfg(test)]
+mod database {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn it_should_index_every_position_once() {
+        let database = BearoffDatabase::generate(3);
+        let mut seen = HashSet::new();
+        for a in 0..=3u8 {
+            for b in 0..=3 - a {
+                for c in 0..=3 - a - b {
+                    let index = database.index(&[a, 0, b, 0, 0, c]).unwrap();
+                    assert!(index < database.len() && seen.insert(index));
+                }
+            }
+        }
+        assert_eq!(database.len(), 84);
+        assert!(database.index(&[4, 0, 0, 0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn it_should_solve_single_checker_races() {
+        let database = BearoffDatabase::generate(2);
+        assert_eq!(database.expected_turns(&[0; 6]), Some(0.));
+        assert!((database.expected_turns(&[2, 0, 0, 0, 0, 0]).unwrap() - 1.).abs() < 1e-5);
+        // A checker on the six point is borne off in one turn unless the roll moves it less than 6 pips
+        let distribution = database.distribution(&[0, 0, 0, 0, 0, 1]).unwrap();
+        assert_eq!(distribution.len(), MAX_TURNS);
+        assert!((distribution[1] - 27. / 36.).abs() < 1e-5);
+        assert!((distribution.iter().sum::<f32>() - 1.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn it_should_round_trip_through_disk() {
+        let database = BearoffDatabase::generate(4);
+        let path = std::env::temp_dir().join(format!("die-e-bearoff-{}.db", std::process::id()));
+        database.save(&path).unwrap();
+        let loaded = BearoffDatabase::load(&path).unwrap();
+        std::fs::write(&path, b"not a database").unwrap();
+        let invalid = BearoffDatabase::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), database.len());
+        let counts = [1, 0, 2, 0, 0, 1];
+        for (a, b) in loaded.distribution(&counts).unwrap().iter().zip(database.distribution(&counts).unwrap()) {
+            assert!((a - b).abs() < 1e-4);
+        }
+        assert!(invalid.is_err());
+    }
+}
+
+#[cfg(test)]
+mod evaluate {
+    use super::*;
+
+    fn race(player_1: [i8; 6], player_2: [i8; 6], player: i8, roll: (u8, u8)) -> Backgammon {
+        let mut points = [0; 24];
+        for (i, (count_1, count_2)) in player_1.iter().zip(player_2).enumerate() {
+            points[i] = -count_1;
+            points[23 - i] = count_2;
+        }
+        let collected_1 = 15 - player_1.iter().sum::<i8>() as u8;
+        let collected_2 = 15 - player_2.iter().sum::<i8>() as u8;
+        let mut state = Backgammon::init_with_fields((points, (0, 0), (collected_1, collected_2)), player, false);
+        state.roll = roll;
+        state
+    }
+
+    #[test]
+    fn it_should_read_home_counts_of_both_players() {
+        let state = race([1, 0, 2, 0, 0, 0], [0, 3, 0, 0, 0, 1], -1, (0, 0));
+        assert_eq!(home_counts(state.board, -1), Some([1, 0, 2, 0, 0, 0]));
+        assert_eq!(home_counts(state.board, 1), Some([0, 3, 0, 0, 0, 1]));
+        assert_eq!(home_counts(Backgammon::new().board, -1), None);
+    }
+
+    #[test]
+    fn it_should_value_races_for_the_player_to_move() {
+        let database = BearoffDatabase::generate(4);
+        // Bears off its last checker with any roll
+        let winning = race([1, 0, 0, 0, 0, 0], [0, 0, 0, 0, 0, 4], -1, (2, 1));
+        assert_eq!(database.evaluate(&winning), Some(1.));
+        // Needs two turns while the opponent bears off with any roll
+        let losing = race([0, 0, 0, 0, 0, 4], [1, 0, 0, 0, 0, 0], -1, (2, 1));
+        assert_eq!(database.evaluate(&losing), Some(-1.));
+
+        let before_roll = race([0, 0, 0, 0, 0, 1], [0, 0, 0, 0, 0, 1], 1, (0, 0));
+        let value = database.evaluate(&before_roll).unwrap();
+        assert!(value > 0. && value < 1.);
+        assert!(database.evaluate(&Backgammon::new()).is_none());
+    }
+
+    #[test]
+    fn it_should_only_value_races_with_the_database_it_is_given() {
+        let database = Arc::new(BearoffDatabase::generate(4));
+        let winning = race([1, 0, 0, 0, 0, 0], [0, 0, 0, 0, 0, 4], -1, (2, 1));
+        assert_eq!(winning.exact_value(None), None);
+        assert_eq!(winning.exact_value(Some(&database)), Some(1.));
+
+        let conf = test_config(&[]);
+        let mut mcts_config = MctsConfig::from_config(&conf).unwrap();
+        let mut rollout_config = RolloutConfig::from_config(&conf).unwrap();
+        assert_eq!((mcts_config.exact_value(&winning), rollout_config.exact_value(&winning)), (None, None));
+        mcts_config.bearoff = Some(database.clone());
+        rollout_config.bearoff = Some(database);
+        assert_eq!((mcts_config.exact_value(&winning), rollout_config.exact_value(&winning)), (Some(1.), Some(1.)));
+    }
+}
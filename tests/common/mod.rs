@@ -0,0 +1,64 @@
+/*
+Proptest strategies, test doubles and configs shared by the test binaries, declared with `mod common;`
+Each binary only uses some of them
+*/
+#![allow(dead_code)]
+
+use std::{collections::HashMap, path::Path};
+
+use config::Config;
+use die_e::{
+    backgammon::{
+        backgammon_logic::Backgammon,
+        generator::{random_position, Phase, PHASES},
+    },
+    base::LearnableGame,
+    constants::DEFAULT_TYPE,
+    evaluator::Evaluator,
+    settings::{load_config_with_env, parse_override},
+    tictactoe::TicTacToe,
+    Error,
+};
+use proptest::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use tch::{Device, Tensor};
+
+// Positions of the phase, each drawn from a seeded rng
+pub fn positions(phase: Phase) -> impl Strategy<Value = Backgammon> {
+    any::<u64>().prop_map(move |seed| random_position(phase, &mut StdRng::seed_from_u64(seed)))
+}
+
+// Positions of any phase
+pub fn any_position() -> impl Strategy<Value = Backgammon> {
+    prop::sample::select(PHASES.to_vec()).prop_flat_map(positions)
+}
+
+// Values a position by the center cell only: 0.5 if the player to move holds it, -0.5 if the opponent does
+pub struct CenterEvaluator;
+
+impl Evaluator<TicTacToe> for CenterEvaluator {
+    fn evaluate(&self, states: &[TicTacToe]) -> (Tensor, Tensor) {
+        let n_states = states.len() as i64;
+        let values = states
+            .iter()
+            .map(|state| (state.board[4] * state.get_player()) as f32 * 0.5)
+            .collect::<Vec<f32>>();
+        (
+            Tensor::full([n_states, TicTacToe::ACTION_SPACE_SIZE], 1. / 9., (DEFAULT_TYPE, Device::Cpu)),
+            Tensor::from_slice(&values).view([n_states, 1]),
+        )
+    }
+}
+
+/**
+ * Config of the defaults (settings::DEFAULTS) with overrides given as key=value, validated like the config of a run.
+ * No config file or environment variable is read, so tests do not depend on where or how they are run.
+ */
+pub fn load_test_config(overrides: &[&str]) -> Result<Config, Error> {
+    let overrides = overrides.iter().map(|arg| parse_override(arg)).collect::<Result<Vec<_>, _>>()?;
+    load_config_with_env(Path::new("./does-not-exist.toml"), false, &overrides, Some(HashMap::new()))
+}
+
+pub fn test_config(overrides: &[&str]) -> Config {
+    load_test_config(overrides).unwrap()
+}
@@ -1,5 +1,7 @@
 extern crate proptest;
 
+mod common;
+
 #[cfg(test)]
 mod encoding_single_and_zero_moves {
     use die_e::backgammon::backgammon_logic::Backgammon;
@@ -90,3 +92,69 @@ mod encoding_exceptional_cases {
         assert_eq!(actions, dec);
     }
 }
+
+#[cfg(test)]
+mod encoding_bijection {
+    use super::common::{any_position, positions};
+    use die_e::{
+        backgammon::{backgammon_logic::Backgammon, generator::Phase},
+        base::LearnableGame,
+        selfcheck::{check_states, self_check},
+        tictactoe::TicTacToe,
+    };
+    use proptest::prelude::*;
+
+    #[test]
+    fn every_valid_move_of_random_games_should_round_trip() {
+        let report = self_check::<Backgammon>(4, 0);
+        assert!(report.n_positions > 0);
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    #[test]
+    fn every_valid_move_of_tictactoe_should_round_trip() {
+        let report = self_check::<TicTacToe>(20, 0);
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    #[test]
+    fn bar_and_bear_off_moves_should_round_trip() {
+        // Player -1 bears off with a checker of player 1 on the bar and player 1 enters from the bar
+        let mut board = [0; 24];
+        board[..6].copy_from_slice(&[-3, -2, -2, 0, -4, -3]);
+        board[20] = 2;
+        let bearing_off = Backgammon::init_with_fields((board, (0, 1), (1, 12)), -1, false);
+        let entering = Backgammon::init_with_fields((board, (0, 1), (1, 12)), 1, false);
+        let states = [bearing_off, entering]
+            .iter()
+            .flat_map(|state| state.chance_outcomes().into_iter().map(|(outcome, _)| outcome))
+            .collect::<Vec<_>>();
+        let report = check_states(&states);
+        assert_eq!(report.n_positions, 42);
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn encoding_should_be_a_bijection_for_any_seed(seed in any::<u64>()) {
+            let report = self_check::<Backgammon>(1, seed);
+            prop_assert!(report.is_ok(), "{}", report);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn every_valid_move_of_any_position_should_round_trip(state in any_position()) {
+            let report = check_states(&[state]);
+            prop_assert!(report.is_ok(), "{}", report);
+        }
+
+        #[test]
+        fn every_valid_move_of_bearoff_positions_should_round_trip(state in positions(Phase::Bearoff)) {
+            let report = check_states(&[state]);
+            prop_assert!(report.is_ok(), "{}", report);
+        }
+    }
+}
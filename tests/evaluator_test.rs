@@ -0,0 +1,169 @@
+use std::{
+    sync::{atomic::{AtomicUsize, Ordering}, Barrier},
+    thread,
+};
+
+use die_e::backgammon::backgammon_logic::Backgammon;
+use die_e::base::LearnableGame;
+use die_e::evaluator::{cache::{CacheStats, CachingEvaluator}, heuristic::BackgammonHeuristic, uniform::UniformEvaluator, Evaluator};
+use die_e::tictactoe::TicTacToe;
+use tch::Tensor;
+
+// Uniform evaluator that counts how many states it has evaluated
+struct CountingEvaluator {
+    n_evaluated: AtomicUsize,
+}
+
+impl<T: LearnableGame> Evaluator<T> for CountingEvaluator {
+    fn evaluate(&self, states: &[T]) -> (Tensor, Tensor) {
+        self.n_evaluated.fetch_add(states.len(), Ordering::SeqCst);
+        UniformEvaluator.evaluate(states)
+    }
+}
+
+#[cfg(test)]
+mod caching_evaluator {
+    use super::*;
+
+    #[test]
+    fn it_should_only_evaluate_missing_positions() {
+        let evaluator = CachingEvaluator::new(CountingEvaluator { n_evaluated: AtomicUsize::new(0) });
+        let mut state = TicTacToe::new();
+        let (policy, value) = evaluator.evaluate(&[state, state]);
+        assert_eq!(policy.size(), vec![2, TicTacToe::ACTION_SPACE_SIZE]);
+        assert_eq!(value.size(), vec![2, 1]);
+        assert_eq!(evaluator.inner().n_evaluated.load(Ordering::SeqCst), 1);
+
+        state.apply_move(&4);
+        let _ = evaluator.evaluate(&[TicTacToe::new(), state]);
+        assert_eq!(evaluator.inner().n_evaluated.load(Ordering::SeqCst), 2);
+        assert_eq!(evaluator.stats(), CacheStats { hits: 2, misses: 2, entries: 2 });
+        assert_eq!(evaluator.stats().hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn it_should_return_policy_of_valid_moves_only() {
+        let evaluator = CachingEvaluator::new(UniformEvaluator);
+        let mut state = TicTacToe::new();
+        state.apply_move(&0);
+        let (policy, _) = evaluator.evaluate(&[state]);
+        assert_eq!(policy.double_value(&[0, 0]), 0.);
+        assert!(policy.double_value(&[0, 1]) > 0.);
+    }
+
+    #[test]
+    fn it_should_stay_within_capacity() {
+        let evaluator = CachingEvaluator::with_capacity(UniformEvaluator, 1);
+        let mut state = TicTacToe::new();
+        let _ = evaluator.evaluate(&[state]);
+        state.apply_move(&4);
+        let _ = evaluator.evaluate(&[state]);
+        assert_eq!(evaluator.len(), 1);
+    }
+
+    #[test]
+    fn it_should_evict_the_least_recently_used_positions() {
+        let evaluator = CachingEvaluator::with_capacity(CountingEvaluator { n_evaluated: AtomicUsize::new(0) }, 2);
+        let [first, second, third] = [0, 1, 2].map(|action| {
+            let mut state = TicTacToe::new();
+            state.apply_move(&action);
+            state
+        });
+        let _ = evaluator.evaluate(&[first, second]);
+        assert_eq!(evaluator.len(), 2);
+
+        // first is a hit and was used more recently than second, which makes room for third
+        let (policy, value) = evaluator.evaluate(&[first, third]);
+        assert_eq!(policy.size(), vec![2, TicTacToe::ACTION_SPACE_SIZE]);
+        assert_eq!(value.size(), vec![2, 1]);
+        assert_eq!(policy.double_value(&[0, 0]), 0.);
+        assert!(policy.double_value(&[0, 1]) > 0.);
+        assert_eq!(policy.double_value(&[1, 2]), 0.);
+        assert!(policy.double_value(&[1, 0]) > 0.);
+        assert_eq!(evaluator.stats(), CacheStats { hits: 1, misses: 3, entries: 2 });
+
+        let _ = evaluator.evaluate(&[first, third]);
+        assert_eq!(evaluator.inner().n_evaluated.load(Ordering::SeqCst), 3);
+        let _ = evaluator.evaluate(&[second]);
+        assert_eq!(evaluator.inner().n_evaluated.load(Ordering::SeqCst), 4);
+    }
+
+    // Waits until both threads are evaluating, which can only happen if the cache is not locked meanwhile
+    struct BarrierEvaluator {
+        barrier: Barrier,
+    }
+
+    impl<T: LearnableGame> Evaluator<T> for BarrierEvaluator {
+        fn evaluate(&self, states: &[T]) -> (Tensor, Tensor) {
+            self.barrier.wait();
+            UniformEvaluator.evaluate(states)
+        }
+    }
+
+    #[test]
+    fn it_should_not_lock_the_cache_while_evaluating() {
+        let evaluator = CachingEvaluator::new(BarrierEvaluator { barrier: Barrier::new(2) });
+        let mut state = TicTacToe::new();
+        state.apply_move(&4);
+        thread::scope(|scope| {
+            scope.spawn(|| evaluator.evaluate(&[TicTacToe::new()]));
+            scope.spawn(|| evaluator.evaluate(&[state]));
+        });
+        assert_eq!(evaluator.len(), 2);
+    }
+}
+
+#[cfg(test)]
+mod backgammon_heuristic {
+    use super::*;
+
+    #[test]
+    fn it_should_favor_player_to_move_in_even_race() {
+        let mut bg = Backgammon::new();
+        bg.roll = (3, 1);
+        let value = BackgammonHeuristic::default().evaluate_state(&bg);
+        assert!(value > 0. && value < 1.);
+    }
+
+    #[test]
+    fn it_should_favor_player_ahead_in_race() {
+        let mut bg = Backgammon::init_with_fields(([0; 24], (0, 0), (13, 13)), -1, false);
+        bg.board.0[0] = -2;
+        bg.board.0[5] = 2;
+        bg.roll = (3, 1);
+        let heuristic = BackgammonHeuristic::default();
+        assert!(heuristic.evaluate_state(&bg) > 0.);
+        bg.player = 1;
+        bg.board.0[0] = -2;
+        bg.board.0[23] = 0;
+        bg.board.0[18] = 2;
+        bg.board.0[5] = 0;
+        assert!(heuristic.evaluate_state(&bg) < 0.);
+    }
+
+    #[test]
+    fn it_should_give_the_player_to_move_the_pips_of_its_roll() {
+        // Even race of 12 pips a player, a large scale keeps tanh close to linear
+        let heuristic = BackgammonHeuristic { pip_scale: 100. };
+        let mut bg = Backgammon::init_with_fields(([0; 24], (0, 0), (13, 13)), 1, false);
+        bg.board.0[5] = 2;
+        bg.board.0[18] = -2;
+        let bonus = |bg: &Backgammon| heuristic.evaluate_state(bg).atanh() * heuristic.pip_scale;
+
+        // Not rolled yet, the pips of an average roll
+        assert!((bonus(&bg) - 8.17).abs() < 1e-3);
+        bg.roll = (3, 1);
+        assert!((bonus(&bg) - 4.).abs() < 1e-3);
+        // A double is played four times, twice on each play
+        bg.roll = (6, 6);
+        assert!((bonus(&bg) - 24.).abs() < 1e-3);
+        bg.is_second_play = true;
+        assert!((bonus(&bg) - 12.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn it_should_return_exact_value_when_game_is_over() {
+        let bg = Backgammon::init_with_fields(([0; 24], (0, 0), (15, 3)), 1, false);
+        assert_eq!(BackgammonHeuristic::default().evaluate_state(&bg), -1.);
+    }
+}
@@ -0,0 +1,68 @@
+mod common;
+
+#[cfg(test)]
+mod move_filter {
+    use die_e::expectimax::MoveFilter;
+
+    #[test]
+    fn it_should_keep_accepted_moves_sorted_by_value() {
+        let filter = MoveFilter { accept: 2, extra: 0, threshold: 1. };
+        let move_values = vec![(0, 0.1), (1, 0.5), (2, -0.3), (3, 0.4)];
+        assert_eq!(filter.apply(move_values), vec![(1, 0.5), (3, 0.4)]);
+    }
+
+    #[test]
+    fn it_should_keep_extra_moves_within_threshold() {
+        let filter = MoveFilter { accept: 1, extra: 3, threshold: 0.2 };
+        let move_values = vec![(0, 0.1), (1, 0.5), (2, 0.35), (3, 0.4)];
+        assert_eq!(filter.apply(move_values), vec![(1, 0.5), (3, 0.4), (2, 0.35)]);
+    }
+
+    #[test]
+    fn it_should_limit_extra_moves() {
+        let filter = MoveFilter { accept: 1, extra: 1, threshold: 1. };
+        let move_values = vec![(0, 0.1), (1, 0.5), (2, 0.35)];
+        assert_eq!(filter.apply(move_values), vec![(1, 0.5), (2, 0.35)]);
+    }
+
+    #[test]
+    fn it_should_handle_no_moves() {
+        let filter = MoveFilter { accept: 2, extra: 2, threshold: 0.1 };
+        let move_values: Vec<(u8, f32)> = vec![];
+        assert!(filter.apply(move_values).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod search {
+    use super::common::CenterEvaluator;
+    use die_e::base::LearnableGame;
+    use die_e::expectimax::{expectimax_actions, expectimax_search, ExpectimaxConfig, MoveFilter};
+    use die_e::tictactoe::TicTacToe;
+
+    fn config(depth: usize) -> ExpectimaxConfig {
+        ExpectimaxConfig { depth, move_filter: MoveFilter { accept: 9, extra: 0, threshold: 0. } }
+    }
+
+    #[test]
+    fn it_should_pick_the_best_move_at_depth_one() {
+        let state = TicTacToe::new();
+        let move_values = expectimax_search(&CenterEvaluator, &[state], &config(1)).pop().unwrap();
+        assert_eq!(move_values.len(), 9);
+        for (action, value) in move_values {
+            assert_eq!(value, if action == 4 { 0.5 } else { 0. });
+        }
+        assert_eq!(expectimax_actions(&CenterEvaluator, &[state], &config(1)), vec![4]);
+    }
+
+    #[test]
+    fn it_should_block_the_opponent_at_depth_two() {
+        // o threatens to complete the middle row, every other move lets it win
+        let state = TicTacToe::from_position_id("x--oo----:-1").unwrap();
+        let move_values = expectimax_search(&CenterEvaluator, &[state], &config(2)).pop().unwrap();
+        for (action, value) in move_values {
+            assert_eq!(value, if action == 5 { -0.5 } else { -1. });
+        }
+        assert_eq!(expectimax_actions(&CenterEvaluator, &[state], &config(2)), vec![5]);
+    }
+}
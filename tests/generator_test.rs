@@ -0,0 +1,65 @@
+mod common;
+
+use common::{any_position, positions};
+use die_e::{
+    backgammon::{
+        backgammon_logic::Backgammon,
+        bearoff::home_counts,
+        generator::{random_position, Phase, PHASES},
+    },
+    base::LearnableGame,
+    tictactoe::TicTacToe,
+};
+use proptest::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+#[cfg(test)]
+mod random_positions {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn it_should_keep_15_checkers_per_player(state in any_position()) {
+            prop_assert_eq!(state.total_checkers(-1), 15);
+            prop_assert_eq!(state.total_checkers(1), 15);
+            prop_assert!(state.check_winner().is_none());
+            let roll = state.get_roll().unwrap();
+            prop_assert!((1..=6).contains(&roll.0) && (1..=6).contains(&roll.1));
+        }
+
+        #[test]
+        fn contact_positions_should_have_contact(state in positions(Phase::Contact)) {
+            prop_assert!(state.has_contact());
+            prop_assert_eq!(state.board.2, (0, 0));
+        }
+
+        #[test]
+        fn race_positions_should_have_no_contact(state in positions(Phase::Race)) {
+            prop_assert!(state.is_race());
+            prop_assert_eq!(state.board.2, (0, 0));
+        }
+
+        #[test]
+        fn bearoff_positions_should_be_in_the_home_boards(state in positions(Phase::Bearoff)) {
+            prop_assert!(home_counts(state.board, -1).is_some());
+            prop_assert!(home_counts(state.board, 1).is_some());
+        }
+    }
+
+    #[test]
+    fn it_should_be_reproducible() {
+        for phase in PHASES {
+            let first = random_position(phase, &mut StdRng::seed_from_u64(7));
+            let second = random_position(phase, &mut StdRng::seed_from_u64(7));
+            assert_eq!(first.position_id(), second.position_id());
+            assert_eq!(first.get_roll(), second.get_roll());
+        }
+    }
+
+    #[test]
+    fn only_backgammon_should_sample_start_positions() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(Backgammon::random_position(None, &mut rng).is_some());
+        assert!(TicTacToe::random_position(None, &mut rng).is_none());
+    }
+}
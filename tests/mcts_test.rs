@@ -1,7 +1,10 @@
+mod common;
+
 use die_e::tictactoe::TicTacToe;
 use die_e::mcts::node_store::NodeStore;
 use die_e::base::LearnableGame;
 use die_e::constants::{DEFAULT_TYPE, DEVICE};
+use common::{load_test_config, test_config};
 use tch::{Tensor, Device};
 
 mod tests_util {
@@ -43,14 +46,11 @@ mod tests_util {
             let root = store.add_node(TicTacToe::new(), None, None, 0.0);
             for i in 1..9 {
                 let child_idx = store.add_node(TicTacToe::new(), Some(root), Some(i), 0.0);
-                let child_mut = store.get_node_as_mut(child_idx);
-                child_mut.visits = 10.;
+                store.set_visits(child_idx, 10.);
             }
-            let mut root = store.get_node(0);
-            root.children = Vec::from_iter(1..9);
-            store.set_node(&root);
+            store.get_node_as_mut(root).children = 1..9;
 
-            let prob_tensor = get_prob_tensor_parallel(&[&root], &store);
+            let prob_tensor = get_prob_tensor_parallel(&[store.get_node_ref(root)], &store);
             assert_eq!(prob_tensor.size(), vec![1, TicTacToe::ACTION_SPACE_SIZE]);
             // Since this is policies converted to a tensor of probabilities
             // All sums of batches should be 1
@@ -59,4 +59,489 @@ mod tests_util {
             assert!(sum.allclose(&sum.ones_like(), 1e-5, 1e-8, false))
         }
     }
-}
\ No newline at end of file
+}
+mod tests_node_store {
+    use super::*;
+    use die_e::mcts::puct::{Fpu, PuctConfig};
+
+    // root -> [child_a -> [grandchild], child_b]
+    fn build_store() -> (NodeStore<TicTacToe>, usize, usize, usize) {
+        let mut store = NodeStore::new();
+        let root_idx = store.add_node(TicTacToe::new(), None, None, 0.0);
+        let root_children = store.expand(root_idx);
+        let (child_a, child_b) = (root_children.start, root_children.start + 1);
+        let grandchild = store.expand(child_a).start;
+        store.set_visits(child_a, 5.);
+        store.set_visits(grandchild, 3.);
+        (store, child_a, child_b, grandchild)
+    }
+
+    #[test]
+    fn find_descendant_respects_max_depth() {
+        let (store, _, child_b, grandchild) = build_store();
+        let grandchild_state = store.get_node_ref(grandchild).state;
+        let child_b_state = store.get_node_ref(child_b).state;
+        assert_eq!(store.find_descendant(0, &child_b_state, 1), Some(child_b));
+        assert_eq!(store.find_descendant(0, &grandchild_state, 1), None);
+        assert_eq!(store.find_descendant(0, &grandchild_state, 2), Some(grandchild));
+    }
+
+    #[test]
+    fn reroot_keeps_subtree_and_compacts() {
+        let (store, child_a, _, grandchild) = build_store();
+        let child_a_state = store.get_node_ref(child_a).state;
+        let new_state = TicTacToe::new();
+        let new_store = store.reroot(&[None, Some(child_a)], &[new_state, child_a_state]);
+
+        // Fresh root, reused root and the 8 children of child_a
+        assert_eq!(new_store.len(), 10);
+        let fresh_root = new_store.get_node_ref(0);
+        assert!(fresh_root.parent.is_none() && fresh_root.children.is_empty());
+
+        let reused_root = new_store.get_node_ref(1);
+        assert!(reused_root.parent.is_none());
+        assert_eq!(new_store.visits(1), 5.);
+        assert_eq!(reused_root.children, 2..10);
+
+        let kept_grandchild = new_store.get_node_ref(2);
+        assert_eq!(kept_grandchild.parent, Some(1));
+        assert_eq!(new_store.visits(2), 3.);
+        assert_eq!(kept_grandchild.action_taken, store.get_node_ref(grandchild).action_taken);
+        assert_eq!(new_store.get_root_nodes().len(), 2);
+    }
+
+    #[test]
+    fn positions_are_reused_across_a_turn_of_the_opponent() {
+        use die_e::backgammon::backgammon_logic::Backgammon;
+
+        let mut state = Backgammon::new();
+        state.set_roll((3, 1));
+        let mut store = NodeStore::new();
+        let root = store.add_node(state, None, None, 0.0);
+        let child = store.expand(root).start;
+        // The opponent plays a roll that is not a double, so the grandchild is the turn of the root player again
+        let child_node = store.get_node_as_mut(child);
+        child_node.state.set_roll((5, 2));
+        child_node.expandable_moves = child_node.state.get_valid_moves();
+        let grandchild = store.expand(child).start;
+        store.expand(grandchild);
+        store.backpropagate(grandchild, 0.5);
+        store.backpropagate(grandchild, 0.5);
+
+        // The tree rolled the dice of the grandchild at random, the game rolls differently
+        let mut actual = store.get_node_ref(grandchild).state;
+        actual.set_roll(if actual.roll == (6, 5) { (6, 4) } else { (6, 5) });
+        assert_eq!(actual.get_player(), state.get_player());
+        assert_eq!(store.find_descendant(root, &actual, 2), Some(grandchild));
+
+        let rerooted = store.reroot(&[Some(grandchild)], &[actual]);
+        let reused_root = rerooted.get_node_ref(0);
+        assert_eq!((rerooted.len(), rerooted.visits(0), rerooted.value(0)), (1, 2., 1.));
+        assert_eq!(reused_root.state.roll, actual.roll);
+        // The moves of the other roll are dropped and the root is expanded again for the actual roll
+        assert!(reused_root.children.is_empty());
+        assert_eq!(reused_root.expandable_moves, actual.get_valid_moves());
+    }
+
+    // Child of node idx reached by action
+    fn child_by_action(store: &NodeStore<TicTacToe>, idx: usize, action: u8) -> usize {
+        store.children(idx).find(|&child_idx| store.get_node_ref(child_idx).action_taken == Some(action)).unwrap()
+    }
+
+    #[test]
+    fn values_are_from_the_view_of_the_player_who_moved() {
+        // x to move wins at 2, o wins at 5 if x does not block it
+        let state = TicTacToe::from_position_id("xx-oo----:-1").unwrap();
+        let mut store = NodeStore::new();
+        let root = store.add_node(state, None, None, 0.0);
+        store.expand(root);
+
+        let win = child_by_action(&store, root, 2);
+        // o is to move and has lost
+        assert_eq!(store.terminal_value(win), Some(-1.));
+        store.backpropagate(win, store.terminal_value(win).unwrap());
+        // Network value of 0.8 for o after x plays 8
+        let other = child_by_action(&store, root, 8);
+        store.backpropagate(other, 0.8);
+        let blunder = child_by_action(&store, root, 6);
+        store.expand(blunder);
+        let loss = child_by_action(&store, blunder, 5);
+        store.backpropagate(loss, store.terminal_value(loss).unwrap());
+
+        assert_eq!(store.win_pct(win), 1.);
+        assert_eq!(store.win_pct(other), -0.8);
+        assert_eq!(store.win_pct(blunder), -1.);
+        assert_eq!(store.win_pct(loss), 1.);
+        // The root and the fpu of a node are from the view of their player to move
+        assert!((store.win_pct(root) - (1. - 0.8 - 1.) / 3.).abs() < 1e-6);
+        let no_reduction = PuctConfig { c: 1., c_base: 0., fpu: Fpu::Reduction(0.) };
+        assert_eq!(store.fpu_value(blunder, &no_reduction, 0.), 1.);
+        let rerooted = store.reroot(&[Some(blunder)], &[store.get_node_ref(blunder).state]);
+        assert_eq!(rerooted.win_pct(0), 1.);
+        assert_eq!(rerooted.win_pct(child_by_action(&rerooted, 0, 5)), 1.);
+    }
+
+    #[test]
+    fn expand_stores_children_next_to_each_other() {
+        let mut store: NodeStore<TicTacToe> = NodeStore::new();
+        let root = store.add_node(TicTacToe::new(), None, None, 0.0);
+        let policy = (0..TicTacToe::ACTION_SPACE_SIZE).map(|i| i as f32).collect::<Vec<f32>>();
+        let children = store.alpha_expand(root, &policy);
+
+        assert_eq!(children, 1..10);
+        assert!(store.get_node_ref(root).is_fully_expanded());
+        assert_eq!(store.alpha_expand(root, &policy), 1..10);
+        assert_eq!(store.len(), 10);
+        for child_idx in children {
+            let child = store.get_node_ref(child_idx);
+            assert_eq!(child.parent, Some(root));
+            assert_eq!(store.policy(child_idx), child.state.encode(child.action_taken.as_ref().unwrap()) as f32);
+        }
+    }
+
+    #[test]
+    fn select_alpha_prefers_prior_then_value() {
+        let mut store: NodeStore<TicTacToe> = NodeStore::new();
+        let root = store.add_node(TicTacToe::new(), None, None, 0.0);
+        let mut policy = vec![0.01; TicTacToe::ACTION_SPACE_SIZE as usize];
+        policy[4] = 0.9;
+        store.alpha_expand(root, &policy);
+        store.set_visits(root, 1.);
+
+        let best = store.select_alpha(root, &PuctConfig::constant(2.));
+        assert_eq!(store.get_node_ref(best).state.encode(store.get_node_ref(best).action_taken.as_ref().unwrap()), 4);
+
+        // A visited child with a high value beats the prior of an unvisited one,
+        // the leaf value is from the view of the opponent who moves next
+        let other = store.children(root).find(|&idx| idx != best).unwrap();
+        store.backpropagate(other, -1.);
+        store.backpropagate(other, -1.);
+        assert_eq!(store.select_alpha(root, &PuctConfig::constant(0.1)), other);
+        assert_eq!(store.visits(root), 3.);
+        assert_eq!(store.value(other), 2.);
+    }
+
+    #[test]
+    fn fpu_and_c_puct_schedule() {
+        let mut store: NodeStore<TicTacToe> = NodeStore::new();
+        let root = store.add_node(TicTacToe::new(), None, None, 0.0);
+        let children = store.alpha_expand(root, &[0.25, 0.25, 0.1, 0.1, 0.1, 0.1, 0.05, 0.05, 0.]);
+        store.backpropagate(children.start, -0.5);
+        store.backpropagate(children.start + 1, -0.5);
+
+        // Root has a mean value of 0.5 and the visited children hold half of the policy
+        let reduction = PuctConfig { c: 1., c_base: 0., fpu: Fpu::Reduction(0.2) };
+        assert!((store.fpu_value(root, &reduction, 0.) - (0.5 - 0.2 * 0.5_f32.sqrt())).abs() < 1e-6);
+        assert_eq!(store.fpu_value(root, &PuctConfig::constant(1.), 0.), 0.);
+
+        let growing = PuctConfig { c: 1.25, c_base: 19652., fpu: Fpu::Absolute(0.) };
+        assert_eq!(growing.c_puct(0.), 1.25 + (19653_f32 / 19652.).ln());
+        assert!(growing.c_puct(100_000.) > growing.c_puct(100.));
+        assert_eq!(PuctConfig::constant(2.).c_puct(100_000.), 2.);
+
+        // With a pessimistic fpu the visited children are preferred over unvisited ones
+        let pessimistic = PuctConfig { c: 0.1, c_base: 0., fpu: Fpu::Absolute(-1.) };
+        assert!(store.children(root).take(2).any(|idx| idx == store.select_alpha(root, &pessimistic)));
+    }
+
+    #[test]
+    fn virtual_loss_spreads_selection() {
+        let mut store: NodeStore<TicTacToe> = NodeStore::new();
+        let root = store.add_node(TicTacToe::new(), None, None, 0.0);
+        store.alpha_expand(root, &[1. / 9.; 9]);
+        store.set_visits(root, 1.);
+
+        let first = store.select_leaf_virtual(root, &PuctConfig::constant(2.), 1.);
+        let second = store.select_leaf_virtual(root, &PuctConfig::constant(2.), 1.);
+        assert_ne!(first, second);
+        assert_eq!(store.virtual_loss(root), 2);
+        assert_eq!(store.virtual_loss(first), 1);
+
+        store.backpropagate_virtual(first, -0.5);
+        store.backpropagate_virtual(second, -0.5);
+        assert_eq!(store.virtual_loss(root), 0);
+        assert_eq!(store.virtual_loss(first), 0);
+        assert_eq!(store.visits(root), 3.);
+        assert_eq!(store.value(second), 0.5);
+    }
+
+    #[test]
+    fn dirichlet_only_touches_legal_children() {
+        let mut state = TicTacToe::new();
+        state.apply_move(&4);
+        state.apply_move(&0);
+        let mut store: NodeStore<TicTacToe> = NodeStore::new();
+        let root = store.add_node(state, None, None, 0.0);
+        store.alpha_expand(root, &[1. / 7.; 9]);
+        store.set_visits(root, 1.);
+
+        store.apply_dirichlet(root, 0.3, 0.25);
+        let children = store.children(root);
+        assert_eq!(children.len(), 7);
+        let actions = children.clone().map(|i| store.get_node_ref(i).action_taken.unwrap()).collect::<Vec<_>>();
+        assert!(!actions.contains(&0) && !actions.contains(&4));
+        let policy_sum: f32 = children.map(|i| store.policy(i)).sum();
+        assert!((policy_sum - 1.).abs() < 1e-4);
+    }
+}
+
+mod tests_budget {
+    use super::*;
+    use std::{thread, time::Duration};
+    use die_e::{mcts::budget::SearchBudget, MctsConfig};
+
+    fn mcts_config(overrides: &[&str]) -> MctsConfig {
+        MctsConfig::from_config(&test_config(overrides)).unwrap()
+    }
+
+    #[test]
+    fn dirichlet_alpha_scales_with_legal_moves() {
+        let config = mcts_config(&["dirichlet_alpha_total=10"]);
+        assert_eq!(config.dirichlet_alpha_for(10), 1.);
+        assert_eq!(config.dirichlet_alpha_for(100), 0.1);
+
+        let fixed = mcts_config(&["dirichlet_alpha_total=0"]);
+        assert_eq!(fixed.dirichlet_alpha_for(10), fixed.dirichlet_alpha_for(100));
+    }
+
+    #[test]
+    fn stops_on_iterations_and_nodes() {
+        let budget = SearchBudget::new(&mcts_config(&["iterations=10", "max_nodes=50"]));
+        assert!(!budget.is_exhausted(9, 49));
+        assert!(budget.is_exhausted(10, 0));
+        assert!(budget.is_exhausted(0, 50));
+    }
+
+    #[test]
+    fn stops_on_time_limit() {
+        let budget = SearchBudget::new(&mcts_config(&["iterations=1000", "time_limit_ms=5"]));
+        assert!(!budget.is_exhausted(0, 0));
+        thread::sleep(Duration::from_millis(10));
+        assert!(budget.is_exhausted(0, 0));
+    }
+
+    #[test]
+    fn decided_when_lead_cannot_be_overtaken() {
+        let mut store: NodeStore<TicTacToe> = NodeStore::new();
+        let root = store.add_node(TicTacToe::new(), None, None, 0.0);
+        let children = store.expand(root);
+        store.set_visits(children.start, 10.);
+        store.set_visits(children.start + 1, 2.);
+
+        let budget = SearchBudget::new(&mcts_config(&["iterations=100", "early_stop=true"]));
+        assert!(!budget.is_decided(&store, root, 12));
+        assert!(budget.is_decided(&store, root, 95));
+
+        let no_early_stop = SearchBudget::new(&mcts_config(&["iterations=100"]));
+        assert!(!no_early_stop.is_decided(&store, root, 95));
+    }
+}
+
+mod tests_move_selection {
+    use super::*;
+    use die_e::mcts::move_selection::{MoveSelection, TemperatureSchedule, TemperatureStep};
+
+    // Root with three searched moves: (visits, value) = (10, 2), (4, 3), (1, 1)
+    fn searched_store() -> (NodeStore<TicTacToe>, usize, usize, usize) {
+        let mut store = NodeStore::new();
+        let root = store.add_node(TicTacToe::new(), None, None, 0.0);
+        let children = store.expand(root);
+        let (most_visited, best_mean, lucky) = (children.start, children.start + 1, children.start + 2);
+        for (idx, visits, value) in [(most_visited, 10., 2.), (best_mean, 4., 3.), (lucky, 1., 1.)] {
+            store.set_visits(idx, visits);
+            store.set_value(idx, value);
+        }
+        (store, most_visited, best_mean, lucky)
+    }
+
+    #[test]
+    fn picks_the_strongest_child() {
+        let (store, most_visited, best_mean, lucky) = searched_store();
+        assert_eq!(MoveSelection::MostVisits.select_child(&store, 0, 0), Some(most_visited));
+        assert_eq!(MoveSelection::BestQ.select_child(&store, 0, 0), Some(lucky));
+        assert_eq!(MoveSelection::Lcb { z: 1. }.select_child(&store, 0, 0), Some(best_mean));
+    }
+
+    #[test]
+    fn q_selections_pick_the_winning_move() {
+        // x to move wins at 2, after any other move o is about to win at 5
+        let state = TicTacToe::from_position_id("xx-oo----:-1").unwrap();
+        let mut store = NodeStore::new();
+        let root = store.add_node(state, None, None, 0.0);
+        let mut win = None;
+        for child_idx in store.expand(root) {
+            match store.terminal_value(child_idx) {
+                Some(value) => {
+                    win = Some(child_idx);
+                    for _ in 0..4 {
+                        store.backpropagate(child_idx, value);
+                    }
+                }
+                // Network value of 0.9 for o, the player to move
+                None => (0..10).for_each(|_| store.backpropagate(child_idx, 0.9)),
+            }
+        }
+        assert_eq!(MoveSelection::BestQ.select_child(&store, root, 0), win);
+        assert_eq!(MoveSelection::Lcb { z: 1. }.select_child(&store, root, 0), win);
+        assert_ne!(MoveSelection::MostVisits.select_child(&store, root, 0), win);
+    }
+
+    #[test]
+    fn temperature_stops_sampling_after_cutoff() {
+        let (store, most_visited, _, _) = searched_store();
+        let selection = MoveSelection::Temperature(TemperatureSchedule::new(vec![
+            TemperatureStep { from_move: 10, temperature: 0. },
+            TemperatureStep { from_move: 0, temperature: 100. },
+        ]));
+        for _ in 0..20 {
+            assert_eq!(selection.select_child(&store, 0, 10), Some(most_visited));
+            let sampled = selection.select_child(&store, 0, 0).unwrap();
+            assert!(store.visits(sampled) > 0.);
+        }
+    }
+
+    #[test]
+    fn schedule_uses_the_latest_step() {
+        let schedule = TemperatureSchedule::new(vec![
+            TemperatureStep { from_move: 4, temperature: 1. },
+            TemperatureStep { from_move: 20, temperature: 0.5 },
+        ]);
+        assert_eq!(schedule.temperature_at(0), 1.);
+        assert_eq!(schedule.temperature_at(19), 1.);
+        assert_eq!(schedule.temperature_at(20), 0.5);
+        assert_eq!(schedule.temperature_at(200), 0.5);
+        assert_eq!(TemperatureSchedule::constant(1.25).temperature_at(7), 1.25);
+    }
+
+    #[test]
+    fn root_without_children_skips() {
+        let mut store: NodeStore<TicTacToe> = NodeStore::new();
+        store.add_node(TicTacToe::new(), None, None, 0.0);
+        assert_eq!(MoveSelection::MostVisits.select_move(&store, 0, 0), TicTacToe::EMPTY_MOVE);
+    }
+
+    #[test]
+    fn parses_from_config() {
+        let conf = test_config(&["move_selection=lcb", "lcb_z=2"]);
+        assert_eq!(MoveSelection::from_config(&conf, "move_selection").unwrap(), MoveSelection::Lcb { z: 2. });
+        assert!(load_test_config(&["move_selection=best"]).is_err());
+
+        let conf = test_config(&[
+            "move_selection=temperature",
+            "temperature_schedule=[{ from_move = 0, temperature = 1.0 }, { from_move = 30, temperature = 0.0 }]",
+        ]);
+        let expected = TemperatureSchedule::new(vec![
+            TemperatureStep { from_move: 0, temperature: 1. },
+            TemperatureStep { from_move: 30, temperature: 0. },
+        ]);
+        assert_eq!(MoveSelection::from_config(&conf, "move_selection").unwrap(), MoveSelection::Temperature(expected));
+    }
+}
+
+mod tests_gumbel {
+    use super::*;
+    use itertools::Itertools;
+    use die_e::mcts::gumbel::{improved_policy, GumbelConfig, GumbelRoot};
+
+    fn expanded_root() -> NodeStore<TicTacToe> {
+        let mut store = NodeStore::new();
+        let root = store.add_node(TicTacToe::new(), None, None, 0.0);
+        store.alpha_expand(root, &[1. / 9.; 9]);
+        store.set_visits(root, 1.);
+        store
+    }
+
+    #[test]
+    fn sequential_halving_narrows_candidates() {
+        let mut store = expanded_root();
+        let config = GumbelConfig { n_sampled: 4, c_visit: 50., c_scale: 1. };
+        let mut gumbel_root = GumbelRoot::new(&store, 0, 16, &config);
+
+        let mut simulated = vec![];
+        for _ in 0..16 {
+            let child_idx = gumbel_root.next_child(&store, &config).unwrap();
+            store.backpropagate(child_idx, 0.);
+            simulated.push(child_idx);
+        }
+        // First phase spreads 2 simulations over each of the 4 sampled moves, second one over the best 2
+        assert_eq!(simulated.iter().unique().count(), 4);
+        assert_eq!(simulated[8..].iter().unique().count(), 2);
+        assert!(simulated[8..].contains(&gumbel_root.best_child(&store, &config).unwrap()));
+    }
+
+    #[test]
+    fn improved_policy_favors_good_moves() {
+        let mut store = expanded_root();
+        let children = store.children(0);
+        store.backpropagate(children.start, -1.);
+        store.backpropagate(children.start + 1, 1.);
+        let config = GumbelConfig { n_sampled: 9, c_visit: 50., c_scale: 0.1 };
+
+        let policy = improved_policy(&store, 0, &config);
+        let total: f32 = policy.iter().map(|(_, prob)| prob).sum();
+        assert!((total - 1.).abs() < 1e-5);
+        let prob_of = |idx: usize| policy.iter().find(|(child_idx, _)| *child_idx == idx).unwrap().1;
+        assert!(prob_of(children.start) > prob_of(children.start + 2));
+        assert!(prob_of(children.start + 2) > prob_of(children.start + 1));
+    }
+}
+
+#[cfg(test)]
+mod tests_alpha_mcts_parallel {
+    use super::*;
+    use die_e::{evaluator::Evaluator, mcts::alpha_mcts::alpha_mcts_parallel, MctsConfig};
+
+    // Same policy for every state, growing with the encoded move
+    struct RampEvaluator;
+
+    impl Evaluator<TicTacToe> for RampEvaluator {
+        fn evaluate(&self, states: &[TicTacToe]) -> (Tensor, Tensor) {
+            let n_states = states.len() as i64;
+            let policy = Tensor::arange(TicTacToe::ACTION_SPACE_SIZE, (DEFAULT_TYPE, Device::Cpu)) + 1.;
+            (policy.repeat([n_states, 1]), Tensor::zeros([n_states, 1], (DEFAULT_TYPE, Device::Cpu)))
+        }
+    }
+
+    fn mcts_config(iterations: i64, root_selection: &str) -> MctsConfig {
+        let iterations = format!("iterations={}", iterations);
+        let root_selection = format!("root_selection={}", root_selection);
+        MctsConfig::from_config(&test_config(&[&iterations, "dirichlet_epsilon=0", &root_selection])).unwrap()
+    }
+
+    #[test]
+    fn new_roots_get_their_own_priors_next_to_reused_roots() {
+        let mcts_config = mcts_config(1, "puct");
+
+        // Root 0 is kept from a previous search, root 1 is a new position
+        let mut new_state = TicTacToe::new();
+        new_state.apply_move(&4);
+        let mut store = NodeStore::new();
+        store.add_node(TicTacToe::new(), None, None, 0.0);
+        store.add_node(new_state, None, None, 0.0);
+        store.alpha_expand(0, &[1. / 9.; 9]);
+        store.set_visits(0, 1.);
+
+        alpha_mcts_parallel(&mut store, &[TicTacToe::new(), new_state], &RampEvaluator, &mcts_config, None);
+
+        let children = store.children(1);
+        assert_eq!(children.len(), 8);
+        let total: f32 = (0..TicTacToe::ACTION_SPACE_SIZE as u32).filter(|&action| action != 4).map(|action| (action + 1) as f32).sum();
+        for child_idx in children {
+            let action = store.get_node_ref(child_idx).action_taken.unwrap();
+            assert!((store.policy(child_idx) - (action + 1) as f32 / total).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn gumbel_searches_return_the_move_to_play() {
+        let states = [TicTacToe::new(), TicTacToe::from_position_id("xoxxoooxx:-1").unwrap()];
+        let mut store = NodeStore::new();
+        let choices = alpha_mcts_parallel(&mut store, &states, &RampEvaluator, &mcts_config(16, "gumbel"), None);
+        assert!(store.children(0).contains(&choices[0].unwrap()));
+        // The full board has no move to choose from
+        assert_eq!(choices[1], None);
+
+        let mut store = NodeStore::new();
+        assert_eq!(alpha_mcts_parallel(&mut store, &states, &RampEvaluator, &mcts_config(16, "puct"), None), vec![None, None]);
+    }
+}
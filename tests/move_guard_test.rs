@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use die_e::{
+    backgammon::backgammon_logic::Backgammon,
+    base::LearnableGame,
+    move_guard::{GuardedMove, IllegalMovePolicy, MoveGuard, MoveGuardConfig},
+    tictactoe::TicTacToe,
+};
+
+fn log_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("die-e-{}-{}.jsonl", name, std::process::id()))
+}
+
+// The records written to the log, the log is removed
+fn read_records(path: &PathBuf) -> Vec<serde_json::Value> {
+    let records = std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<_>>();
+    std::fs::remove_file(path).unwrap();
+    records
+}
+
+#[cfg(test)]
+mod move_guard {
+    use super::*;
+
+    #[test]
+    fn it_should_play_valid_moves() {
+        let config = MoveGuardConfig { policy: IllegalMovePolicy::Panic, log_path: None };
+        let mut guard = MoveGuard::new(&config, "test");
+        let state = TicTacToe::from_position_id("x---o----:-1").unwrap();
+        assert_eq!(guard.check(&state, &1), GuardedMove::Play(1));
+
+        let full = TicTacToe::from_position_id("xoxxoooxx:-1").unwrap();
+        assert_eq!(guard.check(&full, &TicTacToe::EMPTY_MOVE), GuardedMove::Skip);
+        assert_eq!(guard.n_illegal(), 0);
+    }
+
+    #[test]
+    fn it_should_record_and_replace_illegal_moves() {
+        let path = log_path("replace");
+        let config = MoveGuardConfig { policy: IllegalMovePolicy::Replace, log_path: Some(path.clone()) };
+        let mut guard = MoveGuard::new(&config, "test");
+        let state = TicTacToe::from_position_id("x---o----:-1").unwrap();
+        let GuardedMove::Play(replacement) = guard.check(&state, &4) else { panic!("expected a replacement") };
+        assert!(state.get_valid_moves().contains(&replacement));
+        // The empty move is illegal while there are valid moves
+        assert!(matches!(guard.check(&state, &TicTacToe::EMPTY_MOVE), GuardedMove::Play(_)));
+        assert_eq!(guard.n_illegal(), 2);
+
+        let records = read_records(&path);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["context"], "test");
+        assert_eq!(records[0]["position_id"], "x---o----:-1");
+        assert_eq!(records[0]["action"], 4);
+        assert_eq!(records[0]["encoded"], 4);
+        assert_eq!(records[0]["valid_moves"].as_array().unwrap().len(), 7);
+        assert_eq!(records[0]["replaced_by"], replacement);
+    }
+
+    #[test]
+    fn it_should_skip_moves_it_can_not_encode() {
+        let path = log_path("skip");
+        let config = MoveGuardConfig { policy: IllegalMovePolicy::Skip, log_path: Some(path.clone()) };
+        let mut guard = MoveGuard::new(&config, "test");
+        let mut bg = Backgammon::new();
+        bg.roll = (2, 1);
+        let action = vec![(23, 21), (21, 20), (20, 19)];
+        assert_eq!(guard.check(&bg, &action), GuardedMove::Skip);
+
+        let records = read_records(&path);
+        assert_eq!(records[0]["roll"], serde_json::json!([2, 1]));
+        assert!(records[0]["encoded"].is_null());
+        assert!(records[0]["replaced_by"].is_null());
+        assert!(!records[0]["valid_moves"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_should_panic_if_set() {
+        let config = MoveGuardConfig { policy: IllegalMovePolicy::Panic, log_path: None };
+        let state = TicTacToe::from_position_id("x---o----:-1").unwrap();
+        MoveGuard::new(&config, "test").check(&state, &0);
+    }
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::collections::HashSet;
+
+use common::test_config;
+use die_e::{
+    backgammon::backgammon_logic::Backgammon,
+    base::LearnableGame,
+    evaluator::{heuristic::BackgammonHeuristic, uniform::UniformEvaluator},
+    rollout::{rollout, stratified_rolls, RolloutConfig},
+    tictactoe::TicTacToe,
+    versus::Agent,
+    Error,
+};
+
+fn rollout_config(n_games: usize, variance_reduction: bool) -> RolloutConfig {
+    RolloutConfig { n_games, variance_reduction, ..RolloutConfig::from_config(&test_config(&[])).unwrap() }
+}
+
+#[cfg(test)]
+mod stratification {
+    use super::*;
+
+    #[test]
+    fn it_should_cover_every_pair_of_first_rolls() {
+        let pairs: HashSet<_> = (0..1296).map(stratified_rolls).collect();
+        assert_eq!(pairs.len(), 1296);
+    }
+
+    #[test]
+    fn it_should_spread_both_rolls_over_36_games() {
+        for block in 0..3 {
+            let rolls = (block * 36..(block + 1) * 36).map(stratified_rolls).collect::<Vec<_>>();
+            assert_eq!(rolls.iter().map(|rolls| rolls[0]).collect::<HashSet<_>>().len(), 36);
+            assert_eq!(rolls.iter().map(|rolls| rolls[1]).collect::<HashSet<_>>().len(), 36);
+        }
+    }
+}
+
+#[cfg(test)]
+mod rollout_games {
+    use super::*;
+
+    #[test]
+    fn it_should_be_reproducible() {
+        let config = rollout_config(20, false);
+        let first = rollout(&Backgammon::new(), &Agent::Random, &UniformEvaluator, &config).unwrap();
+        let second = rollout(&Backgammon::new(), &Agent::Random, &UniformEvaluator, &config).unwrap();
+        assert_eq!(first.raw_equity, second.raw_equity);
+        assert_eq!(first.wins, second.wins);
+        assert_eq!(first.wins + first.draws + first.losses + first.estimated, 20);
+    }
+
+    #[test]
+    fn it_should_win_a_won_position() {
+        let mut bg = Backgammon::new();
+        bg.board.0 = [0; 24];
+        bg.board.0[0] = -1;
+        bg.board.0[1] = 15;
+        bg.board.2 = (14, 0);
+        let result = rollout(&bg, &Agent::Random, &UniformEvaluator, &rollout_config(36, false)).unwrap();
+        assert_eq!(result.wins, 36);
+        assert_eq!(result.equity, 1.);
+        assert_eq!(result.std_error, 0.);
+    }
+
+    #[test]
+    fn it_should_count_every_game_of_a_deterministic_game() {
+        let state = TicTacToe::from_position_id("x---o----:-1").unwrap();
+        let result = rollout(&state, &Agent::Random, &UniformEvaluator, &rollout_config(100, false)).unwrap();
+        assert_eq!(result.wins + result.draws + result.losses, 100);
+        assert!((-1. ..=1.).contains(&result.equity));
+        assert!(result.std_error > 0.);
+    }
+
+    #[test]
+    fn luck_should_be_zero_for_a_constant_evaluator() {
+        let result = rollout(&Backgammon::new(), &Agent::Random, &UniformEvaluator, &rollout_config(10, true)).unwrap();
+        assert_eq!(result.equity, result.raw_equity);
+        assert_eq!(result.std_error, result.raw_std_error);
+    }
+
+    #[test]
+    fn luck_should_reduce_the_error_of_a_race() {
+        // Even race of 48 pips each, with every checker in the home boards
+        let mut bg = Backgammon::new();
+        bg.board.0 = [0; 24];
+        bg.board.0[..6].copy_from_slice(&[-3, -3, -3, -2, -2, -2]);
+        bg.board.0[18..].copy_from_slice(&[2, 2, 2, 3, 3, 3]);
+        let result = rollout(&bg, &Agent::Random, &BackgammonHeuristic::default(), &rollout_config(720, true)).unwrap();
+        assert!(result.std_error < result.raw_std_error);
+        assert!((result.equity - result.raw_equity).abs() < 3. * result.raw_std_error);
+    }
+
+    #[test]
+    fn it_should_not_roll_out_with_a_searching_agent() {
+        for agent in [Agent::Mcts, Agent::Expectimax, Agent::Model] {
+            let result = rollout(&Backgammon::new(), &agent, &UniformEvaluator, &rollout_config(1, false));
+            assert!(matches!(result, Err(Error::InvalidInput(_))));
+        }
+    }
+}
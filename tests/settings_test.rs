@@ -0,0 +1,94 @@
+use std::{collections::HashMap, path::Path};
+
+use die_e::{
+    settings::{load_config, load_config_with_env, parse_override, save_config, Settings},
+    Error,
+};
+
+const NO_FILE: &str = "./does-not-exist.toml";
+
+fn overrides(args: &[&str]) -> Vec<(String, String)> {
+    args.iter().map(|arg| parse_override(arg).unwrap()).collect()
+}
+
+fn invalid_key(result: Result<config::Config, Error>) -> String {
+    match result {
+        Err(Error::InvalidConfig { key, .. }) => key,
+        Err(e) => panic!("expected an invalid config, got {}", e),
+        Ok(_) => panic!("expected an invalid config"),
+    }
+}
+
+#[cfg(test)]
+mod layers {
+    use super::*;
+
+    #[test]
+    fn it_should_default_every_key() {
+        let conf = load_config(Path::new(NO_FILE), false, &[]).unwrap();
+        let settings = Settings::from_config(&conf).unwrap();
+        assert_eq!(settings.alphazero.learn_iterations, 100);
+        assert_eq!(settings.rollout.n_games, 1296);
+        assert!(matches!(load_config(Path::new(NO_FILE), true, &[]), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn it_should_apply_overrides() {
+        let args = overrides(&[
+            "learn_iterations=10",
+            "move_selection = lcb",
+            "temperature_schedule=[{ from_move = 0, temperature = 0.5 }]",
+        ]);
+        let conf = load_config(Path::new(NO_FILE), false, &args).unwrap();
+        assert_eq!(conf.get_int("learn_iterations").unwrap(), 10);
+        assert_eq!(conf.get_string("move_selection").unwrap(), "lcb");
+        assert_eq!(conf.get_array("temperature_schedule").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn it_should_read_the_environment() {
+        // An explicit environment, the process environment is shared with the tests running in parallel
+        let environment = HashMap::from([(String::from("DIE_E_ROLLOUT_SEED"), String::from("7"))]);
+        let conf = load_config_with_env(Path::new(NO_FILE), false, &[], Some(environment)).unwrap();
+        assert_eq!(conf.get_int("rollout_seed").unwrap(), 7);
+    }
+
+    #[test]
+    fn it_should_save_the_effective_config() {
+        let conf = load_config(Path::new(NO_FILE), false, &overrides(&["iterations=42"])).unwrap();
+        let path = std::env::temp_dir().join(format!("die-e-config-{}.json", std::process::id()));
+        save_config(&conf, &path).unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved["iterations"], 42);
+        assert_eq!(saved["lr"], 0.001);
+    }
+}
+
+#[cfg(test)]
+mod validation {
+    use super::*;
+
+    #[test]
+    fn it_should_name_the_key_out_of_range() {
+        let load = |arg: &str| load_config(Path::new(NO_FILE), false, &overrides(&[arg]));
+        assert_eq!(invalid_key(load("temperature=-1")), "temperature");
+        assert_eq!(invalid_key(load("training_batch_size=0")), "training_batch_size");
+        assert_eq!(invalid_key(load("dirichlet_epsilon=1.5")), "dirichlet_epsilon");
+        assert_eq!(invalid_key(load("annotate_error=0.5")), "annotate_error");
+        assert!(matches!(load("illegal_move_policy=ignore"), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn it_should_reject_unknown_keys() {
+        let result = load_config(Path::new(NO_FILE), false, &overrides(&["learn_iteration=10"]));
+        assert_eq!(invalid_key(result), "learn_iteration");
+    }
+
+    #[test]
+    fn it_should_reject_malformed_overrides() {
+        assert!(matches!(parse_override("learn_iterations"), Err(Error::InvalidInput(_))));
+        assert!(matches!(parse_override("=10"), Err(Error::InvalidInput(_))));
+        assert_eq!(parse_override("lr = 0.01").unwrap(), (String::from("lr"), String::from("0.01")));
+    }
+}
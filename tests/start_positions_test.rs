@@ -0,0 +1,155 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{load_test_config, test_config};
+use die_e::{
+    alphazero::start_positions::{load_opening_book, SourceKind, StartConfig, StartPositions, StartSchedule, StartSource, StartStep},
+    backgammon::{backgammon_logic::Backgammon, bearoff::home_counts, generator::Phase},
+    base::{LearnableGame, NoPhase},
+    tictactoe::TicTacToe,
+    versus::{save_game, Agent, Game, Turn},
+    Error,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+const CURRICULUM: &str = r#"start_schedule = [
+    { from_iteration = 0, sources = [{ source = "random", phase = "bearoff", weight = 1.0 }] },
+    { from_iteration = 10, sources = [{ source = "random", phase = "race", weight = 1.0 }, { source = "initial", weight = 1.0 }] },
+    { from_iteration = 20, sources = [{ source = "initial", weight = 1.0 }] },
+]"#;
+
+fn source(source: SourceKind, phase: Option<&str>, weight: f64) -> StartSource {
+    StartSource { source, phase: phase.map(String::from), weight }
+}
+
+fn schedule(sources: Vec<StartSource>) -> StartSchedule {
+    StartSchedule::new(vec![StartStep { from_iteration: 0, sources }])
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("die-e-{}-{}", name, std::process::id()))
+}
+
+#[cfg(test)]
+mod schedule {
+    use super::*;
+
+    #[test]
+    fn it_should_read_a_curriculum_from_the_config() {
+        let conf = test_config(&[CURRICULUM]);
+        let config = StartConfig::from_config(&conf).unwrap();
+        assert_eq!(config.schedule.sources_at(0), &[source(SourceKind::Random, Some("bearoff"), 1.)]);
+        assert_eq!(config.schedule.sources_at(15).len(), 2);
+        assert_eq!(config.schedule.sources_at(100), &[source(SourceKind::Initial, None, 1.)]);
+        assert_eq!(config.book_path, None);
+    }
+
+    #[test]
+    fn it_should_start_from_the_initial_position_by_default() {
+        let conf = test_config(&[]);
+        assert_eq!(StartConfig::from_config(&conf).unwrap().schedule, StartSchedule::initial());
+    }
+
+    #[test]
+    fn it_should_mix_in_random_positions_by_fraction() {
+        let conf = test_config(&["random_start_fraction = 0.25"]);
+        let expected = [source(SourceKind::Initial, None, 0.75), source(SourceKind::Random, None, 0.25)];
+        assert_eq!(StartConfig::from_config(&conf).unwrap().schedule.sources_at(0), &expected);
+
+        // The schedule takes over from the fraction, both can not be set
+        assert!(load_test_config(&["random_start_fraction = 0.25", CURRICULUM]).is_err());
+    }
+
+    #[test]
+    fn it_should_parse_the_phases_of_the_game() {
+        let raw = schedule(vec![source(SourceKind::Random, Some("Race"), 1.), source(SourceKind::Initial, None, 1.)]);
+        let parsed = raw.parse_phases::<Phase>().unwrap();
+        assert_eq!(parsed.sources_at(0)[0].phase, Some(Phase::Race));
+        assert_eq!(parsed.sources_at(0)[1].phase, None);
+        assert!(raw.parse_phases::<NoPhase>().is_err());
+    }
+
+    #[test]
+    fn it_should_reject_steps_without_weight() {
+        let args = [r#"start_schedule = [{ from_iteration = 0, sources = [{ source = "initial", weight = 0.0 }] }]"#];
+        assert!(load_test_config(&args).is_err());
+    }
+
+    #[test]
+    fn it_should_reject_sources_it_can_not_sample() {
+        let invalid = |result: Result<StartPositions<Backgammon>, Error>| matches!(result, Err(Error::InvalidConfig { .. }));
+        assert!(invalid(StartPositions::new(schedule(vec![source(SourceKind::Book, None, 1.)]), vec![], vec![])));
+        assert!(invalid(StartPositions::new(schedule(vec![source(SourceKind::Games, None, 1.)]), vec![], vec![])));
+        assert!(invalid(StartPositions::new(schedule(vec![source(SourceKind::Random, Some("middle"), 1.)]), vec![], vec![])));
+        let tictactoe = StartPositions::<TicTacToe>::new(schedule(vec![source(SourceKind::Random, None, 1.)]), vec![], vec![]);
+        assert!(matches!(tictactoe, Err(Error::InvalidConfig { .. })));
+        // Sources without weight are never sampled
+        assert!(StartPositions::<Backgammon>::new(schedule(vec![source(SourceKind::Initial, None, 1.), source(SourceKind::Book, None, 0.)]), vec![], vec![]).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod sampling {
+    use super::*;
+
+    #[test]
+    fn it_should_sample_rolled_positions_of_the_phase() {
+        let starts = StartPositions::<Backgammon>::new(schedule(vec![source(SourceKind::Random, Some("bearoff"), 1.)]), vec![], vec![]).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let (state, kind) = starts.sample(0, &mut rng);
+            assert_eq!(kind, SourceKind::Random);
+            assert!(home_counts(state.board, -1).is_some() && home_counts(state.board, 1).is_some());
+            assert_ne!(state.roll, (0, 0));
+        }
+    }
+
+    #[test]
+    fn it_should_sample_the_book_by_weight() {
+        let path = temp_path("book.json");
+        let initial = Backgammon::new().position_id();
+        std::fs::write(&path, format!(r#"[{{ "position": "{}", "weight": 1.0 }}, {{ "position": "1:{}", "weight": 0.0 }}]"#, initial, &initial[3..])).unwrap();
+        let book = load_opening_book::<Backgammon>(&path);
+        std::fs::remove_file(&path).unwrap();
+        let book = book.unwrap();
+        assert_eq!(book.len(), 2);
+
+        let starts = StartPositions::new(schedule(vec![source(SourceKind::Book, None, 1.)]), book, vec![]).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let (state, kind) = starts.sample(3, &mut rng);
+            assert_eq!(kind, SourceKind::Book);
+            assert_eq!(state.position_id(), initial);
+            assert_ne!(state.roll, (0, 0));
+        }
+        assert!(matches!(load_opening_book::<Backgammon>(&path), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn it_should_sample_positions_of_saved_games() {
+        let dir = temp_path("start-games");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut game = Game::new(Agent::Random, Agent::Random, TicTacToe::new());
+        for action in [0, 4] {
+            game.turns.push(Turn { roll: None, action, player: Agent::Random, skipped: false, search: None });
+        }
+        save_game(&game, dir.to_str().unwrap()).unwrap();
+
+        let config = StartConfig {
+            schedule: schedule(vec![source(SourceKind::Games, None, 1.)]),
+            book_path: None,
+            games_path: Some(dir.clone()),
+        };
+        let starts = StartPositions::<TicTacToe>::load(&config);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let starts = starts.unwrap();
+        let positions = [TicTacToe::new().position_id(), game.states().unwrap()[1].position_id()];
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let (state, kind) = starts.sample(0, &mut rng);
+            assert_eq!(kind, SourceKind::Games);
+            assert!(positions.contains(&state.position_id()));
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use die_e::{
+    mcts::node_store::NodeStore,
+    tictactoe::TicTacToe,
+    base::LearnableGame,
+    versus::{Agent, SearchStats, Turn},
+};
+
+#[cfg(test)]
+mod search_stats {
+    use super::*;
+
+    #[test]
+    fn it_should_keep_the_most_visited_moves() {
+        let mut store: NodeStore<TicTacToe> = NodeStore::new();
+        let root = store.add_node(TicTacToe::new(), None, None, 0.0);
+        store.alpha_expand(root, &[1. / 9.; 9]);
+        // Leaf values are from the view of the opponent of the root, who moves next
+        for (child, value) in store.children(root).take(3).zip([-0.5, 0.5, -1.]) {
+            store.backpropagate(child, value);
+        }
+        store.backpropagate(store.children(root).nth(1).unwrap(), -0.5);
+
+        let stats = SearchStats::from_store(&store, root, 2).unwrap();
+        assert_eq!(stats.root_visits, 4.);
+        assert_eq!(stats.root_value, 0.375);
+        assert_eq!(stats.moves.len(), 2);
+        assert_eq!(stats.moves[0].action, 1);
+        assert_eq!((stats.moves[0].visits, stats.moves[0].q), (2., 0.));
+        assert!(SearchStats::from_store(&store, root, 0).is_none());
+        let leaf = store.children(root).last().unwrap();
+        assert!(SearchStats::from_store(&store, leaf, 2).is_none());
+    }
+
+    #[test]
+    fn q_should_be_from_the_view_of_the_player_to_move() {
+        // x to move wins at 2
+        let state = TicTacToe::from_position_id("xx-oo----:-1").unwrap();
+        let mut store: NodeStore<TicTacToe> = NodeStore::new();
+        let root = store.add_node(state, None, None, 0.0);
+        store.alpha_expand(root, &[1. / 9.; 9]);
+        for child in store.children(root) {
+            match store.terminal_value(child) {
+                Some(value) => (0..3).for_each(|_| store.backpropagate(child, value)),
+                // Network value of 0.9 for o, the player to move
+                None => store.backpropagate(child, 0.9),
+            }
+        }
+
+        let stats = SearchStats::from_store(&store, root, 2).unwrap();
+        assert_eq!((stats.moves[0].action, stats.moves[0].q), (2, 1.));
+        assert!((stats.moves[1].q + 0.9).abs() < 1e-6);
+        assert_eq!(stats.root_visits, 7.);
+        assert!((stats.root_value - (3. - 4. * 0.9) / 7.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn turns_without_stats_still_load() {
+        let turn: Turn<u8> = serde_json::from_str(r#"{"roll": null, "action": 4, "player": "Random"}"#).unwrap();
+        assert_eq!(turn.search, None);
+        assert!(!turn.skipped);
+        assert_eq!(turn.player, Agent::Random);
+        assert!(!serde_json::to_string(&turn).unwrap().contains("search"));
+        assert_eq!(TicTacToe::new().get_roll(), None);
+    }
+}
+
+#[cfg(test)]
+mod game_records {
+    use std::path::PathBuf;
+
+    use die_e::{
+        alphazero::{alphazero::AlphaZero, nnet::ResNet},
+        move_guard::{IllegalMovePolicy, MoveGuard, MoveGuardConfig},
+        backgammon::{backgammon_logic::Backgammon, dice::SeededDice},
+        versus::{load_all_games, load_game, play_turn, save_game, Game},
+        Error,
+    };
+
+    use super::*;
+
+    #[test]
+    fn missing_files_should_be_errors() {
+        let missing = PathBuf::from("./does-not-exist/game.json");
+        assert!(matches!(load_game::<TicTacToe>(missing.clone()), Err(Error::NotFound(path)) if path == missing));
+        assert!(matches!(load_all_games::<TicTacToe>(PathBuf::from("./does-not-exist")), Err(Error::Io(_))));
+        assert!(matches!(AlphaZero::load_training_data(&missing), Err(Error::NotFound(_))));
+        assert!(matches!(ResNet::from_path::<TicTacToe>(&missing), Err(Error::Model { .. })));
+    }
+
+    #[test]
+    fn corrupt_records_should_be_errors() {
+        let dir = std::env::temp_dir().join(format!("die-e-records-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let game = Game::new(Agent::Random, Agent::Random, TicTacToe::new());
+        save_game(&game, dir.to_str().unwrap()).unwrap();
+        let path = dir.join(format!("{}.json", game.id));
+        assert_eq!(load_game::<TicTacToe>(path.clone()).unwrap().id, game.id);
+
+        std::fs::write(&path, "{ not a game").unwrap();
+        let loaded = load_game::<TicTacToe>(path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(loaded, Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn games_with_skipped_illegal_moves_should_replay() {
+        let dir = std::env::temp_dir().join(format!("die-e-skipped-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = MoveGuardConfig { policy: IllegalMovePolicy::Skip, log_path: Some(PathBuf::from("illegal.jsonl")) };
+        let mut guard = MoveGuard::new(&config.in_dir(&dir), "test");
+        let mut state = TicTacToe::new();
+        let mut game = Game::new(Agent::Random, Agent::Random, state);
+        // o plays the square x took, the turn is skipped and x plays again
+        for action in [4, 4, 0] {
+            play_turn(&mut guard, &mut state, &mut game, &Agent::Random, &action, None);
+        }
+        assert_eq!(guard.n_illegal(), 1);
+        assert!(dir.join("illegal.jsonl").is_file());
+        assert_eq!(game.turns.iter().map(|turn| (turn.action, turn.skipped)).collect::<Vec<_>>(), [(4, false), (4, true), (0, false)]);
+
+        save_game(&game, dir.to_str().unwrap()).unwrap();
+        let loaded = load_game::<TicTacToe>(dir.join(format!("{}.json", game.id)));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let states = loaded.unwrap().states().unwrap();
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[1].get_player(), -states[2].get_player());
+        let mut last = states[2];
+        last.apply_move(&0);
+        assert_eq!(last.position_id(), state.position_id());
+    }
+
+    #[test]
+    fn replays_should_roll_the_recorded_dice() {
+        let mut dice = SeededDice::new(7);
+        let mut state = Backgammon::new();
+        state.roll_die_with(&mut dice);
+        let mut game = Game::new(Agent::Random, Agent::Random, state);
+        for _ in 0..20 {
+            let action = state.get_valid_moves().first().cloned().unwrap_or(Backgammon::EMPTY_MOVE);
+            game.turns.push(Turn { roll: state.get_roll(), action: action.clone(), player: Agent::Random, skipped: false, search: None });
+            if action == Backgammon::EMPTY_MOVE {
+                state.skip_turn_with(&mut dice);
+            } else {
+                state.apply_move_with(&action, &mut dice);
+            }
+        }
+
+        let replayed = game.replay().unwrap();
+        assert_eq!(replayed.len(), game.turns.len() + 1);
+        for (turn, before) in game.turns.iter().zip(replayed.iter()) {
+            assert_eq!(before.get_roll(), turn.roll);
+        }
+        // The final state has no recorded roll, it keeps the roll of the last turn instead of a random one
+        let last = replayed.last().unwrap();
+        assert_eq!((last.board, last.get_player()), (state.board, state.get_player()));
+        assert_eq!(last.get_roll(), game.turns.last().unwrap().roll);
+        assert_eq!(game.replay().unwrap().last().unwrap().get_roll(), last.get_roll());
+        assert_eq!(game.states().unwrap().len(), game.turns.len());
+    }
+}